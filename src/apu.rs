@@ -0,0 +1,873 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::audio::{AudioBuffer, RingBuffer, WavRecorder};
+
+// Sound register addresses
+pub const NR10: u16 = 0xFF10;  // Channel 1 sweep
+pub const NR11: u16 = 0xFF11;  // Channel 1 duty/length
+pub const NR12: u16 = 0xFF12;  // Channel 1 envelope
+pub const NR13: u16 = 0xFF13;  // Channel 1 frequency low
+pub const NR14: u16 = 0xFF14;  // Channel 1 frequency high/control
+pub const NR21: u16 = 0xFF16;  // Channel 2 duty/length
+pub const NR22: u16 = 0xFF17;  // Channel 2 envelope
+pub const NR23: u16 = 0xFF18;  // Channel 2 frequency low
+pub const NR24: u16 = 0xFF19;  // Channel 2 frequency high/control
+pub const NR30: u16 = 0xFF1A;  // Channel 3 DAC enable
+pub const NR31: u16 = 0xFF1B;  // Channel 3 length
+pub const NR32: u16 = 0xFF1C;  // Channel 3 output level
+pub const NR33: u16 = 0xFF1D;  // Channel 3 frequency low
+pub const NR34: u16 = 0xFF1E;  // Channel 3 frequency high/control
+pub const NR41: u16 = 0xFF20;  // Channel 4 length
+pub const NR42: u16 = 0xFF21;  // Channel 4 envelope
+pub const NR43: u16 = 0xFF22;  // Channel 4 clock shift/width/divisor
+pub const NR44: u16 = 0xFF23;  // Channel 4 control
+pub const NR50: u16 = 0xFF24;  // Master volume
+pub const NR51: u16 = 0xFF25;  // Channel panning
+pub const NR52: u16 = 0xFF26;  // Sound on/off, channel status
+pub const WAVE_RAM_START: u16 = 0xFF30;
+pub const WAVE_RAM_END: u16 = 0xFF3F;
+
+// Bits that read back as 1 for FF10-FF2F: write-only fields (lengths,
+// frequencies, triggers) and unused bits. FF15, FF1F and FF27-FF2F are
+// unmapped and read 0xFF.
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF,  // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF,  // FF15, NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF,  // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF,  // FF1F, NR41-NR44
+    0x00, 0x00, 0x70,              // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,  // FF27-FF2F
+];
+
+// Duty cycle waveforms, one bit per step (MSB first)
+const DUTY_PATTERNS: [u8; 4] = [
+    0b0000_0001,  // 12.5%
+    0b1000_0001,  // 25%
+    0b1000_0111,  // 50%
+    0b0111_1110,  // 75%
+];
+
+/// Convert a channel's digital output (0-15) to an analog level in -1.0..=1.0
+fn dac(enabled: bool, value: u8) -> f32 {
+    if enabled {
+        value as f32 / 7.5 - 1.0
+    } else {
+        0.0
+    }
+}
+
+// The frame sequencer steps at 512 Hz
+const FRAME_SEQUENCER_PERIOD: u32 = 8192;
+
+/// 512 Hz frame sequencer. Each of its eight steps clocks some of the
+/// channels' units: length counters on even steps (256 Hz), the channel 1
+/// sweep on steps 2 and 6 (128 Hz) and the volume envelopes on step 7 (64 Hz).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameSequencer {
+    timer: u32,  // Cycles since the last step
+    step: u8,    // Next step to run, 0-7
+}
+
+impl FrameSequencer {
+    pub fn new() -> FrameSequencer {
+        FrameSequencer::default()
+    }
+
+    /// Advance by `cycles` clock cycles, calling `clock` with each step reached
+    pub fn advance(&mut self, cycles: u32, mut clock: impl FnMut(u8)) {
+        self.timer += cycles;
+        while self.timer >= FRAME_SEQUENCER_PERIOD {
+            self.timer -= FRAME_SEQUENCER_PERIOD;
+            clock(self.step);
+            self.step = (self.step + 1) % 8;
+        }
+    }
+
+    /// Next step to run
+    pub fn step(&self) -> u8 {
+        self.step
+    }
+
+    pub fn clocks_length(step: u8) -> bool {
+        step.is_multiple_of(2)
+    }
+
+    pub fn clocks_sweep(step: u8) -> bool {
+        step == 2 || step == 6
+    }
+
+    pub fn clocks_envelope(step: u8) -> bool {
+        step == 7
+    }
+}
+
+/// Volume envelope shared by the square and noise channels (NRx2)
+#[derive(Clone)]
+pub struct Envelope {
+    pub volume: u8,
+    initial: u8,
+    increase: bool,
+    period: u8,
+    timer: u8,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            volume: 0,
+            initial: 0,
+            increase: false,
+            period: 0,
+            timer: 0,
+        }
+    }
+
+    /// NRx2: bits 4-7 initial volume, bit 3 direction, bits 0-2 period.
+    /// Returns whether the channel DAC is on (upper 5 bits not all zero).
+    pub fn write(&mut self, value: u8) -> bool {
+        self.initial = value >> 4;
+        self.increase = value & 0x08 != 0;
+        self.period = value & 0x07;
+        value & 0xF8 != 0
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+
+    /// Envelope clock (64 Hz)
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+/// Frequency sweep unit (NR10). Only channel 1 has one wired up.
+#[derive(Clone, Default)]
+pub struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    shadow: u16,  // Frequency the calculations work from, latched on trigger
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep::default()
+    }
+
+    /// NR10: bits 4-6 period, bit 3 direction (1 = down), bits 0-2 shift
+    pub fn write(&mut self, value: u8) {
+        self.period = (value >> 4) & 0x07;
+        self.negate = value & 0x08 != 0;
+        self.shift = value & 0x07;
+    }
+
+    /// A period of 0 reloads the timer with 8
+    fn reload(&mut self) {
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    /// Next frequency, or None when it overflows 11 bits
+    fn calculate(&self) -> Option<u16> {
+        let delta = self.shadow >> self.shift;
+        let frequency = if self.negate { self.shadow - delta } else { self.shadow + delta };
+        if frequency > 0x7FF { None } else { Some(frequency) }
+    }
+
+    /// Restart from `frequency`. Returns false if the overflow check done
+    /// straight away fails, which disables the channel.
+    pub fn trigger(&mut self, frequency: u16) -> bool {
+        self.shadow = frequency;
+        self.reload();
+        self.enabled = self.period != 0 || self.shift != 0;
+        self.shift == 0 || self.calculate().is_some()
+    }
+}
+
+/// Square/pulse channel (channels 1 and 2)
+#[derive(Clone)]
+pub struct SquareChannel {
+    pub enabled: bool,
+    dac_enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    pub frequency: u16,     // 11-bit frequency value
+    timer: i32,             // Cycles until the next duty step
+    length_counter: u16,
+    length_enabled: bool,
+    pub envelope: Envelope,
+    pub sweep: Sweep,
+}
+
+impl Default for SquareChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SquareChannel {
+    pub fn new() -> SquareChannel {
+        SquareChannel {
+            enabled: false,
+            dac_enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length_counter: 0,
+            length_enabled: false,
+            envelope: Envelope::new(),
+            sweep: Sweep::new(),
+        }
+    }
+
+    /// NR10 (channel 1 only): frequency sweep
+    pub fn write_sweep(&mut self, value: u8) {
+        self.sweep.write(value);
+    }
+
+    /// NRx1: bits 6-7 duty, bits 0-5 length load (counter = 64 - n)
+    pub fn write_duty_length(&mut self, value: u8) {
+        self.duty = value >> 6;
+        self.length_counter = 64 - (value & 0x3F) as u16;
+    }
+
+    /// NRx2: volume envelope; the DAC is off when the upper 5 bits are all zero
+    pub fn write_envelope(&mut self, value: u8) {
+        self.dac_enabled = self.envelope.write(value);
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    /// NRx3: low 8 bits of the frequency
+    pub fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x700) | value as u16;
+    }
+
+    /// NRx4: bit 7 trigger, bit 6 length enable, bits 0-2 frequency high bits
+    pub fn write_control(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0xFF) | (((value & 0x07) as u16) << 8);
+        self.length_enabled = value & 0x40 != 0;
+        if value & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 4
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        if self.length_counter == 0 {
+            self.length_counter = 64;
+        }
+        self.timer = self.period();
+        self.envelope.trigger();
+        if !self.sweep.trigger(self.frequency) {
+            self.enabled = false;
+        }
+    }
+
+    /// Advance the frequency timer by `cycles` clock cycles
+    pub fn step(&mut self, cycles: u32) {
+        self.timer -= cycles as i32;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    /// Length counter clock (256 Hz): disables the channel when it expires
+    pub fn clock_length(&mut self) {
+        if self.length_enabled && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Volume envelope clock (64 Hz)
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Frequency sweep clock (128 Hz). Each time the sweep timer expires the
+    /// new frequency is written back and checked for overflow once more.
+    pub fn clock_sweep(&mut self) {
+        let sweep = &mut self.sweep;
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload();
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+        match sweep.calculate() {
+            Some(frequency) if sweep.shift > 0 => {
+                sweep.shadow = frequency;
+                self.frequency = frequency;
+                if sweep.calculate().is_none() {
+                    self.enabled = false;
+                }
+            },
+            Some(_) => (),
+            None => self.enabled = false,
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.envelope.volume
+    }
+
+    /// Digital output (0-15) for the current duty step
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let high = (DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_step)) & 0x01;
+        high * self.envelope.volume
+    }
+
+    pub fn analog_output(&self) -> f32 {
+        dac(self.dac_enabled, self.output())
+    }
+}
+
+/// Programmable wave channel (channel 3), playing 32 4-bit samples from wave RAM
+#[derive(Clone)]
+pub struct WaveChannel {
+    pub enabled: bool,
+    dac_enabled: bool,
+    pub frequency: u16,        // 11-bit frequency value
+    timer: i32,                // Cycles until the next sample
+    position: u8,              // Current sample (0-31)
+    length_counter: u16,
+    length_enabled: bool,
+    volume_code: u8,           // 0: mute, 1: 100%, 2: 50%, 3: 25%
+    pub wave_ram: [u8; 16],    // Two samples per byte, high nibble first
+}
+
+impl Default for WaveChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaveChannel {
+    pub fn new() -> WaveChannel {
+        WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            length_counter: 0,
+            length_enabled: false,
+            volume_code: 0,
+            wave_ram: [0; 16],
+        }
+    }
+
+    /// NR30: bit 7 turns the DAC on
+    pub fn write_dac(&mut self, value: u8) {
+        self.dac_enabled = value & 0x80 != 0;
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    /// NR31: length load (counter = 256 - n)
+    pub fn write_length(&mut self, value: u8) {
+        self.length_counter = 256 - value as u16;
+    }
+
+    /// NR32: bits 5-6 output level
+    pub fn write_volume(&mut self, value: u8) {
+        self.volume_code = (value >> 5) & 0x03;
+    }
+
+    /// NR33: low 8 bits of the frequency
+    pub fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x700) | value as u16;
+    }
+
+    /// NR34: bit 7 trigger, bit 6 length enable, bits 0-2 frequency high bits
+    pub fn write_control(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0xFF) | (((value & 0x07) as u16) << 8);
+        self.length_enabled = value & 0x40 != 0;
+        if value & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 2
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        if self.length_counter == 0 {
+            self.length_counter = 256;
+        }
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    /// Advance the frequency timer by `cycles` clock cycles
+    pub fn step(&mut self, cycles: u32) {
+        self.timer -= cycles as i32;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    /// Length counter clock (256 Hz): disables the channel when it expires
+    pub fn clock_length(&mut self) {
+        if self.length_enabled && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Current 4-bit wave sample before the volume shift
+    fn current_sample(&self) -> u8 {
+        let byte = self.wave_ram[(self.position / 2) as usize];
+        if self.position & 0x01 == 0 {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        }
+    }
+
+    /// Digital output (0-15): the current sample scaled by the output level
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        match self.volume_code {
+            0 => 0,
+            shift => self.current_sample() >> (shift - 1),
+        }
+    }
+
+    pub fn analog_output(&self) -> f32 {
+        dac(self.dac_enabled, self.output())
+    }
+}
+
+/// Noise channel (channel 4), driven by a 15-bit linear-feedback shift register
+#[derive(Clone)]
+pub struct NoiseChannel {
+    pub enabled: bool,
+    dac_enabled: bool,
+    pub lfsr: u16,
+    clock_shift: u8,
+    width_mode: bool,          // 7-bit LFSR when set
+    divisor_code: u8,
+    timer: i32,                // Cycles until the next LFSR clock
+    length_counter: u16,
+    length_enabled: bool,
+    pub envelope: Envelope,
+}
+
+impl Default for NoiseChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoiseChannel {
+    pub fn new() -> NoiseChannel {
+        NoiseChannel {
+            enabled: false,
+            dac_enabled: false,
+            lfsr: 0x7FFF,
+            clock_shift: 0,
+            width_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            length_counter: 0,
+            length_enabled: false,
+            envelope: Envelope::new(),
+        }
+    }
+
+    /// NR41: bits 0-5 length load (counter = 64 - n)
+    pub fn write_length(&mut self, value: u8) {
+        self.length_counter = 64 - (value & 0x3F) as u16;
+    }
+
+    /// NR42: volume envelope; the DAC is off when the upper 5 bits are all zero
+    pub fn write_envelope(&mut self, value: u8) {
+        self.dac_enabled = self.envelope.write(value);
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    /// NR43: bits 4-7 clock shift, bit 3 width mode, bits 0-2 divisor code
+    pub fn write_polynomial(&mut self, value: u8) {
+        self.clock_shift = value >> 4;
+        self.width_mode = value & 0x08 != 0;
+        self.divisor_code = value & 0x07;
+    }
+
+    /// NR44: bit 7 trigger, bit 6 length enable
+    pub fn write_control(&mut self, value: u8) {
+        self.length_enabled = value & 0x40 != 0;
+        if value & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    fn period(&self) -> i32 {
+        let divisor = match self.divisor_code {
+            0 => 8,
+            code => code as i32 * 16,
+        };
+        divisor << self.clock_shift
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        if self.length_counter == 0 {
+            self.length_counter = 64;
+        }
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+
+    /// Shift the LFSR once: bits 0 and 1 are XORed into bit 14 (and bit 6 in width mode)
+    pub fn clock_lfsr(&mut self) {
+        let xor = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+        self.lfsr = (self.lfsr >> 1) | (xor << 14);
+        if self.width_mode {
+            self.lfsr = (self.lfsr & !0x40) | (xor << 6);
+        }
+    }
+
+    /// Advance the frequency timer by `cycles` clock cycles
+    pub fn step(&mut self, cycles: u32) {
+        self.timer -= cycles as i32;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.clock_lfsr();
+        }
+    }
+
+    /// Length counter clock (256 Hz): disables the channel when it expires
+    pub fn clock_length(&mut self) {
+        if self.length_enabled && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Volume envelope clock (64 Hz)
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Digital output (0-15): full volume while bit 0 of the LFSR is clear
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 0x01 != 0 {
+            return 0;
+        }
+        self.envelope.volume
+    }
+
+    pub fn analog_output(&self) -> f32 {
+        dac(self.dac_enabled, self.output())
+    }
+}
+
+/// Audio Processing Unit. Sound registers at 0xFF10-0xFF3F are routed here by the MMU.
+pub struct Apu {
+    pub channel1: SquareChannel,
+    pub channel2: SquareChannel,
+    pub channel3: WaveChannel,
+    pub channel4: NoiseChannel,
+    pub sequencer: FrameSequencer,
+    powered: bool,  // NR52 bit 7
+    registers: [u8; 0x30],  // Raw register values, FF10-FF3F
+    audio: Option<AudioBuffer>,
+    recorder: Option<WavRecorder>,
+}
+
+// Snapshots carry the emulated state only: the host audio output stays
+// with the live APU (see Machine::load_state)
+impl Clone for Apu {
+    fn clone(&self) -> Self {
+        Apu {
+            channel1: self.channel1.clone(),
+            channel2: self.channel2.clone(),
+            channel3: self.channel3.clone(),
+            channel4: self.channel4.clone(),
+            sequencer: self.sequencer,
+            powered: self.powered,
+            registers: self.registers,
+            audio: None,
+            recorder: None,
+        }
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu {
+            channel1: SquareChannel::new(),
+            channel2: SquareChannel::new(),
+            channel3: WaveChannel::new(),
+            channel4: NoiseChannel::new(),
+            sequencer: FrameSequencer::new(),
+            powered: true,
+            registers: Self::initial_registers(),
+            audio: None,
+            recorder: None,
+        }
+    }
+
+    /// Post-boot master volume and panning: full volume, values left by the boot ROM
+    fn initial_registers() -> [u8; 0x30] {
+        let mut registers = [0; 0x30];
+        registers[(NR50 - NR10) as usize] = 0x77;
+        registers[(NR51 - NR10) as usize] = 0xF3;
+        registers
+    }
+
+    /// Detach the host audio output, e.g. to move it onto a restored snapshot
+    pub fn take_audio_output(&mut self) -> Option<AudioBuffer> {
+        self.audio.take()
+    }
+
+    pub fn set_audio_output(&mut self, audio: Option<AudioBuffer>) {
+        self.audio = audio;
+    }
+
+    /// Start resampling output to `sample_rate` Hz. Returns the ring the frontend drains.
+    pub fn enable_audio_output(&mut self, sample_rate: u32, capacity: usize) -> Arc<RingBuffer> {
+        let audio = AudioBuffer::new(sample_rate, capacity);
+        let ring = audio.ring();
+        self.audio = Some(audio);
+        ring
+    }
+
+    /// Start capturing the mixed output at `sample_rate` Hz, replacing any recording in progress
+    pub fn start_recording(&mut self, sample_rate: u32) {
+        self.recorder = Some(WavRecorder::new(sample_rate));
+    }
+
+    /// Stop capturing and return the recording as WAV file bytes
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        self.recorder.take().map(WavRecorder::finish)
+    }
+
+    pub fn recorder(&self) -> Option<&WavRecorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Detach the recording in progress, e.g. to move it onto a restored snapshot
+    pub fn take_recorder(&mut self) -> Option<WavRecorder> {
+        self.recorder.take()
+    }
+
+    pub fn set_recorder(&mut self, recorder: Option<WavRecorder>) {
+        self.recorder = recorder;
+    }
+
+    /// Ring of the attached audio output, if any
+    pub fn audio_ring(&self) -> Option<Arc<RingBuffer>> {
+        self.audio.as_ref().map(AudioBuffer::ring)
+    }
+
+    pub fn powered(&self) -> bool {
+        self.powered
+    }
+
+    /// NR52 read: bit 7 power, bits 0-3 whether channels 1-4 are playing
+    fn status(&self) -> u8 {
+        let active = [
+            self.channel1.enabled,
+            self.channel2.enabled,
+            self.channel3.enabled,
+            self.channel4.enabled,
+        ];
+        let channels = active.iter().enumerate().fold(0, |bits, (channel, &on)| bits | (on as u8) << channel);
+        (self.powered as u8) << 7 | channels
+    }
+
+    /// NR52 write: powering off resets every channel and clears FF10-FF25.
+    /// Wave RAM survives; the frame sequencer restarts at step 0 on power on.
+    fn write_power(&mut self, value: u8) {
+        let powered = value & 0x80 != 0;
+        if self.powered && !powered {
+            let wave_ram = self.channel3.wave_ram;
+            self.channel1 = SquareChannel::new();
+            self.channel2 = SquareChannel::new();
+            self.channel3 = WaveChannel::new();
+            self.channel3.wave_ram = wave_ram;
+            self.channel4 = NoiseChannel::new();
+            self.registers[..(NR52 - NR10) as usize].fill(0);
+        } else if !self.powered && powered {
+            self.sequencer = FrameSequencer::new();
+        }
+        self.powered = powered;
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            NR52 => self.status() | READ_MASKS[(NR52 - NR10) as usize],
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                self.channel3.wave_ram[(address - WAVE_RAM_START) as usize]
+            },
+            _ => {
+                let index = (address - NR10) as usize;
+                self.registers[index] | READ_MASKS[index]
+            },
+        }
+    }
+
+    /// Powered off, only NR52 and wave RAM accept writes
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            NR52 => return self.write_power(value),
+            WAVE_RAM_START..=WAVE_RAM_END => (),
+            _ if !self.powered => return,
+            _ => (),
+        }
+        self.registers[(address - NR10) as usize] = value;
+        match address {
+            NR10 => self.channel1.write_sweep(value),
+            NR11 => self.channel1.write_duty_length(value),
+            NR12 => self.channel1.write_envelope(value),
+            NR13 => self.channel1.write_frequency_low(value),
+            NR14 => self.channel1.write_control(value),
+            NR21 => self.channel2.write_duty_length(value),
+            NR22 => self.channel2.write_envelope(value),
+            NR23 => self.channel2.write_frequency_low(value),
+            NR24 => self.channel2.write_control(value),
+            NR30 => self.channel3.write_dac(value),
+            NR31 => self.channel3.write_length(value),
+            NR32 => self.channel3.write_volume(value),
+            NR33 => self.channel3.write_frequency_low(value),
+            NR34 => self.channel3.write_control(value),
+            NR41 => self.channel4.write_length(value),
+            NR42 => self.channel4.write_envelope(value),
+            NR43 => self.channel4.write_polynomial(value),
+            NR44 => self.channel4.write_control(value),
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                self.channel3.wave_ram[(address - WAVE_RAM_START) as usize] = value;
+            },
+            _ => (),
+        }
+    }
+
+    /// Clock the units driven by frame sequencer step `step`
+    fn clock_frame_step(&mut self, step: u8) {
+        if FrameSequencer::clocks_length(step) {
+            self.channel1.clock_length();
+            self.channel2.clock_length();
+            self.channel3.clock_length();
+            self.channel4.clock_length();
+        }
+        if FrameSequencer::clocks_sweep(step) {
+            self.channel1.clock_sweep();
+        }
+        if FrameSequencer::clocks_envelope(step) {
+            self.channel1.clock_envelope();
+            self.channel2.clock_envelope();
+            self.channel4.clock_envelope();
+        }
+    }
+
+    /// Advance the frame sequencer and the channel frequency timers by `cycles` clock cycles
+    pub fn step(&mut self, cycles: u32) {
+        if self.powered {
+            let mut sequencer = self.sequencer;
+            sequencer.advance(cycles, |step| self.clock_frame_step(step));
+            self.sequencer = sequencer;
+        }
+
+        self.channel1.step(cycles);
+        self.channel2.step(cycles);
+        self.channel3.step(cycles);
+        self.channel4.step(cycles);
+        if self.audio.is_some() || self.recorder.is_some() {
+            let sample = self.sample();
+            if let Some(audio) = &mut self.audio {
+                audio.push(sample, cycles);
+            }
+            if let Some(recorder) = &mut self.recorder {
+                recorder.push(sample, cycles);
+            }
+        }
+    }
+
+    /// Current (left, right) output level, each in -1.0..=1.0.
+    /// Channels are routed by NR51 and scaled by the NR50 master volume.
+    pub fn sample(&self) -> (f32, f32) {
+        let panning = self.registers[(NR51 - NR10) as usize];
+        let master = self.registers[(NR50 - NR10) as usize];
+        let outputs = [
+            self.channel1.analog_output(),
+            self.channel2.analog_output(),
+            self.channel3.analog_output(),
+            self.channel4.analog_output(),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (channel, output) in outputs.iter().enumerate() {
+            if panning & (0x10 << channel) != 0 {
+                left += output;
+            }
+            if panning & (0x01 << channel) != 0 {
+                right += output;
+            }
+        }
+        let left_volume = (((master >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((master & 0x07) + 1) as f32 / 8.0;
+        (left / 4.0 * left_volume, right / 4.0 * right_volume)
+    }
+}
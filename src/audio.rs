@@ -0,0 +1,233 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Native APU sample rate: one stereo sample every 4 clock cycles
+pub const NATIVE_SAMPLE_RATE: u32 = 1_048_576;
+
+/// Single-producer/single-consumer ring of stereo samples.
+/// The emulator pushes from one thread and the frontend pops from another without locking.
+pub struct RingBuffer {
+    samples: Box<[AtomicU32]>,  // Interleaved left/right f32 bit patterns
+    read: AtomicUsize,          // Next frame to pop
+    write: AtomicUsize,         // Next frame to push
+    last_left: AtomicU32,       // Last popped frame, repeated on underrun
+    last_right: AtomicU32,
+}
+
+impl RingBuffer {
+    /// A ring holding up to `capacity` stereo frames
+    pub fn new(capacity: usize) -> RingBuffer {
+        // One slot stays empty to tell a full ring from an empty one
+        let slots = (capacity + 1) * 2;
+        RingBuffer {
+            samples: (0..slots).map(|_| AtomicU32::new(0)).collect(),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+            last_left: AtomicU32::new(0.0f32.to_bits()),
+            last_right: AtomicU32::new(0.0f32.to_bits()),
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.frames() - 1
+    }
+
+    /// Number of frames waiting to be popped
+    pub fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        let write = self.write.load(Ordering::Acquire);
+        (write + self.frames() - read) % self.frames()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Push a frame. On overrun the frame is dropped and false is returned.
+    pub fn push(&self, left: f32, right: f32) -> bool {
+        let write = self.write.load(Ordering::Relaxed);
+        let next = (write + 1) % self.frames();
+        if next == self.read.load(Ordering::Acquire) {
+            return false;
+        }
+        self.samples[write * 2].store(left.to_bits(), Ordering::Relaxed);
+        self.samples[write * 2 + 1].store(right.to_bits(), Ordering::Relaxed);
+        self.write.store(next, Ordering::Release);
+        true
+    }
+
+    /// Pop a frame, or None if the ring is empty
+    pub fn pop(&self) -> Option<(f32, f32)> {
+        let read = self.read.load(Ordering::Relaxed);
+        if read == self.write.load(Ordering::Acquire) {
+            return None;
+        }
+        let left = self.samples[read * 2].load(Ordering::Relaxed);
+        let right = self.samples[read * 2 + 1].load(Ordering::Relaxed);
+        self.read.store((read + 1) % self.frames(), Ordering::Release);
+        self.last_left.store(left, Ordering::Relaxed);
+        self.last_right.store(right, Ordering::Relaxed);
+        Some((f32::from_bits(left), f32::from_bits(right)))
+    }
+
+    /// Fill `out` with interleaved stereo samples. On underrun the last frame is repeated.
+    /// Returns the number of frames that came from the ring.
+    pub fn drain(&self, out: &mut [f32]) -> usize {
+        let mut fresh = 0;
+        for frame in out.chunks_mut(2) {
+            let (left, right) = match self.pop() {
+                Some(sample) => {
+                    fresh += 1;
+                    sample
+                },
+                None => (
+                    f32::from_bits(self.last_left.load(Ordering::Relaxed)),
+                    f32::from_bits(self.last_right.load(Ordering::Relaxed)),
+                ),
+            };
+            frame[0] = left;
+            if frame.len() > 1 {
+                frame[1] = right;
+            }
+        }
+        fresh
+    }
+}
+
+/// Downsamples the APU's native-rate output to a host sample rate.
+/// Native samples between two output samples are averaged (box filter).
+pub struct AudioBuffer {
+    sample_rate: u32,
+    ring: Arc<RingBuffer>,
+    cycles: u32,     // Leftover clock cycles short of a native sample
+    phase: u32,      // Resampling accumulator, in units of 1/NATIVE_SAMPLE_RATE
+    sum_left: f32,
+    sum_right: f32,
+    count: u32,
+}
+
+impl AudioBuffer {
+    /// Resample to `sample_rate` Hz (e.g. 44100 or 48000), buffering up to `capacity` frames
+    pub fn new(sample_rate: u32, capacity: usize) -> AudioBuffer {
+        AudioBuffer {
+            sample_rate,
+            ring: Arc::new(RingBuffer::new(capacity)),
+            cycles: 0,
+            phase: 0,
+            sum_left: 0.0,
+            sum_right: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Shared handle the frontend drains from its audio callback
+    pub fn ring(&self) -> Arc<RingBuffer> {
+        Arc::clone(&self.ring)
+    }
+
+    /// Feed the APU output level that held for the last `cycles` clock cycles
+    pub fn push(&mut self, sample: (f32, f32), cycles: u32) {
+        self.cycles += cycles;
+        while self.cycles >= 4 {
+            self.cycles -= 4;
+            self.push_native(sample);
+        }
+    }
+
+    /// Feed one sample at the native rate
+    pub fn push_native(&mut self, (left, right): (f32, f32)) {
+        self.sum_left += left;
+        self.sum_right += right;
+        self.count += 1;
+        self.phase += self.sample_rate;
+        if self.phase >= NATIVE_SAMPLE_RATE {
+            self.phase -= NATIVE_SAMPLE_RATE;
+            let count = self.count as f32;
+            self.ring.push(self.sum_left / count, self.sum_right / count);
+            self.sum_left = 0.0;
+            self.sum_right = 0.0;
+            self.count = 0;
+        }
+    }
+}
+
+/// Sample rate WAV recordings are made at
+pub const RECORDING_SAMPLE_RATE: u32 = 44_100;
+
+/// Captures the mixed stereo output as 16-bit PCM for a WAV file.
+/// Resamples with the same box filter as AudioBuffer.
+pub struct WavRecorder {
+    resampler: AudioBuffer,
+    ring: Arc<RingBuffer>,
+    samples: Vec<i16>,  // Interleaved left/right
+}
+
+impl WavRecorder {
+    pub fn new(sample_rate: u32) -> WavRecorder {
+        // Drained after every push, so the ring only has to hold one step's output
+        let resampler = AudioBuffer::new(sample_rate, 4096);
+        let ring = resampler.ring();
+        WavRecorder { resampler, ring, samples: Vec::new() }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.resampler.sample_rate()
+    }
+
+    /// Feed the APU output level that held for the last `cycles` clock cycles
+    pub fn push(&mut self, sample: (f32, f32), cycles: u32) {
+        self.resampler.push(sample, cycles);
+        while let Some((left, right)) = self.ring.pop() {
+            self.samples.push(to_pcm(left));
+            self.samples.push(to_pcm(right));
+        }
+    }
+
+    /// Stereo frames recorded so far
+    pub fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    /// The recording as a complete WAV file
+    pub fn finish(self) -> Vec<u8> {
+        encode_wav(self.sample_rate(), 2, &self.samples)
+    }
+}
+
+fn to_pcm(level: f32) -> i16 {
+    (level.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Wrap interleaved 16-bit PCM samples in a RIFF/WAVE header
+pub fn encode_wav(sample_rate: u32, channels: u16, samples: &[i16]) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let block_align = channels * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());  // fmt chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes());   // PCM
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());  // Byte rate
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());  // Bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
@@ -0,0 +1,128 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::RangeInclusive;
+
+/// Where GameShark codes may write: cartridge RAM, work RAM and HRAM
+pub const GAMESHARK_RANGES: [RangeInclusive<u16>; 2] = [0xA000..=0xDFFF, 0xFF80..=0xFFFE];
+
+/// A decoded cheat code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    /// ROM patch: reads of `address` return `value`, but only while the
+    /// cartridge byte there equals `compare` (if given)
+    GameGenie { address: u16, value: u8, compare: Option<u8> },
+    /// RAM patch: `value` is written to `address` after every frame.
+    /// `bank` selects the CGB WRAM bank for D000-DFFF (0x80-0x87), 0x01 otherwise.
+    GameShark { bank: u8, address: u16, value: u8 },
+}
+
+/// A code that isn't valid Game Genie or GameShark
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheatError(pub String);
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid cheat code: {}", self.0)
+    }
+}
+
+impl core::error::Error for CheatError {}
+
+impl Cheat {
+    /// Parse a Game Genie code (`ABC-DEF` or `ABC-DEF-GHI`) or an
+    /// 8-digit GameShark code (`ABCDEFGH`). Dashes and case don't matter.
+    ///
+    /// Game Genie: AB is the new value and FCDE the address with F inverted.
+    /// GI is the compare value, rotated right by 2 and XORed with 0xBA; H only
+    /// checks the code on the real device and is ignored.
+    ///
+    /// GameShark: AB is the RAM bank, CD the value and GHEF the address,
+    /// which must fall in GAMESHARK_RANGES.
+    pub fn parse(code: &str) -> Result<Cheat, CheatError> {
+        let invalid = || CheatError(code.into());
+        let digits: Vec<u8> = code
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+
+        match digits.len() {
+            6 | 9 if code.contains('-') => {
+                let value = byte(0);
+                let address = (((digits[5] ^ 0x0F) as u16) << 12)
+                    | ((digits[2] as u16) << 8)
+                    | ((digits[3] as u16) << 4)
+                    | digits[4] as u16;
+                if address >= 0x8000 {
+                    return Err(invalid());
+                }
+                let compare = (digits.len() == 9)
+                    .then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Cheat::GameGenie { address, value, compare })
+            },
+            8 if !code.contains('-') => {
+                let address = u16::from_le_bytes([byte(4), byte(6)]);
+                if !GAMESHARK_RANGES.iter().any(|range| range.contains(&address)) {
+                    return Err(invalid());
+                }
+                Ok(Cheat::GameShark { bank: byte(0), value: byte(2), address })
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The active cheats. Game Genie codes patch ROM reads on the bus;
+/// GameShark codes are applied by the machine at the end of each frame.
+#[derive(Debug, Clone, Default)]
+pub struct Cheats {
+    codes: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    /// Parse and enable `code`
+    pub fn add(&mut self, code: &str) -> Result<Cheat, CheatError> {
+        let cheat = Cheat::parse(code)?;
+        self.codes.push(cheat);
+        Ok(cheat)
+    }
+
+    pub fn clear(&mut self) {
+        self.codes.clear();
+    }
+
+    pub fn codes(&self) -> &[Cheat] {
+        &self.codes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// The byte a ROM read at `address` returns, given the cartridge holds `original` there
+    pub fn patch_rom(&self, address: u16, original: u8) -> u8 {
+        for cheat in &self.codes {
+            if let Cheat::GameGenie { address: target, value, compare } = *cheat {
+                if target == address && compare.is_none_or(|old| old == original) {
+                    return value;
+                }
+            }
+        }
+        original
+    }
+
+    /// (bank, address, value) for every GameShark code
+    pub fn ram_patches(&self) -> impl Iterator<Item = (u8, u16, u8)> + '_ {
+        self.codes.iter().filter_map(|cheat| match *cheat {
+            Cheat::GameShark { bank, address, value } => Some((bank, address, value)),
+            Cheat::GameGenie { .. } => None,
+        })
+    }
+}
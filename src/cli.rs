@@ -0,0 +1,61 @@
+//! Command-line options for the gbrust binary.
+//!
+//! A boot ROM comes from `--boot <path>`, or failing that from the
+//! GBRUST_BOOT_ROM environment variable. `--fast-boot` skips it and starts
+//! from the post-boot state, which is also where the machine starts when
+//! there's no boot ROM at all.
+
+use std::io;
+
+use crate::machine::Machine;
+
+pub const USAGE: &str = "[--fast-boot | --boot <path>] <rom_file>";
+
+/// Boot ROM picked up when --boot isn't given
+pub fn default_boot_rom() -> Option<String> {
+    std::env::var("GBRUST_BOOT_ROM").ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub rom: String,
+    pub boot_rom: Option<String>,  // Boot ROM to load, if any
+    pub fast_boot: bool,           // Skip the boot ROM, start post-boot
+}
+
+impl Options {
+    /// Parse the arguments after the program name. `default_boot_rom` is
+    /// used when --boot isn't given (see `default_boot_rom()`).
+    pub fn parse(args: &[String], default_boot_rom: Option<String>) -> Result<Options, String> {
+        let mut rom = None;
+        let mut boot = None;
+        let mut fast_boot = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--fast-boot" => fast_boot = true,
+                "--boot" => match args.next() {
+                    Some(path) => boot = Some(path.clone()),
+                    None => return Err("--boot needs a boot ROM path".to_string()),
+                },
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                path if rom.is_none() => rom = Some(path.to_string()),
+                _ => return Err("Only one ROM file can be given".to_string()),
+            }
+        }
+        if fast_boot && boot.is_some() {
+            return Err("--fast-boot and --boot can't be combined".to_string());
+        }
+        let rom = rom.ok_or_else(|| "No ROM file given".to_string())?;
+        Ok(Options { rom, boot_rom: boot.or(default_boot_rom), fast_boot })
+    }
+
+    /// Load the ROM (and boot ROM) and put the machine in its starting state.
+    /// With fast boot the boot ROM isn't read at all.
+    pub fn start(&self) -> io::Result<Machine> {
+        match &self.boot_rom {
+            Some(boot_rom) if !self.fast_boot => Machine::from_rom_with_boot(&self.rom, boot_rom),
+            _ => Machine::from_rom(&self.rom),
+        }
+    }
+}
@@ -1,3 +1,16 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{BufWriter, Write};
+
+use crate::mmu::{Accuracy, HardwareModel, MMU};
+
 pub struct CPU {
     // CPU registers
     pub a: u8,    // Accumulator
@@ -10,9 +23,78 @@ pub struct CPU {
     pub l: u8,
     pub sp: u16,  // Stack pointer
     pub pc: u16,  // Program counter
-    pub debug_mode: bool,
-    pub mmu: Option<crate::mmu::MMU>,
+    pub debug_mode: bool,  // Same as log_level = LogLevel::Verbose, whatever log_level says
+    pub log_level: LogLevel,
+    log_capture: Option<Vec<String>>,  // Lines logged since capture_log, instead of stdout
+    exec_hook: Option<ExecHook>,  // Called before each instruction (see set_exec_hook)
     pub interrupt_enabled: bool,  // Add this new field
+    pub ei_pending: bool,  // EI executed: IME turns on after the next instruction
+    pub halted: bool,  // Waiting in HALT for an interrupt
+    #[cfg(feature = "std")]
+    pub trace_file: Option<BufWriter<File>>,  // Per-instruction trace log (see set_trace)
+    pub trace_format: TraceFormat,
+    pub total_cycles: u64,  // Clock cycles executed since power-on (or reset_stats)
+    pub instructions: u64,  // Instructions executed since power-on (or reset_stats)
+    pub opcode_counts: [u64; 256],  // Executions of each base opcode (0xCB counts the prefix)
+    cycles_ticked: u32,  // Cycles already charged to the MMU during the current instruction
+    instruction_pc: u16,  // Address of the instruction being executed
+    pub call_stack: VecDeque<CallFrame>,  // Shadow stack of CALL/RST frames for backtraces
+    pub unbalanced_ret: Option<u16>,  // Address of the last RET executed with no frame to pop
+}
+
+/// Callback run before each instruction with the CPU (PC at the opcode) and the opcode byte
+pub type ExecHook = Box<dyn FnMut(&CPU, u8)>;
+
+/// One entry of the shadow call stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub caller: u16,  // Address of the CALL/RST instruction
+    pub target: u16,  // Address jumped to
+}
+
+/// Frames kept on the shadow call stack. Code that discards return
+/// addresses without RET would otherwise grow it forever.
+const CALL_STACK_LIMIT: usize = 1024;
+
+/// Line layout used for the instruction trace log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Registers followed by the opcode byte (OP:xx)
+    Standard,
+    /// Exact Gameboy Doctor layout, ending with the 4 bytes at PC (PCMEM:xx,xx,xx,xx)
+    Doctor,
+}
+
+/// How much the CPU logs as it runs. Each level includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    #[default]
+    Off,
+    /// Unknown opcodes and other errors
+    Errors,
+    /// Plus a decode line for every instruction
+    Instructions,
+    /// Plus the full register dump and the bytes of the next instruction
+    Verbose,
+}
+
+/// Register file and other CPU state, as captured in a save state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub interrupt_enabled: bool,
+    pub ei_pending: bool,
+    pub halted: bool,
+    pub total_cycles: u64,
 }
 
 // Flag bit positions
@@ -21,12 +103,48 @@ const SUBTRACT_FLAG: u8 = 6;
 const HALF_CARRY_FLAG: u8 = 5;
 const CARRY_FLAG: u8 = 4;
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug)]
 pub enum CPUError {
-    #[error("No MMU connected")]
-    NoMMU,
-    #[error("Unknown opcode: {0:#04x}")]
-    UnknownOpcode(u8),
+    UnknownOpcode { opcode: u8, pc: u16 },
+    UnknownCBOpcode { opcode: u8, pc: u16 },
+}
+
+impl fmt::Display for CPUError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CPUError::UnknownOpcode { opcode, pc } => write!(f, "Unknown opcode: {:#04x} at {:#06x}", opcode, pc),
+            CPUError::UnknownCBOpcode { opcode, pc } => {
+                write!(f, "Unknown CB-prefixed opcode: {:#04x} at {:#06x}", opcode, pc)
+            },
+        }
+    }
+}
+
+// core::error::Error works with and without std
+impl core::error::Error for CPUError {}
+
+impl CPUError {
+    /// Address of the instruction that faulted, if the error has one
+    pub fn pc(&self) -> Option<u16> {
+        match self {
+            CPUError::UnknownOpcode { pc, .. } | CPUError::UnknownCBOpcode { pc, .. } => Some(*pc),
+        }
+    }
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for CPU {
+    fn drop(&mut self) {
+        if let Err(e) = self.set_trace(None) {
+            println!("Failed to flush trace: {}", e);
+        }
+    }
 }
 
 impl CPU {
@@ -43,25 +161,87 @@ impl CPU {
             sp: 0,
             pc: 0,
             debug_mode: false,
-            mmu: None,
+            log_level: LogLevel::Off,
+            log_capture: None,
+            exec_hook: None,
             interrupt_enabled: true,  // Add this line
+            ei_pending: false,
+            halted: false,
+            #[cfg(feature = "std")]
+            trace_file: None,
+            trace_format: TraceFormat::Standard,
+            total_cycles: 0,
+            instructions: 0,
+            opcode_counts: [0; 256],
+            cycles_ticked: 0,
+            instruction_pc: 0,
+            call_stack: VecDeque::new(),
+            unbalanced_ret: None,
         }
     }
 
+    /// Reset to the DMG post-boot state
     pub fn initialize(&mut self) {
-        // Initialize CPU to Game Boy boot state
-        self.a = 0x01;
-        self.f = 0xB0;
-        self.b = 0x00;
-        self.c = 0x13;
-        self.d = 0x00;
-        self.e = 0xD8;
-        self.h = 0x01;
-        self.l = 0x4D;
-        self.sp = 0xFFFE;
+        self.initialize_as(HardwareModel::Dmg);
+    }
+
+    /// Reset to the power-on state, for running a boot ROM from 0x0000
+    pub fn power_on(&mut self) {
+        self.reset_control();
+        self.a = 0;
+        self.f = 0;
+        self.b = 0;
+        self.c = 0;
+        self.d = 0;
+        self.e = 0;
+        self.h = 0;
+        self.l = 0;
+        self.sp = 0;
         self.pc = 0x0000;
     }
 
+    /// Reset to the post-boot state `model`'s boot ROM leaves behind
+    pub fn initialize_as(&mut self, model: HardwareModel) {
+        self.reset_control();
+
+        // Initialize CPU to Game Boy post-boot state. A identifies the
+        // model to the game: 0x01 on DMG, 0x11 on CGB.
+        match model {
+            HardwareModel::Dmg => {
+                self.a = 0x01;
+                self.f = 0xB0;
+                self.b = 0x00;
+                self.c = 0x13;
+                self.d = 0x00;
+                self.e = 0xD8;
+                self.h = 0x01;
+                self.l = 0x4D;
+            },
+            HardwareModel::Cgb => {
+                self.a = 0x11;
+                self.f = 0x80;
+                self.b = 0x00;
+                self.c = 0x00;
+                self.d = 0xFF;
+                self.e = 0x56;
+                self.h = 0x00;
+                self.l = 0x0D;
+            },
+        }
+        self.sp = 0xFFFE;
+        self.pc = 0x0100;  // Cartridge entry point
+    }
+
+    /// Wake from HALT, clear IME (0 after power-on and after the boot ROM)
+    /// and forget the previous program's call frames
+    fn reset_control(&mut self) {
+        self.halted = false;
+        self.interrupt_enabled = false;
+        self.ei_pending = false;
+        self.call_stack.clear();
+        self.unbalanced_ret = None;
+    }
+
     // Flag helpers
     pub fn set_flag(&mut self, flag: u8, value: bool) {
         if value {
@@ -75,6 +255,11 @@ impl CPU {
         (self.f & (1 << flag)) != 0
     }
 
+    // Register pair helpers
+    pub fn get_hl(&self) -> u16 {
+        ((self.h as u16) << 8) | (self.l as u16)
+    }
+
     // region: 8-bit Load Instructions
     /// LD B,n - Load immediate value into B
     /// Opcode: 0x06
@@ -99,18 +284,33 @@ impl CPU {
     /// Length: 1 byte
     /// Flags: None affected
     /// Cycles: 8
-    pub fn ld_hl_dec_a(&mut self) -> Result<(), CPUError> {
+    pub fn ld_hl_dec_a(&mut self, mmu: &mut MMU) {
         let hl = ((self.h as u16) << 8) | (self.l as u16);
-        if let Some(mmu) = &mut self.mmu {
-            mmu.write_byte(hl, self.a);
-            // Decrement HL
-            let new_hl = hl.wrapping_sub(1);
-            self.h = (new_hl >> 8) as u8;
-            self.l = new_hl as u8;
-            Ok(())
-        } else {
-            Err(CPUError::NoMMU)
-        }
+        // The write and the decrement together count as one OAM bug write
+        mmu.oam_bug_write(hl);
+        mmu.write_byte(hl, self.a);
+        // Decrement HL
+        let new_hl = hl.wrapping_sub(1);
+        self.h = (new_hl >> 8) as u8;
+        self.l = new_hl as u8;
+    }
+
+    /// LD (HL),A - Store A at the address in HL
+    /// Opcode: 0x77
+    /// Length: 1 byte
+    /// Flags: None affected
+    /// Cycles: 8
+    pub fn ld_hl_a(&mut self, mmu: &mut MMU) {
+        mmu.write_byte(self.get_hl(), self.a);
+    }
+
+    /// LD (nn),A - Store A at absolute address nn
+    /// Opcode: 0xEA
+    /// Length: 3 bytes
+    /// Flags: None affected
+    /// Cycles: 16
+    pub fn ld_nn_a(&mut self, mmu: &mut MMU, addr: u16) {
+        mmu.write_byte(addr, self.a);
     }
 
     // LD A, D - Load register D into A
@@ -136,15 +336,9 @@ impl CPU {
     /// Length: 2 bytes
     /// Flags: None affected
     /// Cycles: 12
-    pub fn ldh_n_a(&mut self, n: u8) -> Result<(), CPUError> {
+    pub fn ldh_n_a(&mut self, mmu: &mut MMU, n: u8) {
         let address = 0xFF00 | (n as u16);
-        
-        if let Some(mmu) = &mut self.mmu {
-            mmu.write_byte(address, self.a);
-            Ok(())
-        } else {
-            Err(CPUError::NoMMU)
-        }
+        mmu.write_byte(address, self.a);
     }
 
     /// LDH A,(n) - Load A from high RAM address (FF00+n)
@@ -153,15 +347,30 @@ impl CPU {
     /// Flags: None affected
     /// Cycles: 12
     /// Note: This is the reverse of LDH (n),A
-    pub fn ldh_a_n(&mut self, n: u8) -> Result<(), CPUError> {
+    pub fn ldh_a_n(&mut self, mmu: &MMU, n: u8) {
         let address = 0xFF00 | (n as u16);
-        
-        if let Some(mmu) = &self.mmu {
-            self.a = mmu.read_byte(address);
-            Ok(())
-        } else {
-            Err(CPUError::NoMMU)
-        }
+        self.a = mmu.read_byte(address);
+    }
+
+    /// LDH (C),A - Store A into high RAM address (FF00+C)
+    /// Opcode: 0xE2
+    /// Length: 1 byte
+    /// Flags: None affected
+    /// Cycles: 8
+    pub fn ldh_c_a(&mut self, mmu: &mut MMU) {
+        let address = 0xFF00 | (self.c as u16);
+        mmu.write_byte(address, self.a);
+    }
+
+    /// LDH A,(C) - Load A from high RAM address (FF00+C)
+    /// Opcode: 0xF2
+    /// Length: 1 byte
+    /// Flags: None affected
+    /// Cycles: 8
+    /// Note: This is the reverse of LDH (C),A
+    pub fn ldh_a_c(&mut self, mmu: &MMU) {
+        let address = 0xFF00 | (self.c as u16);
+        self.a = mmu.read_byte(address);
     }
     // endregion
 
@@ -303,21 +512,52 @@ impl CPU {
         self.set_flag(CARRY_FLAG, result > 0xFF);
     }
 
+    /// A - value - carry, with the flags every subtraction sets.
+    /// Shared by SUB, SBC and CP so borrow handling lives in one place.
+    /// Flags: Z 1 H C
+    ///   Z: Set if result is zero
+    ///   N: Set
+    ///   H: Set if borrow from bit 4
+    ///   C: Set if borrow (A < value + carry)
+    pub fn sub_with_carry(&mut self, value: u8, carry: bool) -> u8 {
+        let carry = carry as u8;
+        let result = self.a.wrapping_sub(value).wrapping_sub(carry);
+
+        self.set_flag(ZERO_FLAG, result == 0);
+        self.set_flag(SUBTRACT_FLAG, true);
+        self.set_flag(HALF_CARRY_FLAG, (self.a & 0x0F) < (value & 0x0F) + carry);
+        self.set_flag(CARRY_FLAG, (self.a as u16) < value as u16 + carry as u16);
+        result
+    }
+
+    /// SUB n - Subtract immediate value from A
+    /// Opcode: 0xD6
+    /// Length: 2 bytes
+    /// Flags: Z 1 H C
+    pub fn sub_n(&mut self, n: u8) {
+        self.a = self.sub_with_carry(n, false);
+    }
+
+    /// SBC A,n - Subtract immediate value and Carry flag from A
+    /// Opcode: 0xDE
+    /// Length: 2 bytes
+    /// Flags: Z 1 H C
+    pub fn sbc_a_n(&mut self, n: u8) {
+        let carry = self.get_flag(CARRY_FLAG);
+        self.a = self.sub_with_carry(n, carry);
+    }
+
     /// CP n - Compare immediate value with A
     /// Opcode: 0xFE
     /// Length: 2 bytes
     /// Flags: Z 1 H C
     ///   Z: Set if result is zero (A == n)
     ///   N: Set
-    ///   H: Set if no borrow from bit 4
-    ///   C: Set if no borrow (A < n)
+    ///   H: Set if borrow from bit 4
+    ///   C: Set if borrow (A < n)
     pub fn cp_n(&mut self, n: u8) {
-        let result = self.a.wrapping_sub(n);
-        
-        self.set_flag(ZERO_FLAG, result == 0);
-        self.set_flag(SUBTRACT_FLAG, true);
-        self.set_flag(HALF_CARRY_FLAG, (self.a & 0x0F) < (n & 0x0F));
-        self.set_flag(CARRY_FLAG, self.a < n);
+        // Same as SUB with the result thrown away
+        self.sub_with_carry(n, false);
     }
     // endregion
 
@@ -362,6 +602,70 @@ impl CPU {
     pub fn ld_sp_nn(&mut self, nn: u16) {
        self.sp = nn;
     }
+
+    /// LD SP,HL - Load HL into SP
+    /// Opcode: 0xF9
+    /// Length: 1 byte
+    /// Flags: None affected
+    /// Cycles: 8
+    pub fn ld_sp_hl(&mut self) {
+        self.sp = self.get_hl();
+    }
+
+    /// LD (nn),SP - Store SP at absolute address nn (little-endian)
+    /// Opcode: 0x08
+    /// Length: 3 bytes
+    /// Flags: None affected
+    /// Cycles: 20
+    pub fn ld_nn_sp(&mut self, mmu: &mut MMU, addr: u16) {
+        self.write_word(mmu, addr, self.sp)
+    }
+
+    /// LD HL,SP+e - Load SP plus signed immediate offset into HL
+    /// Opcode: 0xF8
+    /// Length: 2 bytes
+    /// Flags: 0 0 H C
+    ///   Z: Reset
+    ///   N: Reset
+    ///   H: Set if carry from bit 3 of the low byte addition
+    ///   C: Set if carry from bit 7 of the low byte addition
+    /// Cycles: 12
+    pub fn ld_hl_sp_e(&mut self, e: u8) {
+        let result = self.sp_plus_e(e);
+        self.h = (result >> 8) as u8;
+        self.l = result as u8;
+    }
+    // endregion
+
+    // region: 16-bit Arithmetic Instructions
+    /// ADD SP,e - Add signed immediate offset to SP
+    /// Opcode: 0xE8
+    /// Length: 2 bytes
+    /// Flags: 0 0 H C
+    ///   Z: Reset
+    ///   N: Reset
+    ///   H: Set if carry from bit 3 of the low byte addition
+    ///   C: Set if carry from bit 7 of the low byte addition
+    /// Cycles: 16
+    pub fn add_sp_e(&mut self, e: u8) {
+        self.sp = self.sp_plus_e(e);
+    }
+
+    /// Computes SP + e (e sign-extended) and sets flags for ADD SP,e / LD HL,SP+e.
+    /// H and C come from the unsigned addition of the low byte of SP and e,
+    /// not from the 16-bit result.
+    fn sp_plus_e(&mut self, e: u8) -> u16 {
+        let sp = self.sp;
+        let half_carry = ((sp & 0x0F) + (e as u16 & 0x0F)) > 0x0F;
+        let carry = ((sp & 0xFF) + e as u16) > 0xFF;
+
+        self.set_flag(ZERO_FLAG, false);
+        self.set_flag(SUBTRACT_FLAG, false);
+        self.set_flag(HALF_CARRY_FLAG, half_carry);
+        self.set_flag(CARRY_FLAG, carry);
+
+        sp.wrapping_add(e as i8 as i16 as u16)
+    }
     // endregion
 
     // region: 16-bit Jump Instructions
@@ -373,6 +677,15 @@ impl CPU {
     pub fn jp(&mut self, addr: u16) {
         self.pc = addr;
     }
+    /// JR n - Jump to address PC+n (n is signed)
+    /// Opcode: 0x18
+    /// Length: 2 bytes
+    /// Flags: None affected
+    /// Cycles: 12
+    pub fn jr_n(&mut self, n: u8) {
+        self.pc = self.pc.wrapping_add(n as i8 as i16 as u16);
+    }
+
     /// JR Nz n - Jump to address PC+n if Z flag is reset
     /// Opcode: 0x20
     /// Length: 2 bytes
@@ -396,13 +709,29 @@ impl CPU {
         // Do nothing
     }
 
+    /// STOP - Enter low-power mode, or perform a CGB speed switch
+    /// Opcode: 0x10 0x00
+    /// Length: 2 bytes
+    /// Flags: None affected
+    /// Cycles: 4
+    /// Note: If KEY1 (0xFF4D) bit 0 is armed, the CPU toggles between
+    /// normal and double speed. Low-power stop mode itself is not
+    /// emulated yet, so otherwise execution simply continues.
+    pub fn stop(&mut self, mmu: &mut MMU) {
+        if mmu.speed_switch_armed() {
+            mmu.switch_speed();
+        }
+    }
+
     /// DI - Disable interrupts
     /// Opcode: 0xF3
     /// Length: 1 byte
     /// Flags: None affected
     /// Cycles: 4
+    /// Note: Also cancels an EI that hasn't taken effect yet
     pub fn di(&mut self) {
         self.interrupt_enabled = false;
+        self.ei_pending = false;
     }
 
     /// EI - Enable interrupts
@@ -410,319 +739,756 @@ impl CPU {
     /// Length: 1 byte
     /// Flags: None affected
     /// Cycles: 4
+    /// Note: IME is only set once the following instruction has executed
+    /// (see step), so `EI; RET` returns before another interrupt can nest
     pub fn ei(&mut self) {
-        self.interrupt_enabled = true;
+        self.ei_pending = true;
+    }
+
+    /// HALT - Stop executing until an interrupt is requested
+    /// Opcode: 0x76
+    /// Length: 1 byte
+    /// Flags: None affected
+    /// Cycles: 4
+    pub fn halt(&mut self) {
+        self.halted = true;
     }
     // endregion
 
     // region: Stack Operations
-    /// RST 18h - Push current PC on stack and jump to 0x0018
-    /// Opcode: 0xDF
+    /// RST n - Push current PC on stack and jump to fixed vector n
+    /// Opcodes: 0xC7 (00h), 0xCF (08h), 0xD7 (10h), 0xDF (18h),
+    ///          0xE7 (20h), 0xEF (28h), 0xF7 (30h), 0xFF (38h)
     /// Length: 1 byte
     /// Flags: None affected
     /// Cycles: 16
-    pub fn rst_18(&mut self) -> Result<(), CPUError> {
-        // Decrement SP and write high byte
-        self.sp = self.sp.wrapping_sub(1);
-        if let Some(mmu) = &mut self.mmu {
-            mmu.write_byte(self.sp, (self.pc >> 8) as u8);
-            
-            // Decrement SP and write low byte
-            self.sp = self.sp.wrapping_sub(1);
-            mmu.write_byte(self.sp, self.pc as u8);
-            
-            // Jump to 0x0018
-            self.pc = 0x0018;
-            Ok(())
-        } else {
-            Err(CPUError::NoMMU)
+    pub fn rst(&mut self, mmu: &mut MMU, vector: u16) {
+        // Push PC: high byte ends up at SP+1, low byte at SP
+        self.sp = self.sp.wrapping_sub(2);
+        self.write_word(mmu, self.sp, self.pc);
+
+        self.pc = vector;
+        self.push_call_frame(vector);
+    }
+
+    /// CALL nn - Push the address of the next instruction and jump to nn
+    /// Opcode: 0xCD
+    /// Length: 3 bytes
+    /// Flags: None affected
+    /// Cycles: 24
+    pub fn call(&mut self, mmu: &mut MMU, addr: u16) {
+        self.sp = self.sp.wrapping_sub(2);
+        self.write_word(mmu, self.sp, self.pc);
+
+        self.pc = addr;
+        self.push_call_frame(addr);
+    }
+
+    /// RET - Pop the return address into PC
+    /// Opcode: 0xC9
+    /// Length: 1 byte
+    /// Flags: None affected
+    /// Cycles: 16
+    pub fn ret(&mut self, mmu: &MMU) {
+        let low = mmu.read_byte(self.sp) as u16;
+        let high = mmu.read_byte(self.sp.wrapping_add(1)) as u16;
+        self.sp = self.sp.wrapping_add(2);
+        self.pc = (high << 8) | low;
+
+        if self.call_stack.pop_back().is_none() {
+            self.unbalanced_ret = Some(self.instruction_pc);
         }
     }
 
-    /// RST 38h - Push current PC on stack and jump to 0x0038
-    /// Opcode: 0xFF
+    /// RETI - Return from an interrupt handler, enabling interrupts
+    /// Opcode: 0xD9
     /// Length: 1 byte
     /// Flags: None affected
     /// Cycles: 16
-    pub fn rst_38(&mut self) -> Result<(), CPUError> {
-        // Decrement SP and write high byte
-        self.sp = self.sp.wrapping_sub(1);
-        if let Some(mmu) = &mut self.mmu {
-            mmu.write_byte(self.sp, (self.pc >> 8) as u8);
-            
-            // Decrement SP and write low byte
-            self.sp = self.sp.wrapping_sub(1);
-            mmu.write_byte(self.sp, self.pc as u8);
-            
-            // Jump to 0x0038
-            self.pc = 0x0038;
-            Ok(())
-        } else {
-            Err(CPUError::NoMMU)
+    pub fn reti(&mut self, mmu: &MMU) {
+        self.ret(mmu);
+        self.interrupt_enabled = true;
+    }
+
+    fn push_call_frame(&mut self, target: u16) {
+        if self.call_stack.len() == CALL_STACK_LIMIT {
+            self.call_stack.pop_front();
         }
+        self.call_stack.push_back(CallFrame { caller: self.instruction_pc, target });
+    }
+
+    /// RST 18h - Push current PC on stack and jump to 0x0018
+    /// Opcode: 0xDF
+    pub fn rst_18(&mut self, mmu: &mut MMU) {
+        self.rst(mmu, 0x0018)
+    }
+
+    /// RST 38h - Push current PC on stack and jump to 0x0038
+    /// Opcode: 0xFF
+    pub fn rst_38(&mut self, mmu: &mut MMU) {
+        self.rst(mmu, 0x0038)
     }
     // endregion
 
     // region: CPU Operation Functions
-    pub fn step(&mut self) -> Result<(), CPUError> {
-        if self.debug_mode {
-            self.print_state();
-            
-            // Print next instruction
-            if let Some(mmu) = &self.mmu {
-                let opcode = mmu.read_byte(self.pc);
-                println!("Next instruction at {:04X}: {:02X}", self.pc, opcode);
+    /// Execute one instruction on the bus `mmu`. Returns the number of
+    /// clock cycles it took.
+    pub fn step(&mut self, mmu: &mut MMU) -> Result<u32, CPUError> {
+        // A requested interrupt ends HALT whether or not IME is set
+        if self.halted {
+            if mmu.pending_interrupt().is_none() {
+                mmu.tick(4);
+                self.total_cycles += 4;
+                return Ok(4);
             }
+            self.halted = false;
         }
+        if let Some(cycles) = self.service_interrupt(mmu) {
+            return Ok(cycles);
+        }
+
+        if self.logs(LogLevel::Verbose) {
+            self.log(self.state_string().trim_end());
+
+            // Print next instruction
+            let length = opcode_length(mmu.read_byte(self.pc));
+            let bytes: Vec<String> = (0..length as u16)
+                .map(|i| format!("{:02X}", mmu.read_byte(self.pc.wrapping_add(i))))
+                .collect();
+            let line = format!("Next instruction at {:04X}: {}", self.pc, bytes.join(" "));
+            self.log(&line);
+        }
+
+        #[cfg(feature = "std")]
+        if self.trace_file.is_some() {
+            self.write_trace_line(mmu);
+        }
+
+        if let Some(mut hook) = self.exec_hook.take() {
+            hook(self, mmu.read_byte(self.pc));
+            self.exec_hook = Some(hook);
+        }
+
+        self.cycles_ticked = 0;
+        mmu.instruction_pc = self.pc;
+        let enable_interrupts = self.ei_pending;
+        let opcode = self.fetch_byte(mmu);
+        let cycles = match self.execute(mmu, opcode) {
+            Ok(cycles) => cycles,
+            Err(e) => {
+                if self.logs(LogLevel::Errors) {
+                    self.log(&format!("CPU Error: {}", e));
+                }
+                return Err(e);
+            },
+        };
         
-        let opcode = self.fetch_byte()?;
-        let cycles = self.execute(opcode)?;
-        
-        // Update LCD timing
-        if let Some(ref mut mmu) = self.mmu {
-            mmu.update_lcd(cycles);
+        // Opcode and operand fetches have already ticked the MMU as they
+        // happened. Other memory accesses ((HL) loads/stores, stack pushes,
+        // LDH and LD (nn) data accesses) and internal delays are still
+        // charged in bulk here, once the instruction has finished.
+        mmu.tick(cycles.saturating_sub(self.cycles_ticked));
+        // An EI before this instruction takes effect now, unless it ran DI
+        if enable_interrupts && self.ei_pending {
+            self.ei_pending = false;
+            self.interrupt_enabled = true;
         }
+        self.total_cycles += cycles as u64;
+        self.instructions += 1;
+        self.opcode_counts[opcode as usize] += 1;
         
-        Ok(())
+        Ok(cycles)
+    }
+
+    /// Jump to the handler of the highest-priority pending interrupt if IME
+    /// is set, clearing IME so the handler isn't re-entered before it
+    /// executes EI or RETI. Returns the cycles the dispatch took, if it happened.
+    fn service_interrupt(&mut self, mmu: &mut MMU) -> Option<u32> {
+        if !self.interrupt_enabled {
+            return None;
+        }
+        let bit = mmu.pending_interrupt()?;
+        mmu.acknowledge_interrupt(bit);
+        self.interrupt_enabled = false;
+        self.ei_pending = false;
+
+        let vector = 0x40 + bit as u16 * 8;
+        self.instruction_pc = self.pc;
+        self.sp = self.sp.wrapping_sub(2);
+        self.write_word(mmu, self.sp, self.pc);
+        self.pc = vector;
+        self.push_call_frame(vector);
+
+        let cycles = 20;
+        mmu.tick(cycles);
+        self.total_cycles += cycles as u64;
+        Some(cycles)
     }
 
-    pub fn execute(&mut self, opcode: u8) -> Result<u32, CPUError> {
+    /// Call `hook` before every instruction executes, e.g. for tracing or
+    /// coverage. Replaces any hook already installed.
+    pub fn set_exec_hook(&mut self, hook: ExecHook) {
+        self.exec_hook = Some(hook);
+    }
+
+    pub fn clear_exec_hook(&mut self) {
+        self.exec_hook = None;
+    }
+
+    /// Zero the instruction, cycle and per-opcode counters
+    pub fn reset_stats(&mut self) {
+        self.total_cycles = 0;
+        self.instructions = 0;
+        self.opcode_counts = [0; 256];
+    }
+
+    /// Run without the debugger until `done` returns true or `max_cycles`
+    /// more cycles have elapsed. Returns the number of cycles executed.
+    pub fn run_headless<F>(&mut self, mmu: &mut MMU, max_cycles: u64, mut done: F) -> Result<u64, CPUError>
+    where
+        F: FnMut(&CPU, &MMU) -> bool,
+    {
+        let start = self.total_cycles;
+        while self.total_cycles - start < max_cycles && !done(self, mmu) {
+            self.step(mmu)?;
+        }
+        Ok(self.total_cycles - start)
+    }
+
+    /// Execute `opcode`, which has already been fetched: PC points just past
+    /// it, at its operands. Errors report PC - 1 as the faulting address.
+    pub fn execute(&mut self, mmu: &mut MMU, opcode: u8) -> Result<u32, CPUError> {
+        self.instruction_pc = self.pc.wrapping_sub(1);
         match opcode {
             0x00 => {
-                if self.debug_mode {
-                    println!("NOP - No operation");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("NOP - No operation");
                 }
                 self.nop();
                 Ok(4)  // NOP takes 4 cycles
             },
             0x06 => {
-                let n = self.fetch_byte()?;
-                if self.debug_mode {
-                    println!("LD B,n - Load immediate value into B (n={:02X})", n);
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD B,n - Load immediate value into B (n={:02X})", n));
                 }
                 self.ld_b_n(n);
                 Ok(8)  // LD B,n takes 8 cycles
             },
             0x04 => {
-                if self.debug_mode {
-                    println!("INC B - Increment register B");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("INC B - Increment register B");
                 }
                 self.inc_b();
                 Ok(4)
             },
             0x05 => {
-                if self.debug_mode {
-                    println!("DEC B - Decrement register B");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("DEC B - Decrement register B");
                 }
                 self.dec_b();
                 Ok(4)
             },
+            0x08 => {
+                let addr = self.fetch_word(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD (${:04X}),SP [SP=${:04X}]", addr, self.sp));
+                }
+                self.ld_nn_sp(mmu, addr);
+                Ok(20)
+            },
             0x0C => {
-                if self.debug_mode {
-                    println!("INC C - Increment register C");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("INC C - Increment register C");
                 }
                 self.inc_c();
                 Ok(4)
             },
             0x0D => {
-                if self.debug_mode {
-                    println!("DEC C - Decrement register C");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("DEC C - Decrement register C");
                 }
                 self.dec_c();
                 Ok(4)
             },
             0x0E => {
-                let n = self.fetch_byte()?;
-                if self.debug_mode {
-                    println!("LD C,n - Load immediate value into C (n={:02X})", n);
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD C,n - Load immediate value into C (n={:02X})", n));
                 }
                 self.ld_c_n(n);
                 Ok(8)
             },
             0x14 => {
-                if self.debug_mode {
-                    println!("INC D - Increment register D");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("INC D - Increment register D");
                 }
                 self.inc_d();
                 Ok(4)
             },
             0x15 => {
-                if self.debug_mode {
-                    println!("DEC D - Decrement register D");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("DEC D - Decrement register D");
                 }
                 self.dec_d();
                 Ok(4)
             },
+            0x10 => {
+                // STOP is followed by a padding byte
+                self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log("STOP");
+                }
+                self.stop(mmu);
+                Ok(4)
+            },
+            0x18 => {
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("JR ${:02X}", n));
+                }
+                self.jr_n(n);
+                Ok(12)
+            },
             0x1F => {
-                if self.debug_mode {
-                    println!("RRA");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("RRA");
                 }
                 self.rra();
                 Ok(4)
             },
             0x20 => {
-                let n = self.fetch_byte()?;
-                if self.debug_mode {
-                    println!("JR NZ,${:02X}", n);
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("JR NZ,${:02X}", n));
                 }
                 self.jr_nz_n(n);
                 Ok(12)
             },
             0x21 => {
-                let nn = self.fetch_word()?;
-                if self.debug_mode {
-                    println!("LD HL,${:04X}", nn);
+                let nn = self.fetch_word(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD HL,${:04X}", nn));
                 }
                 self.ld_hl_nn(nn);
                 Ok(12)
             },
             0x31 => {
-                let nn = self.fetch_word()?;
-                if self.debug_mode {
-                    println!("LD SP,${:04X}", nn);
+                let nn = self.fetch_word(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD SP,${:04X}", nn));
                 }
                 self.ld_sp_nn(nn);
                 Ok(12)
             },
             0x32 => {
-                if self.debug_mode {
+                if self.logs(LogLevel::Instructions) {
                     let hl = ((self.h as u16) << 8) | (self.l as u16);
-                    println!("LD (HL-),A [HL=${:04X}, A=${:02X}]", hl, self.a);
+                    self.log(&format!("LD (HL-),A [HL=${:04X}, A=${:02X}]", hl, self.a));
                 }
-                self.ld_hl_dec_a()?;
+                self.ld_hl_dec_a(mmu);
                 Ok(8)
             },
             0x3E => {
-                let n = self.fetch_byte()?;
-                if self.debug_mode {
-                    println!("LD A,${:02X}", n);
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD A,${:02X}", n));
                 }
                 self.ld_a_n(n);
                 Ok(8)
             },
             0x89 => {
-                if self.debug_mode {
-                    println!("ADC A,C");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("ADC A,C");
                 }
                 self.adc_a_c();
                 Ok(4)
             },
             0xAF => {
-                if self.debug_mode {
-                    println!("XOR A,A");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("XOR A,A");
                 }
                 self.xor_a();
                 Ok(4)
             },
             0xC3 => {
-                let addr = self.fetch_word()?;
-                if self.debug_mode {
-                    println!("JP ${:04X}", addr);
+                let addr = self.fetch_word(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("JP ${:04X}", addr));
                 }
                 self.jp(addr);
                 Ok(16)
             },
-            0xDF => {
-                if self.debug_mode {
-                    println!("RST 18H");
+            0xC9 => {
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("RET [SP=${:04X}]", self.sp));
                 }
-                self.rst_18()?;
+                self.ret(mmu);
                 Ok(16)
             },
-            0xFF => {
-                if self.debug_mode {
-                    println!("RST 38H");
+            0xCD => {
+                let addr = self.fetch_word(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("CALL ${:04X}", addr));
+                }
+                self.call(mmu, addr);
+                Ok(24)
+            },
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                // The vector is encoded in bits 3-5 of the opcode
+                let vector = (opcode & 0x38) as u16;
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("RST {:02X}H", vector));
                 }
-                self.rst_38()?;
+                self.rst(mmu, vector);
                 Ok(16)
             },
+            0x77 => {
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD (HL),A [HL=${:04X}, A=${:02X}]", self.get_hl(), self.a));
+                }
+                self.ld_hl_a(mmu);
+                Ok(8)
+            },
             0x7A => {
-                if self.debug_mode {
-                    println!("LD A,D");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("LD A,D");
                 }
                 self.ld_a_d();
                 Ok(4)
             },
             0xE0 => {
-                let n = self.fetch_byte()?;
-                if self.debug_mode {
-                    println!("LDH (${:02X}),A [A=${:02X}]", n, self.a);
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LDH (${:02X}),A [A=${:02X}]", n, self.a));
                 }
-                self.ldh_n_a(n)?;
+                self.ldh_n_a(mmu, n);
                 Ok(12)
             },
+            0xE2 => {
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LDH (C),A [C=${:02X}, A=${:02X}]", self.c, self.a));
+                }
+                self.ldh_c_a(mmu);
+                Ok(8)
+            },
             0xF0 => {
-                let n = self.fetch_byte()?;
-                if self.debug_mode {
-                    println!("LDH A,(${:02X})", n);
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LDH A,(${:02X})", n));
+                }
+                self.ldh_a_n(mmu, n);
+                Ok(12)
+            },
+            0xF2 => {
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LDH A,(C) [C=${:02X}]", self.c));
                 }
-                self.ldh_a_n(n)?;
+                self.ldh_a_c(mmu);
+                Ok(8)
+            },
+            0xE8 => {
+                let e = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("ADD SP,{} [SP=${:04X}]", e as i8, self.sp));
+                }
+                self.add_sp_e(e);
+                Ok(16)
+            },
+            0xEA => {
+                let addr = self.fetch_word(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD (${:04X}),A [A=${:02X}]", addr, self.a));
+                }
+                self.ld_nn_a(mmu, addr);
+                Ok(16)
+            },
+            0xF8 => {
+                let e = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD HL,SP{:+} [SP=${:04X}]", e as i8, self.sp));
+                }
+                self.ld_hl_sp_e(e);
                 Ok(12)
             },
+            0xF9 => {
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("LD SP,HL [HL=${:04X}]", self.get_hl()));
+                }
+                self.ld_sp_hl();
+                Ok(8)
+            },
             0xF3 => {
-                if self.debug_mode {
-                    println!("DI - Disable interrupts");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("DI - Disable interrupts");
                 }
                 self.di();
                 Ok(4)
             },
             0xFB => {
-                if self.debug_mode {
-                    println!("EI - Enable interrupts");
+                if self.logs(LogLevel::Instructions) {
+                    self.log("EI - Enable interrupts");
                 }
                 self.ei();
                 Ok(4)
             },
+            0x76 => {
+                if self.logs(LogLevel::Instructions) {
+                    self.log("HALT");
+                }
+                self.halt();
+                Ok(4)
+            },
+            0xD9 => {
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("RETI [SP=${:04X}]", self.sp));
+                }
+                self.reti(mmu);
+                Ok(16)
+            },
+            0xD6 => {
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("SUB ${:02X}", n));
+                }
+                self.sub_n(n);
+                Ok(8)
+            },
+            0xDE => {
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("SBC A,${:02X}", n));
+                }
+                self.sbc_a_n(n);
+                Ok(8)
+            },
             0xFE => {
-                let n = self.fetch_byte()?;
-                if self.debug_mode {
-                    println!("CP ${:02X} [A=${:02X}]", n, self.a);
+                let n = self.fetch_byte(mmu);
+                if self.logs(LogLevel::Instructions) {
+                    self.log(&format!("CP ${:02X} [A=${:02X}]", n, self.a));
                 }
                 self.cp_n(n);
                 Ok(8)
             },
-            _ => Err(CPUError::UnknownOpcode(opcode)),
+            0xCB => {
+                let cb_opcode = self.fetch_byte(mmu);
+                self.execute_cb(cb_opcode)
+            },
+            _ => Err(CPUError::UnknownOpcode { opcode, pc: self.instruction_pc }),
         }
     }
+
+    /// Execute the second byte of a 0xCB-prefixed instruction; PC points
+    /// just past it, so the prefix is at PC - 2
+    pub fn execute_cb(&mut self, opcode: u8) -> Result<u32, CPUError> {
+        self.instruction_pc = self.pc.wrapping_sub(2);
+        Err(CPUError::UnknownCBOpcode { opcode, pc: self.instruction_pc })
+    }
     // endregion
 
     // region: Helper Functions
-    pub fn fetch_byte(&mut self) -> Result<u8, CPUError> {
-        if let Some(mmu) = &self.mmu {
-            let byte = mmu.read_byte(self.pc);
-            self.pc = self.pc.wrapping_add(1);
-            Ok(byte)
-        } else {
-            Err(CPUError::NoMMU)
+    /// Read the byte at PC and advance PC, ticking the MMU by one memory access
+    /// (under Accuracy::Fast the whole instruction is charged at the end instead)
+    pub fn fetch_byte(&mut self, mmu: &mut MMU) -> u8 {
+        let byte = mmu.read_byte(self.pc);
+        if mmu.accuracy == Accuracy::Accurate {
+            mmu.tick(4);
+            self.cycles_ticked += 4;
         }
+        self.pc = self.pc.wrapping_add(1);
+        byte
     }
 
-    pub fn fetch_word(&mut self) -> Result<u16, CPUError> {
-        if let Some(mmu) = &self.mmu {
-            let low_byte = mmu.read_byte(self.pc);
-            let high_byte = mmu.read_byte(self.pc.wrapping_add(1));
-            self.pc = self.pc.wrapping_add(2);
-            Ok(((high_byte as u16) << 8) | (low_byte as u16))
-        } else {
-            Err(CPUError::NoMMU)
+    /// Read the little-endian word at PC and advance PC, ticking the MMU per byte
+    pub fn fetch_word(&mut self, mmu: &mut MMU) -> u16 {
+        let low_byte = self.fetch_byte(mmu);
+        let high_byte = self.fetch_byte(mmu);
+        ((high_byte as u16) << 8) | (low_byte as u16)
+    }
+
+    pub fn write_word(&mut self, mmu: &mut MMU, addr: u16, value: u16) {
+        mmu.write_byte(addr, value as u8);
+        mmu.write_byte(addr.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            a: self.a,
+            f: self.f,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            sp: self.sp,
+            pc: self.pc,
+            interrupt_enabled: self.interrupt_enabled,
+            ei_pending: self.ei_pending,
+            halted: self.halted,
+            total_cycles: self.total_cycles,
         }
     }
 
-    pub fn set_mmu(&mut self, mmu: crate::mmu::MMU) {
-        self.mmu = Some(mmu);
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.a = state.a;
+        self.f = state.f;
+        self.b = state.b;
+        self.c = state.c;
+        self.d = state.d;
+        self.e = state.e;
+        self.h = state.h;
+        self.l = state.l;
+        self.sp = state.sp;
+        self.pc = state.pc;
+        self.interrupt_enabled = state.interrupt_enabled;
+        self.ei_pending = state.ei_pending;
+        self.halted = state.halted;
+        self.total_cycles = state.total_cycles;
+        // The shadow call stack belongs to the timeline being left
+        self.call_stack.clear();
+        self.unbalanced_ret = None;
     }
 
-    pub fn print_state(&self) {
-        println!("\nCPU State:");
-        println!("AF: {:02X}{:02X} BC: {:02X}{:02X} DE: {:02X}{:02X} HL: {:02X}{:02X}",
-            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l);
-        println!("PC: {:04X} SP: {:04X}", self.pc, self.sp);
-        println!("Flags: Z:{} N:{} H:{} C:{}",
+    /// Start logging every executed instruction to `path` (truncating it),
+    /// or with None stop and flush the current log. Each line holds the
+    /// registers and PC before the instruction runs, using the Gameboy
+    /// Doctor column layout, followed by the opcode. The log is buffered;
+    /// it's also flushed when the CPU is dropped.
+    #[cfg(feature = "std")]
+    pub fn set_trace(&mut self, path: Option<&str>) -> std::io::Result<()> {
+        if let Some(mut file) = self.trace_file.take() {
+            file.flush()?;
+        }
+        if let Some(path) = path {
+            self.trace_file = Some(BufWriter::new(File::create(path)?));
+        }
+        Ok(())
+    }
+
+    fn register_columns(&self) -> String {
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc
+        )
+    }
+
+    #[cfg(feature = "std")]
+    fn trace_line(&self, mmu: &MMU) -> String {
+        match self.trace_format {
+            TraceFormat::Standard => {
+                format!("{} OP:{:02X}", self.register_columns(), mmu.read_byte(self.pc))
+            },
+            TraceFormat::Doctor => self.doctor_line(mmu),
+        }
+    }
+
+    /// Current state in the exact Gameboy Doctor log format, e.g.
+    /// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+    pub fn doctor_line(&self, mmu: &MMU) -> String {
+        let byte = |offset: u16| mmu.read_byte(self.pc.wrapping_add(offset));
+        format!(
+            "{} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.register_columns(),
+            byte(0),
+            byte(1),
+            byte(2),
+            byte(3)
+        )
+    }
+
+    #[cfg(feature = "std")]
+    fn write_trace_line(&mut self, mmu: &MMU) {
+        let line = self.trace_line(mmu);
+        if let Some(file) = &mut self.trace_file {
+            if let Err(e) = writeln!(file, "{}", line) {
+                println!("Failed to write trace, disabling: {}", e);
+                self.trace_file = None;
+            }
+        }
+    }
+
+    /// Formatted register dump, exactly as printed by `print_state`
+    pub fn state_string(&self) -> String {
+        let mut state = String::new();
+        state.push_str("\nCPU State:\n");
+        state.push_str(&format!("AF: {:02X}{:02X} BC: {:02X}{:02X} DE: {:02X}{:02X} HL: {:02X}{:02X}\n",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l));
+        state.push_str(&format!("PC: {:04X} SP: {:04X}\n", self.pc, self.sp));
+        state.push_str(&format!("Flags: Z:{} N:{} H:{} C:{}\n",
             self.get_flag(ZERO_FLAG) as u8,
             self.get_flag(SUBTRACT_FLAG) as u8,
             self.get_flag(HALF_CARRY_FLAG) as u8,
-            self.get_flag(CARRY_FLAG) as u8);
-        println!("Interrupts: {}\n", if self.interrupt_enabled { "Enabled" } else { "Disabled" });
+            self.get_flag(CARRY_FLAG) as u8));
+        state.push_str(&format!("Interrupts: {}\n\n", if self.interrupt_enabled { "Enabled" } else { "Disabled" }));
+        state
+    }
+
+    pub fn print_state(&self) {
+        print!("{}", self.state_string());
+    }
+
+    /// The level in effect: Verbose while debug_mode is set
+    pub fn effective_log_level(&self) -> LogLevel {
+        if self.debug_mode { LogLevel::Verbose } else { self.log_level }
+    }
+
+    fn logs(&self, level: LogLevel) -> bool {
+        level != LogLevel::Off && level <= self.effective_log_level()
+    }
+
+    fn log(&mut self, line: &str) {
+        match &mut self.log_capture {
+            Some(lines) => lines.push(line.into()),
+            None => println!("{}", line),
+        }
+    }
+
+    /// Collect log lines in memory instead of printing them
+    pub fn capture_log(&mut self) {
+        self.log_capture = Some(Vec::new());
+    }
+
+    /// Lines logged since capture_log (or the last take_log)
+    pub fn take_log(&mut self) -> Vec<String> {
+        self.log_capture.as_mut().map(core::mem::take).unwrap_or_default()
     }
     // endregion
 }
+
+/// Length in bytes of the instruction starting with `opcode`, operands
+/// included. CB-prefixed instructions are all 2 bytes; unused opcodes count as 1.
+pub fn opcode_length(opcode: u8) -> u8 {
+    match opcode {
+        // nn operand: LD rr,nn, LD (nn),SP, JP, CALL, LD (nn),A / A,(nn)
+        0x01 | 0x08 | 0x11 | 0x21 | 0x31 | 0xC2 | 0xC3 | 0xC4 | 0xCA | 0xCC | 0xCD | 0xD2 | 0xD4 | 0xDA | 0xDC
+        | 0xEA | 0xFA => 3,
+        // n operand: LD r,n, STOP, JR, ALU A,n, LDH, ADD SP,e, LD HL,SP+e; and the CB prefix
+        0x06 | 0x0E | 0x10 | 0x16 | 0x18 | 0x1E | 0x20 | 0x26 | 0x28 | 0x2E | 0x30 | 0x36 | 0x38 | 0x3E | 0xC6
+        | 0xCB | 0xCE | 0xD6 | 0xDE | 0xE0 | 0xE6 | 0xE8 | 0xEE | 0xF0 | 0xF6 | 0xF8 | 0xFE => 2,
+        _ => 1,
+    }
+}
+
+/// Which base and CB-prefixed opcodes the CPU can decode, found by running
+/// each one through `execute` on a scratch CPU and MMU.
+pub fn implemented_opcodes() -> ([bool; 256], [bool; 256]) {
+    fn run(bytes: &[u8]) -> Result<u32, CPUError> {
+        let mut mmu = MMU::new();
+        for (i, &byte) in bytes.iter().enumerate() {
+            mmu.write_byte(0xC000 + i as u16, byte);
+        }
+        let mut cpu = CPU::new();
+        cpu.pc = 0xC000;
+        cpu.sp = 0xDFF0;
+        cpu.step(&mut mmu)
+    }
+
+    let mut base = [false; 256];
+    let mut cb = [false; 256];
+    for opcode in 0..=255u8 {
+        base[opcode as usize] = !matches!(run(&[opcode]), Err(CPUError::UnknownOpcode { .. }));
+        cb[opcode as usize] = !matches!(run(&[0xCB, opcode]), Err(CPUError::UnknownCBOpcode { .. }));
+    }
+    (base, cb)
+}
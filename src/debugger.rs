@@ -0,0 +1,664 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::cpu::{opcode_length, CPUError, CPU};
+use crate::mmu::MMU;
+use crate::ppu::{decode_tile, OamEntry};
+
+/// Tiles in one VRAM bank's tile data area (0x8000-0x97FF)
+pub const TILE_COUNT: usize = 384;
+
+/// Tiles per row in the tile viewer
+pub const TILES_PER_ROW: usize = 16;
+
+/// ASCII characters for shades 0 (lightest) to 3 (darkest)
+const SHADE_CHARS: [char; 4] = [' ', '.', 'o', '#'];
+
+/// Render every tile in VRAM `bank` as ASCII art, one character per pixel,
+/// laid out in a grid of TILES_PER_ROW tiles (128x192 characters).
+pub fn dump_tiles(mmu: &MMU, bank: u8) -> String {
+    let width = TILES_PER_ROW * 8;
+    let mut out = String::with_capacity((width + 1) * TILE_COUNT / TILES_PER_ROW * 8);
+    for tile_row in 0..TILE_COUNT / TILES_PER_ROW {
+        let tiles: Vec<[[u8; 8]; 8]> = (0..TILES_PER_ROW)
+            .map(|tile_col| {
+                let base = (tile_row * TILES_PER_ROW + tile_col) * 16;
+                let mut bytes = [0; 16];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = mmu.read_vram_bank(bank, (base + i) as u16);
+                }
+                decode_tile(&bytes)
+            })
+            .collect();
+        for y in 0..8 {
+            for tile in &tiles {
+                out.extend(tile[y].iter().map(|&shade| SHADE_CHARS[shade as usize]));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Number of sprites in OAM
+pub const SPRITE_COUNT: usize = 40;
+
+/// Table of all OAM entries for the `sprites` command. Reads go through
+/// the bus, so OAM shows as 0xFF while the PPU is scanning it.
+pub fn dump_sprites(mmu: &MMU) -> String {
+    let tall = mmu.read_byte(0xFF40) & 0x04 != 0;
+    let mut out = String::from(" #    Y    X  Tile  Attr  Flags\n");
+    for index in 0..SPRITE_COUNT {
+        let base = 0xFE00 + index as u16 * 4;
+        let bytes = [0, 1, 2, 3].map(|i| mmu.read_byte(base + i));
+        let sprite = OamEntry::from_bytes(index as u8, bytes);
+
+        let mut flags = Vec::new();
+        if sprite.on_screen(tall) {
+            flags.push("visible");
+        }
+        if sprite.behind_background() {
+            flags.push("behind-bg");
+        }
+        if sprite.x_flip() {
+            flags.push("x-flip");
+        }
+        if sprite.y_flip() {
+            flags.push("y-flip");
+        }
+        if sprite.dmg_palette() == 1 {
+            flags.push("obp1");
+        }
+        out.push_str(&format!(
+            "{:2}  {:3}  {:3}  0x{:02X}  0x{:02X}  {}\n",
+            index,
+            sprite.y,
+            sprite.x,
+            sprite.tile,
+            sprite.attrs,
+            flags.join(" ")
+        ));
+    }
+    out
+}
+
+fn on_off(set: bool) -> &'static str {
+    if set { "on" } else { "off" }
+}
+
+/// Decode LCDC (0xFF40) for the `lcd` command, one flag per line
+pub fn describe_lcdc(value: u8) -> String {
+    let bit = |n: u8| value & (1 << n) != 0;
+    let map = |set: bool| if set { "0x9C00" } else { "0x9800" };
+    format!(
+        "LCD: {}\nWindow tile map: {}\nWindow: {}\nTile data: {}\nBG tile map: {}\nSprite size: {}\nSprites: {}\nBG: {}\n",
+        on_off(bit(7)),
+        map(bit(6)),
+        on_off(bit(5)),
+        if bit(4) { "0x8000 (unsigned)" } else { "0x8800 (signed)" },
+        map(bit(3)),
+        if bit(2) { "8x16" } else { "8x8" },
+        on_off(bit(1)),
+        on_off(bit(0)),
+    )
+}
+
+/// Decode STAT (0xFF41) for the `lcd` command, one field per line
+pub fn describe_stat(value: u8) -> String {
+    let mode = match value & 0x03 {
+        0 => "0 (HBlank)",
+        1 => "1 (VBlank)",
+        2 => "2 (OAM scan)",
+        _ => "3 (Drawing)",
+    };
+    let sources = [(6, "LYC"), (5, "OAM"), (4, "VBlank"), (3, "HBlank")];
+    let enabled: Vec<&str> = sources
+        .iter()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!(
+        "Mode: {}\nLY=LYC: {}\nSTAT interrupts: {}\n",
+        mode,
+        if value & 0x04 != 0 { "yes" } else { "no" },
+        if enabled.is_empty() { "none".to_string() } else { enabled.join(" ") },
+    )
+}
+
+/// Write a register by name for the `set` command. Accepts the 8-bit
+/// registers (a, f, b, c, d, e, h, l), the pairs (af, bc, de, hl) and sp/pc.
+/// The low nibble of F doesn't exist in hardware and is cleared.
+pub fn set_register(cpu: &mut CPU, name: &str, value: u16) -> Result<(), String> {
+    let register = Register::parse(name).ok_or_else(|| format!("Unknown register: {}", name.to_lowercase()))?;
+    let byte = || u8::try_from(value).map_err(|_| format!("0x{:X} doesn't fit in 8-bit register {}", value, register));
+    match register {
+        Register::A => cpu.a = byte()?,
+        Register::F => cpu.f = byte()? & 0xF0,
+        Register::B => cpu.b = byte()?,
+        Register::C => cpu.c = byte()?,
+        Register::D => cpu.d = byte()?,
+        Register::E => cpu.e = byte()?,
+        Register::H => cpu.h = byte()?,
+        Register::L => cpu.l = byte()?,
+        Register::AF => (cpu.a, cpu.f) = ((value >> 8) as u8, value as u8 & 0xF0),
+        Register::BC => (cpu.b, cpu.c) = ((value >> 8) as u8, value as u8),
+        Register::DE => (cpu.d, cpu.e) = ((value >> 8) as u8, value as u8),
+        Register::HL => (cpu.h, cpu.l) = ((value >> 8) as u8, value as u8),
+        Register::SP => cpu.sp = value,
+        Register::PC => cpu.pc = value,
+    }
+    Ok(())
+}
+
+/// Shadow call stack for the `bt` command, innermost frame first
+pub fn backtrace(cpu: &CPU, symbols: &SymbolTable) -> String {
+    let mut out = String::new();
+    for (depth, frame) in cpu.call_stack.iter().rev().enumerate() {
+        out.push_str(&format!("#{} {} called from {}\n", depth, symbols.describe(frame.target), symbols.describe(frame.caller)));
+    }
+    if cpu.call_stack.is_empty() {
+        out.push_str("No frames\n");
+    }
+    if let Some(pc) = cpu.unbalanced_ret {
+        out.push_str(&format!("Warning: RET at 0x{:04X} returned with an empty call stack\n", pc));
+    }
+    out
+}
+
+/// Most frequently executed opcodes listed by the `stats` command
+const STATS_TOP_OPCODES: usize = 10;
+
+/// Execution statistics for the `stats` command
+pub fn stats(cpu: &CPU) -> String {
+    let mut out = format!("Instructions: {}\nCycles: {}\n", cpu.instructions, cpu.total_cycles);
+    let mut counts: Vec<(usize, u64)> = cpu.opcode_counts.iter().copied().enumerate().filter(|&(_, count)| count > 0).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    for (opcode, count) in counts.iter().take(STATS_TOP_OPCODES) {
+        let percent = *count as f64 * 100.0 / cpu.instructions as f64;
+        out.push_str(&format!("  {:02X}: {} ({:.1}%)\n", opcode, count, percent));
+    }
+    out
+}
+
+/// Addresses listed by the `hotspots` command
+const HOTSPOT_COUNT: usize = 16;
+
+/// The most accessed addresses since access counting was turned on.
+/// The debugger's own reads (tile dumps, disassembly) count too.
+pub fn hotspots(mmu: &MMU, symbols: &SymbolTable) -> String {
+    let Some(counts) = mmu.access_counts() else {
+        return "Access counting is off (enable it with `hotspots on`)\n".to_string();
+    };
+    let hot = counts.hottest(HOTSPOT_COUNT);
+    if hot.is_empty() {
+        return "No accesses yet\n".to_string();
+    }
+    let mut out = String::from("Address        Reads     Writes\n");
+    for (address, reads, writes) in hot {
+        out.push_str(&format!("{:<12} {:>7} {:>10}\n", symbols.describe(address), reads, writes));
+    }
+    out
+}
+
+/// Detects a likely hang: PC staying within a few bytes of the same
+/// address for `threshold` consecutive instructions, as in `JR $-2` or a
+/// tight polling loop that never sees what it waits for.
+pub struct HangDetector {
+    pub threshold: u64,  // Instructions before reporting a hang (0 disables)
+    anchor: u16,         // Start of the range PC is currently looping in
+    steps: u64,          // Consecutive instructions spent near the anchor
+}
+
+impl HangDetector {
+    /// How far PC may wander from the anchor and still count as the same loop
+    pub const WINDOW: u16 = 8;
+    pub const DEFAULT_THRESHOLD: u64 = 100_000;
+
+    pub fn new(threshold: u64) -> HangDetector {
+        HangDetector { threshold, anchor: 0, steps: 0 }
+    }
+
+    /// Record the PC after an instruction. Returns the looping address once
+    /// the threshold is reached, then starts counting again.
+    pub fn observe(&mut self, pc: u16) -> Option<u16> {
+        if self.threshold == 0 {
+            return None;
+        }
+        if pc.wrapping_sub(self.anchor) > Self::WINDOW && self.anchor.wrapping_sub(pc) > Self::WINDOW {
+            self.anchor = pc;
+            self.steps = 1;
+            return None;
+        }
+        self.steps += 1;
+        if self.steps >= self.threshold {
+            self.steps = 0;
+            return Some(self.anchor);
+        }
+        None
+    }
+
+    /// Forget the current loop, e.g. after the user resumes execution
+    pub fn reset(&mut self) {
+        self.steps = 0;
+    }
+}
+
+impl Default for HangDetector {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_THRESHOLD)
+    }
+}
+
+/// Opcodes that stop execution wherever they are about to run (`bop`),
+/// e.g. 0x76 HALT, or 0xFF RST 38H: the fill byte that runaway code
+/// usually ends up executing.
+#[derive(Debug, Clone, Default)]
+pub struct OpcodeBreaks {
+    opcodes: BTreeSet<u8>,
+}
+
+impl OpcodeBreaks {
+    pub fn new() -> OpcodeBreaks {
+        OpcodeBreaks::default()
+    }
+
+    /// Returns false if `opcode` was already watched
+    pub fn insert(&mut self, opcode: u8) -> bool {
+        self.opcodes.insert(opcode)
+    }
+
+    pub fn remove(&mut self, opcode: u8) -> bool {
+        self.opcodes.remove(&opcode)
+    }
+
+    pub fn opcodes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.opcodes.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.opcodes.is_empty()
+    }
+
+    /// The watched opcode at `pc`, if the next instruction is one
+    pub fn check(&self, mmu: &MMU, pc: u16) -> Option<u8> {
+        if self.opcodes.is_empty() {
+            return None;
+        }
+        let opcode = mmu.read_byte(pc);
+        self.opcodes.contains(&opcode).then_some(opcode)
+    }
+}
+
+/// Labels loaded from an RGBDS-style .sym file (`BANK:ADDR LABEL` per line,
+/// `;` starts a comment). Banks are not tracked: when banked code reuses an
+/// address, the first label wins.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    labels: HashMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn load(path: &str) -> std::io::Result<SymbolTable> {
+        Ok(SymbolTable::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parse .sym contents, skipping lines that don't hold a symbol
+    pub fn parse(contents: &str) -> SymbolTable {
+        let mut symbols = SymbolTable::default();
+        for line in contents.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let mut parts = line.split_whitespace();
+            let (Some(location), Some(name)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let Some((_bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            if let Ok(address) = u16::from_str_radix(address, 16) {
+                symbols.insert(address, name);
+            }
+        }
+        symbols
+    }
+
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.labels.entry(address).or_insert_with(|| name.to_string());
+        self.addresses.entry(name.to_string()).or_insert(address);
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// A label or hex address typed by the user
+    pub fn resolve(&self, input: &str) -> Option<u16> {
+        self.address(input.trim()).or_else(|| parse_hex(input))
+    }
+
+    /// `0x0150 (Main)`, or just `0x0150` when the address has no label
+    pub fn describe(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => format!("0x{:04X} ({})", address, label),
+            None => format!("0x{:04X}", address),
+        }
+    }
+}
+
+/// Parse a hex number with or without a 0x prefix
+pub fn parse_hex(input: &str) -> Option<u16> {
+    let cleaned = input.trim();
+    let digits = cleaned.strip_prefix("0x").or_else(|| cleaned.strip_prefix("0X")).unwrap_or(cleaned);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Where `so` (step over) should stop: the instruction after the CALL or
+/// RST at `pc`. None for any other instruction, which is simply stepped.
+pub fn step_over_target(mmu: &MMU, pc: u16) -> Option<u16> {
+    let opcode = mmu.read_byte(pc);
+    match opcode {
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC | 0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+            Some(pc.wrapping_add(opcode_length(opcode) as u16))
+        },
+        _ => None,
+    }
+}
+
+/// Tracks a `finish` (run to return): done once a RET, RET cc or RETI
+/// leaves SP above where it was when the command was issued, i.e. the
+/// current subroutine's frame has been popped.
+#[derive(Debug, Clone, Copy)]
+pub struct Finish {
+    sp: u16,
+}
+
+impl Finish {
+    pub fn new(cpu: &CPU) -> Finish {
+        Finish { sp: cpu.sp }
+    }
+
+    /// Call after each step with the opcode that was at PC before it
+    pub fn returned(&self, opcode: u8, cpu: &CPU) -> bool {
+        matches!(opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9) && cpu.sp > self.sp
+    }
+}
+
+/// Disassemble the instruction at `address`. Returns its text and length in
+/// bytes. Opcodes the CPU doesn't implement yet show as `DB $xx`.
+pub fn disassemble(mmu: &MMU, address: u16) -> (String, u16) {
+    let opcode = mmu.read_byte(address);
+    let n = mmu.read_byte(address.wrapping_add(1));
+    let nn = u16::from_le_bytes([n, mmu.read_byte(address.wrapping_add(2))]);
+    // Relative jump target, from the end of the 2-byte instruction
+    let target = address.wrapping_add(2).wrapping_add(n as i8 as u16);
+
+    let text = match opcode {
+        0x00 => "NOP".to_string(),
+        0x04 => "INC B".to_string(),
+        0x05 => "DEC B".to_string(),
+        0x06 => format!("LD B,${:02X}", n),
+        0x08 => format!("LD (${:04X}),SP", nn),
+        0x0C => "INC C".to_string(),
+        0x0D => "DEC C".to_string(),
+        0x0E => format!("LD C,${:02X}", n),
+        0x10 => "STOP".to_string(),
+        0x14 => "INC D".to_string(),
+        0x15 => "DEC D".to_string(),
+        0x18 => format!("JR ${:04X}", target),
+        0x1F => "RRA".to_string(),
+        0x20 => format!("JR NZ,${:04X}", target),
+        0x21 => format!("LD HL,${:04X}", nn),
+        0x31 => format!("LD SP,${:04X}", nn),
+        0x32 => "LD (HL-),A".to_string(),
+        0x3E => format!("LD A,${:02X}", n),
+        0x76 => "HALT".to_string(),
+        0x77 => "LD (HL),A".to_string(),
+        0x7A => "LD A,D".to_string(),
+        0x89 => "ADC A,C".to_string(),
+        0xAF => "XOR A".to_string(),
+        0xC3 => format!("JP ${:04X}", nn),
+        0xC9 => "RET".to_string(),
+        0xCB => disassemble_cb(n),
+        0xCD => format!("CALL ${:04X}", nn),
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => format!("RST ${:02X}", opcode & 0x38),
+        0xD9 => "RETI".to_string(),
+        0xD6 => format!("SUB ${:02X}", n),
+        0xDE => format!("SBC A,${:02X}", n),
+        0xE0 => format!("LDH (${:02X}),A", n),
+        0xE2 => "LD (C),A".to_string(),
+        0xE8 => format!("ADD SP,{}", n as i8),
+        0xEA => format!("LD (${:04X}),A", nn),
+        0xF0 => format!("LDH A,(${:02X})", n),
+        0xF2 => "LD A,(C)".to_string(),
+        0xF3 => "DI".to_string(),
+        0xF8 => format!("LD HL,SP{:+}", n as i8),
+        0xF9 => "LD SP,HL".to_string(),
+        0xFB => "EI".to_string(),
+        0xFE => format!("CP ${:02X}", n),
+        _ => return (format!("DB ${:02X}", opcode), 1),
+    };
+    (text, opcode_length(opcode) as u16)
+}
+
+/// CB-prefixed opcodes follow a regular layout: operation in bits 3-7, register in bits 0-2
+fn disassemble_cb(opcode: u8) -> String {
+    const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+    const SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+    let register = REGISTERS[(opcode & 0x07) as usize];
+    let bit = (opcode >> 3) & 0x07;
+    match opcode >> 6 {
+        0 => format!("{} {}", SHIFTS[bit as usize], register),
+        1 => format!("BIT {},{}", bit, register),
+        2 => format!("RES {},{}", bit, register),
+        _ => format!("SET {},{}", bit, register),
+    }
+}
+
+/// Instructions disassembled in a crash dump, starting at the crash PC
+const CRASH_DISASSEMBLY_LINES: usize = 4;
+
+/// Bytes shown either side of the crash PC in a crash dump
+const CRASH_MEMORY_CONTEXT: u16 = 8;
+
+/// Plain-text crash report: the error, registers, the bytes around the
+/// faulting PC and a short disassembly from there. The `PC:` line is what
+/// the `t` command reads back.
+pub fn crash_dump(error: &CPUError, cpu: &CPU, mmu: &MMU) -> String {
+    let pc = error.pc().unwrap_or(cpu.pc);
+    let flag = |bit: u8, name: char| if cpu.f & (1 << bit) != 0 { name } else { '-' };
+    let mut out = String::from("GBRust crash dump\n");
+    out.push_str(&format!("Error: {}\n", error));
+    out.push_str(&format!("PC: 0x{:04X}\n", pc));
+    out.push_str(&format!(
+        "AF: 0x{:02X}{:02X}  BC: 0x{:02X}{:02X}  DE: 0x{:02X}{:02X}  HL: 0x{:02X}{:02X}\n",
+        cpu.a, cpu.f, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l
+    ));
+    out.push_str(&format!("SP: 0x{:04X}  Flags: {}{}{}{}\n", cpu.sp, flag(7, 'Z'), flag(6, 'N'), flag(5, 'H'), flag(4, 'C')));
+
+    out.push_str(&format!("Opcode: 0x{:02X}\n", mmu.read_byte(pc)));
+
+    let start = pc.saturating_sub(CRASH_MEMORY_CONTEXT);
+    let end = pc.saturating_add(CRASH_MEMORY_CONTEXT);
+    out.push_str(&format!("Memory 0x{:04X}-0x{:04X}:", start, end));
+    for address in start..=end {
+        let byte = mmu.read_byte(address);
+        if address == pc {
+            out.push_str(&format!(" [{:02X}]", byte));
+        } else {
+            out.push_str(&format!(" {:02X}", byte));
+        }
+    }
+    out.push('\n');
+
+    out.push_str("Disassembly:\n");
+    let mut address = pc;
+    for _ in 0..CRASH_DISASSEMBLY_LINES {
+        let (text, length) = disassemble(mmu, address);
+        let marker = if address == pc { ">" } else { " " };
+        out.push_str(&format!("{} 0x{:04X}: {}\n", marker, address, text));
+        address = address.wrapping_add(length);
+    }
+    out
+}
+
+/// Write `crash_dump` to `path`
+pub fn write_crash_dump(path: &str, error: &CPUError, cpu: &CPU, mmu: &MMU) -> std::io::Result<()> {
+    std::fs::write(path, crash_dump(error, cpu, mmu))
+}
+
+/// Crash PC from a dump written by `crash_dump`. Also accepts the older
+/// format that held just the PC in hex.
+pub fn parse_crash_pc(content: &str) -> Option<u16> {
+    match content.lines().find_map(|line| line.strip_prefix("PC:")) {
+        Some(pc) => parse_hex(pc),
+        None => parse_hex(content),
+    }
+}
+
+/// A register the debugger can read, write and test in breakpoint conditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A, F, B, C, D, E, H, L,
+    AF, BC, DE, HL,
+    SP, PC,
+}
+
+impl Register {
+    /// Look up a register by name, ignoring case
+    pub fn parse(name: &str) -> Option<Register> {
+        let register = match name.trim().to_lowercase().as_str() {
+            "a" => Register::A,
+            "f" => Register::F,
+            "b" => Register::B,
+            "c" => Register::C,
+            "d" => Register::D,
+            "e" => Register::E,
+            "h" => Register::H,
+            "l" => Register::L,
+            "af" => Register::AF,
+            "bc" => Register::BC,
+            "de" => Register::DE,
+            "hl" => Register::HL,
+            "sp" => Register::SP,
+            "pc" => Register::PC,
+            _ => return None,
+        };
+        Some(register)
+    }
+
+    /// Lowercase name, as accepted by `parse`
+    pub fn name(self) -> &'static str {
+        match self {
+            Register::A => "a",
+            Register::F => "f",
+            Register::B => "b",
+            Register::C => "c",
+            Register::D => "d",
+            Register::E => "e",
+            Register::H => "h",
+            Register::L => "l",
+            Register::AF => "af",
+            Register::BC => "bc",
+            Register::DE => "de",
+            Register::HL => "hl",
+            Register::SP => "sp",
+            Register::PC => "pc",
+        }
+    }
+
+    /// Current value; 8-bit registers are zero-extended
+    pub fn get(self, cpu: &CPU) -> u16 {
+        let pair = |high: u8, low: u8| ((high as u16) << 8) | low as u16;
+        match self {
+            Register::A => cpu.a as u16,
+            Register::F => cpu.f as u16,
+            Register::B => cpu.b as u16,
+            Register::C => cpu.c as u16,
+            Register::D => cpu.d as u16,
+            Register::E => cpu.e as u16,
+            Register::H => cpu.h as u16,
+            Register::L => cpu.l as u16,
+            Register::AF => pair(cpu.a, cpu.f),
+            Register::BC => pair(cpu.b, cpu.c),
+            Register::DE => pair(cpu.d, cpu.e),
+            Register::HL => pair(cpu.h, cpu.l),
+            Register::SP => cpu.sp,
+            Register::PC => cpu.pc,
+        }
+    }
+}
+
+impl std::fmt::Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Breakpoint condition: a register compared for equality with a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub value: u16,
+}
+
+/// Halt when PC reaches `address`, if `condition` (when present) holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    /// Parse the arguments of the `b` command: `<addr>` or `<addr> if <reg>==<value>`,
+    /// where the address may also be a label from `symbols`
+    pub fn parse(input: &str, symbols: &SymbolTable) -> Result<Breakpoint, String> {
+        let (address, condition) = match input.split_once(" if ") {
+            Some((address, condition)) => (address, Some(condition)),
+            None => (input, None),
+        };
+        let address = symbols.resolve(address).ok_or_else(|| format!("Invalid address: {}", address.trim()))?;
+
+        let condition = match condition {
+            Some(condition) => {
+                let (register, value) = condition
+                    .split_once("==")
+                    .ok_or_else(|| format!("Expected <reg>==<value>, got: {}", condition.trim()))?;
+                let register = Register::parse(register).ok_or_else(|| format!("Unknown register: {}", register.trim()))?;
+                let value = parse_hex(value).ok_or_else(|| format!("Invalid value: {}", value.trim()))?;
+                Some(Condition { register, value })
+            },
+            None => None,
+        };
+        Ok(Breakpoint { address, condition })
+    }
+
+    /// True if execution should halt with the CPU in this state
+    pub fn hit(&self, cpu: &CPU) -> bool {
+        cpu.pc == self.address
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.register.get(cpu) == condition.value)
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "0x{:04X}", self.address)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}==0x{:X}", condition.register, condition.value)?;
+        }
+        Ok(())
+    }
+}
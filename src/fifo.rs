@@ -0,0 +1,298 @@
+//! Pixel FIFO renderer. Models the background/window fetcher, the
+//! background and sprite FIFOs and one pixel shifted out per dot, so
+//! register writes made during mode 3 land on the pixels drawn after
+//! them. Used under Accuracy::Accurate; the scanline renderer in ppu.rs
+//! draws whole lines at once under Accuracy::Fast, sharing `mix` with it.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::ppu::{decode_tile_row, tile_data_address, LcdRegs, OamEntry, SCREEN_WIDTH};
+
+/// Dots the fetcher spends reading one sprite's tile row
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+/// A pixel waiting in the background FIFO
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BgPixel {
+    pub(crate) color: u8,
+    pub(crate) attrs: u8,  // CGB map attributes (palette in bits 0-2, priority in bit 7)
+}
+
+/// A pixel waiting in the sprite FIFO. Color 0 is a transparent slot.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ObjPixel {
+    pub(crate) color: u8,
+    pub(crate) attrs: u8,  // OAM attributes
+    pub(crate) index: u8,  // OAM index, which decides overlaps on CGB
+}
+
+impl ObjPixel {
+    /// Whether `sprite`'s opaque pixel `color` replaces this one. The first
+    /// opaque pixel wins on DMG; on CGB the lower OAM index wins.
+    pub(crate) fn loses_to(&self, sprite: &OamEntry, color: u8, cgb: bool) -> bool {
+        color != 0 && (self.color == 0 || (cgb && sprite.index < self.index))
+    }
+}
+
+/// What the FIFO shifted out for one screen pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FifoPixel {
+    /// Background switched off by LCDC bit 0 on DMG
+    Blank,
+    /// Background or window color (0-3); `palette` is the CGB palette
+    Background { color: u8, palette: u8 },
+    /// Sprite color (1-3); `palette` is 0/1 for OBP0/OBP1 on DMG or the CGB palette
+    Sprite { color: u8, palette: u8 },
+}
+
+/// Fetcher steps. The three reads take two dots each; Push waits until
+/// the background FIFO is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum FetchStep {
+    #[default]
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PixelFifo {
+    line: u8,
+    active: bool,
+    dots: u32,  // Dots spent in mode 3 on this line
+    x: u8,  // Next screen pixel
+    discard: u8,  // Pixels still to drop for SCX fine scroll
+    bg: VecDeque<BgPixel>,
+    obj: VecDeque<ObjPixel>,
+    sprites: Vec<OamEntry>,  // Sprites on this line not fetched yet, in fetch order
+    sprite_fetch: Option<(OamEntry, u8)>,  // Sprite being fetched and dots left
+    // Fetcher
+    step: FetchStep,
+    second_dot: bool,
+    fetch_x: u8,  // Tiles pushed so far on this line (or since the window started)
+    tile: u8,
+    tile_attrs: u8,
+    tile_row: u8,
+    low: u8,
+    high: u8,
+    dummy_fetch: bool,  // The first fetch of each line is thrown away
+    // Window
+    window: bool,  // Fetching window tiles
+    window_triggered: bool,  // LY matched WY this frame
+    window_line: u8,  // Window rows drawn this frame
+}
+
+impl PixelFifo {
+    pub fn new() -> PixelFifo {
+        PixelFifo::default()
+    }
+
+    /// Begin mode 3 for `line`. `sprites` are the OAM scan results in the
+    /// order select_sprites returns them.
+    pub fn start_line(&mut self, line: u8, sprites: Vec<OamEntry>, regs: LcdRegs) {
+        if line == 0 {
+            self.window_triggered = false;
+            self.window_line = 0;
+        }
+        if regs.lcdc & 0x20 != 0 && regs.wy == line {
+            self.window_triggered = true;
+        }
+
+        self.line = line;
+        self.active = true;
+        self.dots = 0;
+        self.x = 0;
+        self.discard = regs.scx & 0x07;
+        self.bg.clear();
+        self.obj.clear();
+        self.sprites = sprites;
+        self.sprite_fetch = None;
+        self.step = FetchStep::Tile;
+        self.second_dot = false;
+        self.fetch_x = 0;
+        self.dummy_fetch = true;
+        self.window = false;
+    }
+
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+
+    /// True until all SCREEN_WIDTH pixels of the line are out
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Dots spent in mode 3 so far (the mode 3 length once the line is done)
+    pub fn dots(&self) -> u32 {
+        self.dots
+    }
+
+    /// Advance one dot, returning the pixel shifted out (and its screen X), if any
+    pub fn tick(&mut self, vram: &[[u8; 0x2000]; 2], regs: LcdRegs, cgb: bool) -> Option<(u8, FifoPixel)> {
+        if !self.active {
+            return None;
+        }
+        self.dots += 1;
+
+        if let Some((sprite, left)) = self.sprite_fetch {
+            if left > 1 {
+                self.sprite_fetch = Some((sprite, left - 1));
+            } else {
+                self.sprite_fetch = None;
+                self.mix_sprite(sprite, vram, regs, cgb);
+            }
+            return None;
+        }
+
+        // A sprite starting at this pixel stops the shifter; it's fetched
+        // once the background FIFO has pixels, restarting the tile fetch
+        if self.discard == 0 && regs.lcdc & 0x02 != 0 && self.sprites.first().is_some_and(|s| s.x <= self.x + 8) {
+            if self.bg.is_empty() {
+                self.fetch(vram, regs, cgb);
+            } else {
+                let sprite = self.sprites.remove(0);
+                self.sprite_fetch = Some((sprite, SPRITE_FETCH_DOTS));
+                self.step = FetchStep::Tile;
+                self.second_dot = false;
+            }
+            return None;
+        }
+
+        let pixel = self.shift_out(regs, cgb);
+        self.fetch(vram, regs, cgb);
+        pixel
+    }
+
+    fn shift_out(&mut self, regs: LcdRegs, cgb: bool) -> Option<(u8, FifoPixel)> {
+        // Reaching WX switches the fetcher to the window, dropping the background pixels
+        if !self.window && self.window_triggered && regs.lcdc & 0x20 != 0 && self.discard == 0 && self.x + 7 >= regs.wx {
+            self.window = true;
+            self.bg.clear();
+            self.fetch_x = 0;
+            self.step = FetchStep::Tile;
+            self.second_dot = false;
+            return None;
+        }
+
+        let bg = self.bg.pop_front()?;
+        if self.discard > 0 {
+            self.discard -= 1;
+            return None;
+        }
+        let obj = self.obj.pop_front();
+        let x = self.x;
+        self.x += 1;
+        if self.x as usize == SCREEN_WIDTH {
+            self.active = false;
+            if self.window {
+                self.window_line = self.window_line.wrapping_add(1);
+            }
+        }
+        Some((x, mix(bg, obj, regs, cgb)))
+    }
+
+    fn fetch(&mut self, vram: &[[u8; 0x2000]; 2], regs: LcdRegs, cgb: bool) {
+        if self.step != FetchStep::Push {
+            if !self.second_dot {
+                self.second_dot = true;
+                return;
+            }
+            self.second_dot = false;
+        }
+
+        match self.step {
+            FetchStep::Tile => {
+                // SCX bits 3-7 and SCY are read at every tile, so writes show up mid-line
+                let (map_bit, col, y) = if self.window {
+                    (0x40, self.fetch_x, self.window_line)
+                } else {
+                    (0x08, ((regs.scx / 8).wrapping_add(self.fetch_x)) & 0x1F, self.line.wrapping_add(regs.scy))
+                };
+                let map_base: usize = if regs.lcdc & map_bit != 0 { 0x1C00 } else { 0x1800 };
+                let map_addr = map_base + (y as usize / 8) * 32 + (col as usize & 0x1F);
+                self.tile = vram[0][map_addr];
+                self.tile_attrs = if cgb { vram[1][map_addr] } else { 0 };
+                self.tile_row = if self.tile_attrs & 0x40 != 0 { 7 - y % 8 } else { y % 8 };
+                self.step = FetchStep::DataLow;
+            },
+            FetchStep::DataLow => {
+                self.low = vram[self.tile_bank()][self.tile_data(regs)];
+                self.step = FetchStep::DataHigh;
+            },
+            FetchStep::DataHigh => {
+                self.high = vram[self.tile_bank()][self.tile_data(regs) + 1];
+                self.step = FetchStep::Push;
+                self.push();
+            },
+            FetchStep::Push => self.push(),
+        }
+    }
+
+    fn push(&mut self) {
+        if !self.bg.is_empty() {
+            return;
+        }
+        self.step = FetchStep::Tile;
+        if self.dummy_fetch {
+            self.dummy_fetch = false;
+            return;
+        }
+        let mut row = decode_tile_row(self.low, self.high);
+        if self.tile_attrs & 0x20 != 0 {
+            row.reverse();
+        }
+        let attrs = self.tile_attrs;
+        self.bg.extend(row.iter().map(|&color| BgPixel { color, attrs }));
+        self.fetch_x = self.fetch_x.wrapping_add(1);
+    }
+
+    fn tile_bank(&self) -> usize {
+        ((self.tile_attrs >> 3) & 0x01) as usize
+    }
+
+    fn tile_data(&self, regs: LcdRegs) -> usize {
+        tile_data_address(regs.lcdc, self.tile) + self.tile_row as usize * 2
+    }
+
+    /// Merge `sprite`'s row into the sprite FIFO. Opaque pixels already
+    /// there win on DMG; on CGB the lower OAM index wins.
+    fn mix_sprite(&mut self, sprite: OamEntry, vram: &[[u8; 0x2000]; 2], regs: LcdRegs, cgb: bool) {
+        let colors = sprite.row(self.line, regs.lcdc & 0x04 != 0, vram, cgb);
+
+        // Columns left of the current pixel (off the left edge) are already past
+        let skip = (self.x + 8 - sprite.x) as usize;
+        while self.obj.len() < 8 {
+            self.obj.push_back(ObjPixel::default());
+        }
+        for (slot, &color) in self.obj.iter_mut().zip(colors.iter().skip(skip)) {
+            if slot.loses_to(&sprite, color, cgb) {
+                *slot = ObjPixel { color, attrs: sprite.attrs, index: sprite.index };
+            }
+        }
+    }
+}
+
+/// Pick between the background and sprite pixel for one screen position
+pub(crate) fn mix(bg: BgPixel, obj: Option<ObjPixel>, regs: LcdRegs, cgb: bool) -> FifoPixel {
+    // On CGB, LCDC bit 0 instead takes priority away from the background
+    let bg_enabled = cgb || regs.lcdc & 0x01 != 0;
+    let bg_color = if bg_enabled { bg.color } else { 0 };
+
+    if let Some(obj) = obj.filter(|obj| obj.color != 0 && regs.lcdc & 0x02 != 0) {
+        let bg_priority = !(cgb && regs.lcdc & 0x01 == 0);
+        let behind = obj.attrs & 0x80 != 0 || (cgb && bg.attrs & 0x80 != 0);
+        if !bg_priority || bg_color == 0 || !behind {
+            let palette = if cgb { obj.attrs & 0x07 } else { (obj.attrs >> 4) & 0x01 };
+            return FifoPixel::Sprite { color: obj.color, palette };
+        }
+    }
+
+    if bg_enabled {
+        FifoPixel::Background { color: bg.color, palette: bg.attrs & 0x07 }
+    } else {
+        FifoPixel::Blank
+    }
+}
@@ -0,0 +1,92 @@
+use crate::joypad::JoypadState;
+
+/// Host side of the emulator: where finished frames and audio go and where
+/// input comes from. `Machine::run` drives any implementation with the same loop.
+pub trait Frontend {
+    /// Show a finished frame (SCREEN_WIDTH x SCREEN_HEIGHT RGBA pixels)
+    fn present(&mut self, framebuffer: &[u8]);
+
+    /// Buttons to hold for the next frame
+    fn poll_input(&mut self) -> JoypadState;
+
+    /// Interleaved stereo samples produced during the last frame
+    fn queue_audio(&mut self, samples: &[f32]);
+}
+
+/// Headless sink: discards output and presses nothing
+#[derive(Debug, Default)]
+pub struct NullFrontend;
+
+impl Frontend for NullFrontend {
+    fn present(&mut self, _framebuffer: &[u8]) {}
+
+    fn poll_input(&mut self) -> JoypadState {
+        JoypadState::default()
+    }
+
+    fn queue_audio(&mut self, _samples: &[f32]) {}
+}
+
+/// Terminal display: draws every `interval`th frame to stdout as ASCII art,
+/// one character per 2x4 block of pixels. Input is whatever `held` is set to.
+#[cfg(feature = "std")]
+pub struct TextFrontend {
+    pub interval: u32,
+    pub held: JoypadState,
+    frames: u32,
+}
+
+#[cfg(feature = "std")]
+impl TextFrontend {
+    pub fn new(interval: u32) -> TextFrontend {
+        TextFrontend {
+            interval: interval.max(1),
+            held: JoypadState::default(),
+            frames: 0,
+        }
+    }
+
+    /// Frames presented so far
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for TextFrontend {
+    fn default() -> Self {
+        Self::new(60)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Frontend for TextFrontend {
+    fn present(&mut self, framebuffer: &[u8]) {
+        use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+        use std::fmt::Write;
+
+        self.frames += 1;
+        if !self.frames.is_multiple_of(self.interval) {
+            return;
+        }
+
+        // Darkest to lightest by green channel brightness
+        const RAMP: [char; 4] = ['#', 'o', '.', ' '];
+        let mut out = String::new();
+        for y in (0..SCREEN_HEIGHT).step_by(4) {
+            for x in (0..SCREEN_WIDTH).step_by(2) {
+                let green = framebuffer[(y * SCREEN_WIDTH + x) * 4 + 1];
+                out.push(RAMP[green as usize * RAMP.len() / 256]);
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "Frame {}", self.frames);
+        print!("{}", out);
+    }
+
+    fn poll_input(&mut self) -> JoypadState {
+        self.held
+    }
+
+    fn queue_audio(&mut self, _samples: &[f32]) {}
+}
@@ -0,0 +1,111 @@
+// Joypad register address
+pub const P1: u16 = 0xFF00;
+
+/// Game Boy buttons. The discriminant is the button's index for frontends
+/// that pass buttons as numbers (bits 0-3 directions, 4-7 actions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right = 0,
+    Left = 1,
+    Up = 2,
+    Down = 3,
+    A = 4,
+    B = 5,
+    Select = 6,
+    Start = 7,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    pub fn from_index(index: u8) -> Option<Button> {
+        Button::ALL.get(index as usize).copied()
+    }
+}
+
+/// Snapshot of which buttons are held, one bit per Button index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JoypadState {
+    pub pressed: u8,
+}
+
+impl JoypadState {
+    /// The same state with `button` pressed or released
+    pub fn with(self, button: Button, pressed: bool) -> JoypadState {
+        let bit = 1 << button as u8;
+        JoypadState {
+            pressed: if pressed { self.pressed | bit } else { self.pressed & !bit },
+        }
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & (1 << button as u8) != 0
+    }
+}
+
+/// P1/JOYP: bits 4-5 select the direction or action row (active low),
+/// bits 0-3 read the selected buttons (0 = pressed).
+#[derive(Clone)]
+pub struct Joypad {
+    select: u8,    // P1 bits 4-5 as last written
+    pressed: u8,   // One bit per Button index
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: 0x30,
+            pressed: 0,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        let mut low = 0x0F;
+        if self.select & 0x10 == 0 {
+            low &= !(self.pressed & 0x0F);
+        }
+        if self.select & 0x20 == 0 {
+            low &= !(self.pressed >> 4);
+        }
+        0xC0 | self.select | low
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.select = value & 0x30;
+    }
+
+    /// Update a button. Returns true on a new press, which requests the joypad interrupt.
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let bit = 1 << button as u8;
+        let was_pressed = self.pressed & bit != 0;
+        if pressed {
+            self.pressed |= bit;
+        } else {
+            self.pressed &= !bit;
+        }
+        pressed && !was_pressed
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & (1 << button as u8) != 0
+    }
+
+    pub fn state(&self) -> JoypadState {
+        JoypadState { pressed: self.pressed }
+    }
+}
@@ -1,3 +1,52 @@
+// CPU/MMU follow the hardware naming used throughout the docs
+#![allow(clippy::upper_case_acronyms)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+// Without std there is no stdout: the core's logging compiles away.
+// Defined before the modules so they pick it up in place of std's.
+#[cfg(not(feature = "std"))]
+macro_rules! println {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "std"))]
+macro_rules! print {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+pub mod apu;
+pub mod audio;
+pub mod cheats;
+#[cfg(feature = "std")]
+pub mod cli;
 pub mod cpu;
-pub mod mmu;
\ No newline at end of file
+#[cfg(feature = "std")]
+pub mod debugger;
+pub mod fifo;
+pub mod frontend;
+pub mod joypad;
+pub mod licensee;
+#[cfg(feature = "std")]
+pub mod machine;
+pub mod mapper;
+pub mod mmu;
+#[cfg(feature = "std")]
+pub mod movie;
+#[cfg(feature = "std")]
+pub mod pacing;
+pub mod ppu;
+pub mod printer;
+#[cfg(feature = "std")]
+pub mod rewind;
+pub mod rng;
+pub mod serial;
+pub mod sgb;
+pub mod timer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
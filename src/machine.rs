@@ -0,0 +1,390 @@
+use image::{ColorType, ImageFormat};
+
+use crate::apu::Apu;
+use crate::audio::RECORDING_SAMPLE_RATE;
+use crate::cpu::{CPUError, CpuState, CPU};
+use crate::frontend::Frontend;
+use crate::mmu::{Accuracy, HardwareModel, MMU};
+use crate::movie::{InputPlayer, InputRecorder};
+use crate::pacing::FrameLimiter;
+use crate::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::rewind::RewindBuffer;
+use crate::timer::Timer;
+
+/// A complete Game Boy: the CPU and the bus it drives.
+///
+/// The MMU is the system bus and owns the memory-mapped peripherals (PPU,
+/// timer, APU). The machine lends it to the CPU for each step, and the CPU
+/// ticks the peripherals through it as each memory access happens, so
+/// every subsystem stays in lockstep with the instruction stream.
+pub struct Machine {
+    pub cpu: CPU,
+    mmu: MMU,
+    pub limiter: FrameLimiter,
+    rewind_buffer: Option<RewindBuffer>,
+    model: HardwareModel,
+    forced_model: Option<HardwareModel>,
+    recording_path: Option<String>,
+    input_recorder: Option<InputRecorder>,
+    input_player: Option<InputPlayer>,
+    on_frame: Option<FrameCallback>,
+    frame_ready: bool,  // Taken over from the PPU by step
+}
+
+/// Called with the finished framebuffer (RGBA, SCREEN_WIDTH x SCREEN_HEIGHT) on each VBlank
+pub type FrameCallback = Box<dyn FnMut(&[u8])>;
+
+/// Snapshot of everything the emulated hardware holds. The ROM image is
+/// shared with the machine; the framebuffer and host-side attachments
+/// (link cable, audio output, trace file) are not included.
+#[derive(Clone)]
+pub struct SaveState {
+    pub cpu: CpuState,
+    pub mmu: MMU,
+}
+
+impl SaveState {
+    /// Memory the snapshot takes up
+    pub fn size_bytes(&self) -> usize {
+        core::mem::size_of::<SaveState>() + self.mmu.heap_bytes()
+    }
+}
+
+/// Clock cycles per frame (154 scanlines of 456 cycles) at normal speed
+pub const CYCLES_PER_FRAME: u32 = 70_224;
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    /// A machine with an empty cartridge slot, in the post-boot state
+    pub fn new() -> Machine {
+        Machine::with_mmu(MMU::new())
+    }
+
+    /// A machine whose power-on RAM contents come from `seed`; the same
+    /// seed always gives the same run
+    pub fn new_seeded(seed: u64) -> Machine {
+        Machine::with_mmu(MMU::new_seeded(seed))
+    }
+
+    /// Build a machine around an already configured bus (ROM, boot ROM, ...).
+    /// The hardware model follows the cartridge's CGB flag.
+    pub fn with_mmu(mmu: MMU) -> Machine {
+        let mut machine = Machine {
+            cpu: CPU::new(),
+            mmu,
+            limiter: FrameLimiter::new(),
+            rewind_buffer: None,
+            model: HardwareModel::Dmg,
+            forced_model: None,
+            recording_path: None,
+            input_recorder: None,
+            input_player: None,
+            on_frame: None,
+            frame_ready: false,
+        };
+        machine.reset_model();
+        machine
+    }
+
+    /// Load a cartridge ROM and start from the post-boot state
+    pub fn from_rom(filename: &str) -> std::io::Result<Machine> {
+        let mut mmu = MMU::new();
+        mmu.load_rom(filename)?;
+        Ok(Machine::with_mmu(mmu))
+    }
+
+    /// Load a cartridge ROM and a boot ROM, starting from power-on so the
+    /// boot sequence runs before the game
+    pub fn from_rom_with_boot(filename: &str, boot_rom: &str) -> std::io::Result<Machine> {
+        let mut mmu = MMU::new();
+        mmu.load_rom(filename)?;
+        mmu.load_boot_rom(boot_rom)?;
+        Ok(Machine::with_mmu(mmu))
+    }
+
+    /// Fast boot: unmap the boot ROM and jump straight to the post-boot
+    /// state instead of running the boot sequence
+    pub fn skip_boot(&mut self) {
+        self.mmu_mut().skip_boot_rom();
+        self.reset_model();
+    }
+
+    /// Swap in a different cartridge and restart the CPU from the post-boot state.
+    /// On failure the current ROM stays loaded.
+    pub fn load_rom(&mut self, filename: &str) -> std::io::Result<()> {
+        self.mmu_mut().load_rom(filename)?;
+        self.reset_model();
+        if let Some(buffer) = &mut self.rewind_buffer {
+            buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Current speed/fidelity profile (see Accuracy for what each enables)
+    pub fn accuracy(&self) -> Accuracy {
+        self.mmu().accuracy
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.mmu_mut().accuracy = accuracy;
+    }
+
+    pub fn model(&self) -> HardwareModel {
+        self.model
+    }
+
+    /// Run as `model` regardless of the cartridge (None goes back to detecting
+    /// it from the header). Restarts from the post-boot state.
+    pub fn force_model(&mut self, model: Option<HardwareModel>) {
+        self.forced_model = model;
+        self.reset_model();
+    }
+
+    /// Pick the model for the current cartridge and restart the CPU as that model
+    fn reset_model(&mut self) {
+        let detected = self.mmu().header.as_ref().map_or(HardwareModel::Dmg, |header| header.model());
+        self.model = self.forced_model.unwrap_or(detected);
+        let cgb = self.model == HardwareModel::Cgb;
+        self.mmu.ppu.set_cgb_mode(cgb);
+        // With a boot ROM mapped, start from power-on state and let it run
+        if self.mmu.boot_rom_mapped() {
+            self.cpu.power_on();
+        } else {
+            self.cpu.initialize_as(self.model);
+        }
+    }
+
+    pub fn mmu(&self) -> &MMU {
+        &self.mmu
+    }
+
+    pub fn mmu_mut(&mut self) -> &mut MMU {
+        &mut self.mmu
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.mmu().ppu
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.mmu().timer
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.mmu().apu
+    }
+
+    /// Save the current framebuffer as a 160x144 PNG
+    pub fn screenshot(&self, path: &str) -> std::io::Result<()> {
+        let (width, height) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        let framebuffer = self.ppu().framebuffer();
+        image::save_buffer_with_format(path, framebuffer, width, height, ColorType::Rgba8, ImageFormat::Png)
+            .map_err(std::io::Error::other)
+    }
+
+    /// Execute one instruction and advance the peripherals by the same
+    /// number of cycles. Returns the cycles taken.
+    pub fn step(&mut self) -> Result<u32, CPUError> {
+        let cycles = self.cpu.step(&mut self.mmu)?;
+        if self.mmu.ppu.take_frame_ready() {
+            self.frame_ready = true;
+            if let Some(on_frame) = &mut self.on_frame {
+                on_frame(self.mmu.ppu.framebuffer());
+            }
+        }
+        Ok(cycles)
+    }
+
+    /// True once per frame the PPU completed (entered VBlank) during `step`,
+    /// cleared by the call. The machine takes the PPU's own flag over.
+    pub fn take_frame_ready(&mut self) -> bool {
+        core::mem::take(&mut self.frame_ready)
+    }
+
+    /// Call `callback` with the framebuffer each time the PPU enters VBlank
+    pub fn on_frame(&mut self, callback: FrameCallback) {
+        self.on_frame = Some(callback);
+    }
+
+    pub fn clear_on_frame(&mut self) {
+        self.on_frame = None;
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            cpu: self.cpu.save_state(),
+            mmu: self.mmu().snapshot(),
+        }
+    }
+
+    /// Restore a snapshot, keeping the current link cable, audio output,
+    /// MMIO overrides and framebuffer attached, and the current watchpoints,
+    /// cheats and accuracy settings
+    pub fn load_state(&mut self, state: &SaveState) {
+        let mut mmu = state.mmu.clone();
+        mmu.adopt_host_state(&mut self.mmu);
+        self.mmu = mmu;
+        self.cpu.load_state(&state.cpu);
+    }
+
+    /// Capture the mixed stereo output until `stop_recording`, which writes it to `path` as a WAV file
+    pub fn record_audio(&mut self, path: &str) {
+        self.mmu_mut().apu.start_recording(RECORDING_SAMPLE_RATE);
+        self.recording_path = Some(path.to_string());
+    }
+
+    /// Finish the recording started by `record_audio` and write the WAV file
+    pub fn stop_recording(&mut self) -> std::io::Result<()> {
+        let wav = self.mmu_mut().apu.stop_recording();
+        match (self.recording_path.take(), wav) {
+            (Some(path), Some(wav)) => std::fs::write(path, wav),
+            _ => Err(std::io::Error::other("No audio recording in progress")),
+        }
+    }
+
+    /// Log the joypad state at the start of every frame from now on to
+    /// `path` (see movie.rs), until `stop_input_recording`
+    pub fn record_input(&mut self, path: &str) -> std::io::Result<()> {
+        self.input_recorder = Some(InputRecorder::create(path)?);
+        Ok(())
+    }
+
+    pub fn stop_input_recording(&mut self) {
+        self.input_recorder = None;
+    }
+
+    /// Drive the joypad from the recording at `path`, starting with the next
+    /// frame. Playback overrides frontend input until the recording ends.
+    pub fn play_input(&mut self, path: &str) -> std::io::Result<()> {
+        self.input_player = Some(InputPlayer::open(path)?);
+        Ok(())
+    }
+
+    pub fn is_playing_input(&self) -> bool {
+        self.input_player.is_some()
+    }
+
+    /// Apply recorded input and log live input for the frame about to run
+    fn frame_input(&mut self) {
+        if let Some(mut player) = self.input_player.take() {
+            if let Some(state) = player.frame() {
+                self.mmu_mut().set_joypad_state(state);
+            }
+            if !player.finished() {
+                self.input_player = Some(player);
+            }
+        }
+        let state = self.mmu().joypad.state();
+        if let Some(recorder) = &mut self.input_recorder {
+            if let Err(e) = recorder.frame(state) {
+                println!("Failed to write input recording, stopping: {}", e);
+                self.input_recorder = None;
+            }
+        }
+    }
+
+    /// Start recording a snapshot every `interval` frames, keeping `seconds` of history
+    pub fn enable_rewind(&mut self, interval: u32, seconds: u32) {
+        self.rewind_buffer = Some(RewindBuffer::new(interval, seconds));
+    }
+
+    pub fn rewind_buffer(&self) -> Option<&RewindBuffer> {
+        self.rewind_buffer.as_ref()
+    }
+
+    pub fn rewind_buffer_mut(&mut self) -> Option<&mut RewindBuffer> {
+        self.rewind_buffer.as_mut()
+    }
+
+    /// Restore the most recent rewind snapshot and drop it.
+    /// Returns false if there is nothing to rewind to.
+    pub fn rewind(&mut self) -> bool {
+        match self.rewind_buffer.as_mut().and_then(|buffer| buffer.pop()) {
+            Some(state) => {
+                self.load_state(&state);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// CPU clock cycles in one frame. In double speed the CPU executes
+    /// twice as many cycles per frame.
+    pub fn frame_cycles(&self) -> u32 {
+        if self.mmu().double_speed {
+            CYCLES_PER_FRAME * 2
+        } else {
+            CYCLES_PER_FRAME
+        }
+    }
+
+    /// Run one frame, then wait for the frame limiter.
+    /// Returns the cycles executed.
+    pub fn run_frame(&mut self) -> Result<u32, CPUError> {
+        let cycles = self.emulate_frame()?;
+        self.limiter.wait();
+        Ok(cycles)
+    }
+
+    /// Run until the PPU enters VBlank, without any wall-clock pacing, so
+    /// the framebuffer holds a whole frame on return. With the LCD off there
+    /// is no VBlank and the frame ends after `frame_cycles` instead.
+    /// With rewind enabled, the state at the start of the frame may be recorded.
+    /// Returns the cycles executed.
+    pub fn emulate_frame(&mut self) -> Result<u32, CPUError> {
+        if let Some(mut buffer) = self.rewind_buffer.take() {
+            buffer.record(|| self.save_state());
+            self.rewind_buffer = Some(buffer);
+        }
+        self.frame_input();
+
+        let limit = self.frame_cycles();
+        let mut cycles = 0;
+        self.frame_ready = false;
+        loop {
+            cycles += self.step()?;
+            if self.take_frame_ready() || cycles >= limit {
+                break;
+            }
+        }
+        self.mmu_mut().apply_ram_cheats();
+        Ok(cycles)
+    }
+
+    /// Run `frames` frames against a frontend: poll its input before each
+    /// frame, then present the framebuffer and hand over the frame's audio.
+    /// Paced by the frame limiter. Returns the total cycles executed.
+    pub fn run(&mut self, frontend: &mut dyn Frontend, frames: u32) -> Result<u64, CPUError> {
+        let mut cycles = 0;
+        for _ in 0..frames {
+            let input = frontend.poll_input();
+            if !self.is_playing_input() {
+                self.mmu_mut().set_joypad_state(input);
+            }
+            cycles += self.run_frame()? as u64;
+            frontend.present(self.ppu().framebuffer());
+            let samples = self.drain_audio();
+            if !samples.is_empty() {
+                frontend.queue_audio(&samples);
+            }
+        }
+        Ok(cycles)
+    }
+
+    /// Interleaved stereo samples waiting in the APU's audio output, if attached
+    fn drain_audio(&mut self) -> Vec<f32> {
+        let mut samples = Vec::new();
+        if let Some(ring) = self.mmu().apu.audio_ring() {
+            while let Some((left, right)) = ring.pop() {
+                samples.push(left);
+                samples.push(right);
+            }
+        }
+        samples
+    }
+}
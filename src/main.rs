@@ -1,147 +1,338 @@
 use std::env;
 use std::io::{self, Write};
 use std::fs;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-mod cpu;
-mod mmu;
+use gbrust::cli::{self, Options};
+use gbrust::cpu;
+use gbrust::debugger::{self, Breakpoint, HangDetector, OpcodeBreaks, SymbolTable};
+use gbrust::frontend::{Frontend, TextFrontend};
+use gbrust::machine::Machine;
 
 fn debug_prompt() -> String {
     print!("> ");
     io::stdout().flush().unwrap();
+    read_input().trim().to_string()
+}
+
+/// Where crash dumps go (and where `t` reads the last crash PC from).
+/// Override with the GBRUST_CRASH_FILE environment variable.
+fn crash_file() -> String {
+    env::var("GBRUST_CRASH_FILE").unwrap_or_else(|_| "last_crash.txt".to_string())
+}
+
+/// Report a CPU error and write a crash dump. Returns the crash PC.
+fn record_crash(e: &cpu::CPUError, machine: &Machine) -> u16 {
+    let cpu = &machine.cpu;
+    // At LogLevel::Errors and up the CPU has already reported it
+    if cpu.effective_log_level() < cpu::LogLevel::Errors {
+        println!("CPU Error: {}", e);
+    }
+    let crash_pc = e.pc().unwrap_or(cpu.pc);
+    let path = crash_file();
+    match debugger::write_crash_dump(&path, e, cpu, machine.mmu()) {
+        Ok(()) => println!("Crash dump written to {}", path),
+        Err(e) => println!("Failed to write crash dump: {}", e),
+    }
+    crash_pc
+}
+
+/// Ctrl-C pauses a running command back to the prompt; at a prompt it quits
+struct Interrupt {
+    running: AtomicBool,    // A command is executing
+    requested: AtomicBool,  // Ctrl-C was pressed while it was
+}
+
+static INTERRUPT: Interrupt = Interrupt {
+    running: AtomicBool::new(false),
+    requested: AtomicBool::new(false),
+};
+
+fn install_interrupt_handler() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPT.running.load(Ordering::SeqCst) {
+            INTERRUPT.requested.store(true, Ordering::SeqCst);
+        } else {
+            println!();
+            process::exit(130);
+        }
+    });
+    if let Err(e) = result {
+        println!("Failed to install the Ctrl-C handler: {}", e);
+    }
+}
+
+/// Read a line of user input. Ctrl-C quits while waiting for it; once it's
+/// read, a command is running and Ctrl-C pauses it instead.
+fn read_input() -> String {
+    INTERRUPT.running.store(false, Ordering::SeqCst);
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
+    INTERRUPT.requested.store(false, Ordering::SeqCst);
+    INTERRUPT.running.store(true, Ordering::SeqCst);
+    input
 }
 
-fn parse_hex_address(input: &str) -> Option<u16> {
-    let cleaned = input.trim();
-    if cleaned.len() < 1 {
-        return None;
+/// Debugger state that decides when running commands stop
+struct Session {
+    breakpoints: Vec<Breakpoint>,
+    opcode_breaks: OpcodeBreaks,
+    hang: HangDetector,
+    symbols: SymbolTable,
+}
+
+/// Execute one instruction. Returns Ok(false) if it tripped a watchpoint,
+/// stopped on a breakpoint (by address or by the next opcode) or looks
+/// stuck in a loop, or if Ctrl-C was pressed (in which case nothing is
+/// executed).
+fn step_machine(machine: &mut Machine, session: &mut Session) -> Result<bool, cpu::CPUError> {
+    if INTERRUPT.requested.swap(false, Ordering::SeqCst) {
+        println!("Paused at PC = {}", session.symbols.describe(machine.cpu.pc));
+        return Ok(false);
     }
-    
-    let without_prefix = if cleaned.to_lowercase().starts_with("0x") {
-        &cleaned[2..]
-    } else {
-        cleaned
-    };
-    
-    u16::from_str_radix(without_prefix, 16).ok()
+    let pc = machine.cpu.pc;
+    if machine.cpu.debug_mode {
+        if let Some(label) = session.symbols.label(pc) {
+            println!("{}:", label);
+        }
+    }
+    machine.step()?;
+    if let Some(hit) = machine.mmu_mut().take_watch_hit() {
+        println!("Watchpoint: 0x{:04X} changed 0x{:02X} -> 0x{:02X} (written by instruction at {})",
+            hit.address, hit.old, hit.new, session.symbols.describe(pc));
+        return Ok(false);
+    }
+    if let Some(breakpoint) = session.breakpoints.iter().find(|breakpoint| breakpoint.hit(&machine.cpu)) {
+        println!("Breakpoint: {}", describe_breakpoint(breakpoint, &session.symbols));
+        return Ok(false);
+    }
+    if let Some(opcode) = session.opcode_breaks.check(machine.mmu(), machine.cpu.pc) {
+        println!("Opcode breakpoint: 0x{:02X} at {}", opcode, session.symbols.describe(machine.cpu.pc));
+        return Ok(false);
+    }
+    if let Some(address) = session.hang.observe(machine.cpu.pc) {
+        println!("Likely hang: PC has stayed near {} for {} instructions",
+            session.symbols.describe(address), session.hang.threshold);
+        return Ok(false);
+    }
+    Ok(true)
 }
 
-fn save_crash_pc(pc: u16) -> io::Result<()> {
-    fs::write("last_crash.txt", format!("{:04X}", pc - 1))
+fn describe_breakpoint(breakpoint: &Breakpoint, symbols: &SymbolTable) -> String {
+    match symbols.label(breakpoint.address) {
+        Some(label) => format!("{} ({})", breakpoint, label),
+        None => breakpoint.to_string(),
+    }
 }
 
-fn load_crash_pc() -> Option<u16> {
-    if let Ok(content) = fs::read_to_string("last_crash.txt") {
-        parse_hex_address(&content)
-    } else {
-        None
+/// Load `<rom>.sym` from next to the ROM, if there is one
+fn load_rom_symbols(rom_path: &str) -> SymbolTable {
+    let sym_path = std::path::Path::new(rom_path).with_extension("sym");
+    match SymbolTable::load(&sym_path.to_string_lossy()) {
+        Ok(symbols) => {
+            println!("Loaded {} symbols from {}", symbols.len(), sym_path.display());
+            symbols
+        },
+        Err(_) => SymbolTable::default(),
     }
 }
 
+fn print_help() {
+    println!("  s - Step (execute one instruction)");
+    println!("  so - Step over: run a CALL or RST until it returns");
+    println!("  finish - Run until the current subroutine returns to its caller");
+    println!("  c - Continue until a breakpoint, error or Ctrl-C");
+    println!("  f - Run one frame and show it");
+    println!("  r - Run until PC reaches specified address");
+    println!("  t - Run until last crash PC (loaded from file)");
+    println!("  b <addr> [if <reg>==<value>] - Add a breakpoint (b alone lists them)");
+    println!("  bop <opcode> - Break before any instruction with this opcode (bop alone lists them)");
+    println!("  watch <addr> - Stop when an instruction writes to addr");
+    println!("  set <reg> <value> - Write a register (e.g. set pc 0x0150)");
+    println!("  bt - Show the call stack");
+    println!("  sym <path> - Load labels from a .sym file");
+    println!("  v - View VRAM tiles");
+    println!("  sprites - List the 40 OAM sprites");
+    println!("  lcd - Decode the LCDC and STAT registers");
+    println!("  screenshot <path> - Save the screen as a PNG");
+    println!("  stats - Show instruction and cycle counts");
+    println!("  hotspots [on|off] - Show the most accessed addresses, or start/stop counting");
+    println!("  strict on|off - Warn (with the PC) about writes to ROM, unusable memory or read-only registers and reads of write-only ones");
+    println!("  reset - Clear the statistics");
+    println!("  hang <n> - Pause after n instructions in a tight loop (0 disables)");
+    println!("  o <path> - Load a different ROM (clears breakpoints and watchpoints)");
+    println!("  q - Quit (or Ctrl-C at the prompt)");
+    println!("  h - Show this help");
+}
+
+fn load_crash_pc() -> Option<u16> {
+    let content = fs::read_to_string(crash_file()).ok()?;
+    debugger::parse_crash_pc(&content)
+}
+
 fn main() {
     println!("GBRust - Game Boy Emulator");
-    let mut cpu = cpu::CPU::new();
-    let mut mmu = mmu::MMU::new();
     let mut last_crash_pc = load_crash_pc();  // Load from file at startup
     
-    // Get ROM file from command line argument
+    // Get ROM file and boot options from the command line
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <rom_file>", args[0]);
-        return;
-    }
+    let options = match Options::parse(&args[1..], cli::default_boot_rom()) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: {} {}", args[0], cli::USAGE);
+            return;
+        }
+    };
 
-    // Load ROM
-    match mmu.load_rom(&args[1]) {
-        Ok(_) => println!("ROM loaded successfully"),
+    // Load ROM, plus the boot ROM unless fast booting
+    let mut machine = match options.start() {
+        Ok(machine) => {
+            println!("ROM loaded successfully");
+            machine
+        },
         Err(e) => {
             println!("Failed to load ROM: {}", e);
             return;
         }
-    }
-
-    cpu.set_mmu(mmu);
-    cpu.initialize();
-    cpu.debug_mode = true;
+    };
+    machine.cpu.debug_mode = true;
 
     println!("\nDebugger commands:");
-    println!("  s - Step (execute one instruction)");
-    println!("  c - Continue (run normally)");
-    println!("  r - Run until PC reaches specified address");
-    println!("  t - Run until last crash PC (loaded from file)");
-    println!("  q - Quit");
-    println!("  h - Show this help");
+    print_help();
 
+    install_interrupt_handler();
+    let mut session = Session {
+        breakpoints: Vec::new(),
+        opcode_breaks: OpcodeBreaks::new(),
+        hang: HangDetector::default(),
+        symbols: load_rom_symbols(&options.rom),
+    };
     let mut running = true;
     while running {
-        match debug_prompt().as_str() {
+        let command = debug_prompt();
+        session.hang.reset();
+        match command.as_str() {
             "s" => {
-                match cpu.step() {
+                match step_machine(&mut machine, &mut session) {
                     Ok(_) => (),
                     Err(e) => {
-                        println!("CPU Error: {}", e);
-                        last_crash_pc = Some(cpu.pc);
-                        if let Err(e) = save_crash_pc(cpu.pc) {
-                            println!("Failed to save crash PC: {}", e);
-                        }
+                        last_crash_pc = Some(record_crash(&e, &machine));
                         running = false;
                     }
                 }
             },
+            "so" => {
+                // Run a CALL/RST until it returns; anything else is a plain step
+                let target = debugger::step_over_target(machine.mmu(), machine.cpu.pc);
+                machine.cpu.debug_mode = target.is_none();
+                loop {
+                    match step_machine(&mut machine, &mut session) {
+                        Ok(true) if target.is_some_and(|target| machine.cpu.pc != target) => (),
+                        Ok(_) => break,
+                        Err(e) => {
+                            last_crash_pc = Some(record_crash(&e, &machine));
+                            running = false;
+                            break;
+                        }
+                    }
+                }
+                machine.cpu.debug_mode = true;
+                if target == Some(machine.cpu.pc) {
+                    println!("Stepped over to {}", session.symbols.describe(machine.cpu.pc));
+                }
+            },
+            "finish" => {
+                let finish = debugger::Finish::new(&machine.cpu);
+                machine.cpu.debug_mode = false;
+                let mut returned = false;
+                while !returned {
+                    let opcode = machine.mmu().read_byte(machine.cpu.pc);
+                    match step_machine(&mut machine, &mut session) {
+                        Ok(true) => returned = finish.returned(opcode, &machine.cpu),
+                        Ok(false) => break,
+                        Err(e) => {
+                            last_crash_pc = Some(record_crash(&e, &machine));
+                            running = false;
+                            break;
+                        }
+                    }
+                }
+                machine.cpu.debug_mode = true;
+                if returned {
+                    println!("Returned to {}", session.symbols.describe(machine.cpu.pc));
+                }
+            },
             "c" => {
-                cpu.debug_mode = false;
-                for _ in 0..100 {
-                    match cpu.step() {
-                        Ok(_) => (),
+                machine.cpu.debug_mode = false;
+                loop {
+                    match step_machine(&mut machine, &mut session) {
+                        Ok(true) => (),
+                        Ok(false) => break,
                         Err(e) => {
-                            println!("CPU Error: {}", e);
-                            last_crash_pc = Some(cpu.pc);
-                            if let Err(e) = save_crash_pc(cpu.pc) {
-                                println!("Failed to save crash PC: {}", e);
-                            }
+                            last_crash_pc = Some(record_crash(&e, &machine));
+                            running = false;
+                            break;
+                        }
+                    }
+                }
+                machine.cpu.debug_mode = true;
+            },
+            "f" => {
+                // Run to the next VBlank (or one frame's worth of cycles with
+                // the LCD off), stopping early like `c` does
+                let target = machine.cpu.total_cycles + machine.frame_cycles() as u64;
+                machine.cpu.debug_mode = false;
+                machine.take_frame_ready();
+                while machine.cpu.total_cycles < target {
+                    match step_machine(&mut machine, &mut session) {
+                        Ok(true) if machine.take_frame_ready() => break,
+                        Ok(true) => (),
+                        Ok(false) => break,
+                        Err(e) => {
+                            last_crash_pc = Some(record_crash(&e, &machine));
                             running = false;
                             break;
                         }
                     }
                 }
-                cpu.debug_mode = true;
+                machine.cpu.debug_mode = true;
+                TextFrontend::new(1).present(machine.ppu().framebuffer());
+                println!("Frame {}, PC = {}", machine.ppu().frame_count(), session.symbols.describe(machine.cpu.pc));
             },
             "r" => {
-                print!("Enter target PC (hex, e.g. 0x0393): ");
+                print!("Enter target PC (hex or label, e.g. 0x0393): ");
                 io::stdout().flush().unwrap();
-                let mut input = String::new();
-                io::stdin().read_line(&mut input).unwrap();
+                let input = read_input();
                 
-                match parse_hex_address(input.trim()) {
+                match session.symbols.resolve(&input) {
                     Some(target_pc) => {
-                        println!("Running until PC = 0x{:04X}", target_pc);
+                        println!("Running until PC = {}", session.symbols.describe(target_pc));
                         let mut reached = false;
-                        cpu.debug_mode = false;
+                        machine.cpu.debug_mode = false;
                         
                         while !reached {
-                            match cpu.step() {
-                                Ok(_) => {
-                                    if cpu.pc == target_pc {
+                            match step_machine(&mut machine, &mut session) {
+                                Ok(true) => {
+                                    if machine.cpu.pc == target_pc {
                                         reached = true;
                                     }
                                 },
+                                Ok(false) => break,
                                 Err(e) => {
-                                    println!("CPU Error: {}", e);
-                                    last_crash_pc = Some(cpu.pc);
-                                    if let Err(e) = save_crash_pc(cpu.pc) {
-                                        println!("Failed to save crash PC: {}", e);
-                                    }
+                                    last_crash_pc = Some(record_crash(&e, &machine));
                                     break;
                                 }
                             }
                         }
                         
-                        cpu.debug_mode = true;
-                        println!("Reached target PC = 0x{:04X}", cpu.pc);
+                        machine.cpu.debug_mode = true;
+                        if reached {
+                            println!("Reached target PC = 0x{:04X}", machine.cpu.pc);
+                        }
                     },
-                    None => println!("Invalid hexadecimal address"),
+                    None => println!("Invalid address or unknown label"),
                 }
             },
             "t" => {
@@ -149,43 +340,164 @@ fn main() {
                     Some(target_pc) => {
                         println!("Running until last crash PC = 0x{:04X}", target_pc);
                         let mut reached = false;
-                        cpu.debug_mode = false;
+                        machine.cpu.debug_mode = false;
                         
                         while !reached {
-                            match cpu.step() {
-                                Ok(_) => {
-                                    if cpu.pc == target_pc {
+                            match step_machine(&mut machine, &mut session) {
+                                Ok(true) => {
+                                    if machine.cpu.pc == target_pc {
                                         reached = true;
                                     }
                                 },
+                                Ok(false) => break,
                                 Err(e) => {
-                                    println!("CPU Error: {}", e);
-                                    last_crash_pc = Some(cpu.pc);
-                                    if let Err(e) = save_crash_pc(cpu.pc) {
-                                        println!("Failed to save crash PC: {}", e);
-                                    }
+                                    last_crash_pc = Some(record_crash(&e, &machine));
                                     break;
                                 }
                             }
                         }
                         
-                        cpu.debug_mode = true;
+                        machine.cpu.debug_mode = true;
                         if reached {
-                            println!("Reached crash PC = 0x{:04X}", cpu.pc);
+                            println!("Reached crash PC = 0x{:04X}", machine.cpu.pc);
+                        }
+                    },
+                    None => println!("No previous crash PC found in {}", crash_file()),
+                }
+            },
+            "v" => print!("{}", debugger::dump_tiles(machine.mmu(), 0)),
+            "sprites" => print!("{}", debugger::dump_sprites(machine.mmu())),
+            "lcd" => {
+                let lcdc = machine.mmu().read_byte(0xFF40);
+                let stat = machine.mmu().read_byte(0xFF41);
+                println!("LCDC = 0x{:02X}", lcdc);
+                print!("{}", debugger::describe_lcdc(lcdc));
+                println!("STAT = 0x{:02X}, LY = {}", stat, machine.mmu().read_byte(0xFF44));
+                print!("{}", debugger::describe_stat(stat));
+            },
+            cmd if cmd.starts_with("screenshot ") => {
+                let path = cmd["screenshot ".len()..].trim();
+                match machine.screenshot(path) {
+                    Ok(()) => println!("Saved screenshot to {}", path),
+                    Err(e) => println!("Failed to save screenshot: {}", e),
+                }
+            },
+            cmd if cmd.starts_with("o ") => {
+                let path = cmd[2..].trim();
+                match machine.load_rom(path) {
+                    Ok(()) => {
+                        // A crash PC, labels, breakpoints and watchpoints from
+                        // the previous ROM mean nothing here
+                        last_crash_pc = None;
+                        session.symbols = load_rom_symbols(path);
+                        let breakpoints = session.breakpoints.len();
+                        let watchpoints = machine.mmu().watchpoints.len();
+                        session.breakpoints.clear();
+                        machine.mmu_mut().watchpoints.clear();
+                        machine.mmu_mut().take_watch_hit();
+                        session.hang.reset();
+                        let title = machine.mmu().header.as_ref().map_or("", |header| header.title.as_str());
+                        println!("Switched to {} ({})", path, title);
+                        if breakpoints + watchpoints > 0 {
+                            println!("Cleared {} breakpoint(s) and {} watchpoint(s)", breakpoints, watchpoints);
                         }
                     },
-                    None => println!("No previous crash PC found in last_crash.txt"),
+                    Err(e) => println!("Failed to load ROM, keeping the current one: {}", e),
+                }
+            },
+            cmd if cmd.starts_with("set ") => {
+                let parts: Vec<&str> = cmd.split_whitespace().collect();
+                match (parts.get(1), parts.get(2).and_then(|value| debugger::parse_hex(value)), parts.len()) {
+                    (Some(reg), Some(value), 3) => match debugger::set_register(&mut machine.cpu, reg, value) {
+                        Ok(()) => machine.cpu.print_state(),
+                        Err(e) => println!("{}", e),
+                    },
+                    _ => println!("Usage: set <reg> <hex value>"),
+                }
+            },
+            cmd if cmd.starts_with("watch ") => {
+                match session.symbols.resolve(&cmd[6..]) {
+                    Some(address) => {
+                        machine.mmu_mut().watchpoints.insert(address);
+                        println!("Watching writes to 0x{:04X}", address);
+                    },
+                    None => println!("Invalid address or unknown label"),
+                }
+            },
+            "b" => {
+                for breakpoint in &session.breakpoints {
+                    println!("  {}", describe_breakpoint(breakpoint, &session.symbols));
+                }
+            },
+            cmd if cmd.starts_with("b ") => {
+                match Breakpoint::parse(&cmd[2..], &session.symbols) {
+                    Ok(breakpoint) => {
+                        println!("Breakpoint at {}", describe_breakpoint(&breakpoint, &session.symbols));
+                        session.breakpoints.push(breakpoint);
+                    },
+                    Err(e) => println!("{}", e),
+                }
+            },
+            "bop" => {
+                for opcode in session.opcode_breaks.opcodes() {
+                    println!("  0x{:02X}", opcode);
+                }
+            },
+            cmd if cmd.starts_with("bop ") => {
+                match debugger::parse_hex(&cmd[4..]).and_then(|opcode| u8::try_from(opcode).ok()) {
+                    Some(opcode) => {
+                        session.opcode_breaks.insert(opcode);
+                        println!("Breaking on opcode 0x{:02X}", opcode);
+                    },
+                    None => println!("Usage: bop <hex opcode>"),
+                }
+            },
+            "bt" => print!("{}", debugger::backtrace(&machine.cpu, &session.symbols)),
+            cmd if cmd.starts_with("sym ") => {
+                match SymbolTable::load(cmd[4..].trim()) {
+                    Ok(symbols) => {
+                        println!("Loaded {} symbols", symbols.len());
+                        session.symbols = symbols;
+                    },
+                    Err(e) => println!("Failed to load symbols: {}", e),
+                }
+            },
+            "hotspots" => print!("{}", debugger::hotspots(machine.mmu(), &session.symbols)),
+            "hotspots on" => {
+                machine.mmu_mut().set_access_counting(true);
+                println!("Counting memory accesses");
+            },
+            "hotspots off" => {
+                machine.mmu_mut().set_access_counting(false);
+                println!("Stopped counting memory accesses");
+            },
+            "strict on" => {
+                machine.mmu_mut().strict = true;
+                println!("Warning about ROM writes, unusable memory writes and write-only register reads");
+            },
+            "strict off" => {
+                machine.mmu_mut().strict = false;
+                machine.mmu_mut().take_diagnostics();
+                println!("Strict mode off");
+            },
+            "stats" => print!("{}", debugger::stats(&machine.cpu)),
+            "reset" => {
+                machine.cpu.reset_stats();
+                println!("Statistics cleared");
+            },
+            cmd if cmd.starts_with("hang ") => {
+                match cmd[5..].trim().parse() {
+                    Ok(threshold) => {
+                        session.hang = HangDetector::new(threshold);
+                        println!("Hang detection threshold: {} instructions", threshold);
+                    },
+                    Err(_) => println!("Usage: hang <instructions>"),
                 }
             },
             "q" => running = false,
             "h" => {
                 println!("Commands:");
-                println!("  s - Step (execute one instruction)");
-                println!("  c - Continue (run normally)");
-                println!("  r - Run until PC reaches specified address");
-                println!("  t - Run until last crash PC");
-                println!("  q - Quit");
-                println!("  h - Show this help");
+                print_help();
             },
             "" => (),
             cmd => println!("Unknown command: {}", cmd),
@@ -0,0 +1,136 @@
+use core::fmt;
+
+/// Memory bank controller on the cartridge, decoded from the cartridge
+/// type byte (0x0147) together with the extra hardware it carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mapper {
+    NoMbc { ram: bool, battery: bool },
+    /// `multicart` is the MBC1M wiring used by collection carts: only 4 bits
+    /// of the low bank register are connected. Detected when the ROM loads.
+    Mbc1 { ram: bool, battery: bool, multicart: bool },
+    Mbc2 { battery: bool },  // 512x4-bit RAM built into the MBC
+    Mbc3 { ram: bool, battery: bool, rtc: bool },
+    Mbc5 { ram: bool, battery: bool, rumble: bool },
+    Mmm01 { ram: bool, battery: bool },
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    Huc1,
+    Huc3,
+    Unknown(u8),
+}
+
+impl Default for Mapper {
+    fn default() -> Self {
+        Mapper::NoMbc { ram: false, battery: false }
+    }
+}
+
+impl Mapper {
+    pub fn from_byte(cartridge_type: u8) -> Mapper {
+        match cartridge_type {
+            0x00 => Mapper::NoMbc { ram: false, battery: false },
+            0x08 => Mapper::NoMbc { ram: true, battery: false },
+            0x09 => Mapper::NoMbc { ram: true, battery: true },
+            0x01 => Mapper::Mbc1 { ram: false, battery: false, multicart: false },
+            0x02 => Mapper::Mbc1 { ram: true, battery: false, multicart: false },
+            0x03 => Mapper::Mbc1 { ram: true, battery: true, multicart: false },
+            0x05 => Mapper::Mbc2 { battery: false },
+            0x06 => Mapper::Mbc2 { battery: true },
+            0x0B => Mapper::Mmm01 { ram: false, battery: false },
+            0x0C => Mapper::Mmm01 { ram: true, battery: false },
+            0x0D => Mapper::Mmm01 { ram: true, battery: true },
+            0x0F => Mapper::Mbc3 { ram: false, battery: true, rtc: true },
+            0x10 => Mapper::Mbc3 { ram: true, battery: true, rtc: true },
+            0x11 => Mapper::Mbc3 { ram: false, battery: false, rtc: false },
+            0x12 => Mapper::Mbc3 { ram: true, battery: false, rtc: false },
+            0x13 => Mapper::Mbc3 { ram: true, battery: true, rtc: false },
+            0x19 => Mapper::Mbc5 { ram: false, battery: false, rumble: false },
+            0x1A => Mapper::Mbc5 { ram: true, battery: false, rumble: false },
+            0x1B => Mapper::Mbc5 { ram: true, battery: true, rumble: false },
+            0x1C => Mapper::Mbc5 { ram: false, battery: false, rumble: true },
+            0x1D => Mapper::Mbc5 { ram: true, battery: false, rumble: true },
+            0x1E => Mapper::Mbc5 { ram: true, battery: true, rumble: true },
+            0x20 => Mapper::Mbc6,
+            0x22 => Mapper::Mbc7,
+            0xFC => Mapper::PocketCamera,
+            0xFD => Mapper::Tama5,
+            0xFE => Mapper::Huc3,
+            0xFF => Mapper::Huc1,
+            other => Mapper::Unknown(other),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mapper::NoMbc { .. } => "ROM ONLY",
+            Mapper::Mbc1 { multicart: true, .. } => "MBC1M",
+            Mapper::Mbc1 { .. } => "MBC1",
+            Mapper::Mbc2 { .. } => "MBC2",
+            Mapper::Mbc3 { .. } => "MBC3",
+            Mapper::Mbc5 { .. } => "MBC5",
+            Mapper::Mmm01 { .. } => "MMM01",
+            Mapper::Mbc6 => "MBC6",
+            Mapper::Mbc7 => "MBC7",
+            Mapper::PocketCamera => "POCKET CAMERA",
+            Mapper::Tama5 => "TAMA5",
+            Mapper::Huc1 => "HuC1",
+            Mapper::Huc3 => "HuC3",
+            Mapper::Unknown(_) => "UNKNOWN",
+        }
+    }
+
+    /// External RAM on the cartridge (MBC2's built-in RAM counts)
+    pub fn has_ram(&self) -> bool {
+        match *self {
+            Mapper::NoMbc { ram, .. }
+            | Mapper::Mbc1 { ram, .. }
+            | Mapper::Mbc3 { ram, .. }
+            | Mapper::Mbc5 { ram, .. }
+            | Mapper::Mmm01 { ram, .. } => ram,
+            Mapper::Mbc2 { .. } | Mapper::Mbc7 | Mapper::PocketCamera | Mapper::Huc1 => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        match *self {
+            Mapper::NoMbc { battery, .. }
+            | Mapper::Mbc1 { battery, .. }
+            | Mapper::Mbc2 { battery }
+            | Mapper::Mbc3 { battery, .. }
+            | Mapper::Mbc5 { battery, .. }
+            | Mapper::Mmm01 { battery, .. } => battery,
+            Mapper::Mbc7 | Mapper::PocketCamera | Mapper::Huc1 | Mapper::Huc3 => true,
+            _ => false,
+        }
+    }
+
+    /// Real-time clock (MBC3 timer carts)
+    pub fn has_rtc(&self) -> bool {
+        matches!(self, Mapper::Mbc3 { rtc: true, .. } | Mapper::Huc3)
+    }
+}
+
+impl fmt::Display for Mapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        if let Mapper::Unknown(byte) = self {
+            write!(f, " (0x{:02X})", byte)?;
+        }
+        if matches!(self, Mapper::Mbc5 { rumble: true, .. }) {
+            write!(f, "+RUMBLE")?;
+        }
+        if self.has_rtc() {
+            write!(f, "+TIMER")?;
+        }
+        if self.has_ram() {
+            write!(f, "+RAM")?;
+        }
+        if self.has_battery() {
+            write!(f, "+BATTERY")?;
+        }
+        Ok(())
+    }
+}
@@ -1,180 +1,1479 @@
+use alloc::format;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io::Read;
 
+use crate::apu::{Apu, NR10, NR52};
+use crate::cheats::{Cheat, CheatError, Cheats, GAMESHARK_RANGES};
+use crate::joypad::{Button, Joypad, JoypadState, P1};
+use crate::mapper::Mapper;
+use crate::ppu::{self, LcdRegs, OamEntry, Ppu, BCPS, OCPD};
+use crate::rng::{self, Rng};
+use crate::serial::{Serial, SB, SC};
+use crate::sgb::SgbPackets;
+use crate::timer::{Timer, DIV, TAC, TIMA, TMA};
+
 // LCD Register addresses
 const LCDC: u16 = 0xFF40;  // LCD Control
 const STAT: u16 = 0xFF41;  // LCD Status
 const LY: u16   = 0xFF44;  // LCD Y-Coordinate
 const LYC: u16  = 0xFF45;  // LY Compare
+const SCY: u16  = 0xFF42;  // Background scroll Y
+const SCX: u16  = 0xFF43;  // Background scroll X
+const BGP: u16  = 0xFF47;  // DMG background palette
+const OBP0: u16 = 0xFF48;  // DMG sprite palette 0
+const OBP1: u16 = 0xFF49;  // DMG sprite palette 1
+const WY: u16   = 0xFF4A;  // Window Y position
+const WX: u16   = 0xFF4B;  // Window X position + 7
+const KEY1: u16 = 0xFF4D;  // CGB speed switch
+const VBK: u16  = 0xFF4F;  // CGB VRAM bank select
+const BOOT: u16 = 0xFF50;  // Boot ROM disable
+const SVBK: u16 = 0xFF70;  // CGB WRAM bank select
+
+// CGB VRAM DMA registers
+const HDMA1: u16 = 0xFF51;  // Source high
+const HDMA2: u16 = 0xFF52;  // Source low
+const HDMA3: u16 = 0xFF53;  // Destination high
+const HDMA4: u16 = 0xFF54;  // Destination low
+const HDMA5: u16 = 0xFF55;  // Length/mode/start
+const IF: u16   = 0xFF0F;  // Interrupt Flag
+
+// Interrupt bits in IF/IE, highest priority first
+pub const INT_VBLANK: u8 = 0x01;
+pub const INT_STAT: u8 = 0x02;
+pub const INT_TIMER: u8 = 0x04;
+pub const INT_SERIAL: u8 = 0x08;
+pub const INT_JOYPAD: u8 = 0x10;
+
+// PPU timing, in cycles
+const LINE_CYCLES: u32 = 456;
+const MODE2_CYCLES: u32 = 80;  // OAM scan
+const MODE3_MIN_CYCLES: u32 = 172;  // Mode 3 on a line with no fine scroll, window or sprites
+
+/// A fixed-size block of the address map backed by an array.
+/// `offset` is the single place addresses become array indices: callers
+/// only pass addresses their match arm has already confined to the region,
+/// and debug builds assert it, so an off-by-one in a future arm panics
+/// with the address instead of silently hitting the wrong byte. Release
+/// builds compile it down to the plain subtraction.
+#[derive(Clone, Copy)]
+struct Region {
+    base: u16,
+    len: usize,
+}
+
+impl Region {
+    #[inline(always)]
+    fn offset(self, address: u16) -> usize {
+        let offset = address.wrapping_sub(self.base) as usize;
+        debug_assert!(
+            offset < self.len,
+            "address {:#06x} outside region {:#06x}-{:#06x}",
+            address,
+            self.base,
+            self.base as usize + self.len - 1
+        );
+        offset
+    }
+}
+
+const REGION_ROM_BANK: Region = Region { base: 0x4000, len: 0x4000 };
+const REGION_VRAM: Region = Region { base: 0x8000, len: 0x2000 };
+const REGION_EXT_RAM: Region = Region { base: 0xA000, len: 0x2000 };
+const REGION_WRAM: Region = Region { base: 0xC000, len: 0x2000 };
+const REGION_ECHO: Region = Region { base: 0xE000, len: 0x1E00 };
+const REGION_OAM: Region = Region { base: 0xFE00, len: 0xA0 };
+const REGION_IO: Region = Region { base: 0xFF00, len: 0x80 };
+const REGION_HRAM: Region = Region { base: 0xFF80, len: 0x7F };
+
+/// Bits of each I/O register (0xFF00-0xFF7F) that always read as 1:
+/// unused bits, write-only registers and addresses with nothing behind them.
+/// The sound registers apply their own masks in the APU.
+const IO_READ_MASKS: [u8; 0x80] = [
+    // FF00-FF0F: P1, SB, SC, -, DIV, TIMA, TMA, TAC, -, ..., IF
+    0xC0, 0x00, 0x7C, 0xFF, 0x00, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+    // FF10-FF3F: sound
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // FF40-FF4F: LCDC, STAT, SCY, SCX, LY, LYC, DMA, BGP, OBP0, OBP1, WY, WX, -, KEY1, -, VBK
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, 0xFF, 0xFE,
+    // FF50-FF5F: BOOT, HDMA1-HDMA5, -
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // FF60-FF6F: -, BCPS, BCPD, OCPS, OCPD, -
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+    // FF70-FF7F: SVBK, -
+    0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// A ROM image that can't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomError {
+    /// Cartridge image shorter than its header (0x150 bytes)
+    TooSmall(usize),
+    /// Boot ROM image that isn't exactly 256 bytes
+    BootRomSize(usize),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::TooSmall(len) => write!(f, "ROM too small to hold a header: {} bytes", len),
+            RomError::BootRomSize(len) => write!(f, "Boot ROM must be 256 bytes, got {}", len),
+        }
+    }
+}
+
+impl core::error::Error for RomError {}
+
+#[cfg(feature = "std")]
+impl From<RomError> for std::io::Error {
+    fn from(e: RomError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// A write to a watched address, recorded by the MMU for the debugger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub address: u16,
+    pub old: u8,     // Value read back before the write
+    pub new: u8,     // Value written
+}
+
+/// Custom read/write behavior for an address range, installed with
+/// MMU::register_mmio and consulted before the normal memory map
+pub trait MmioHandler {
+    /// The value a read of `address` returns, or None to use the normal map
+    fn read(&self, address: u16) -> Option<u8> {
+        let _ = address;
+        None
+    }
+
+    /// Handle a write. Returns true if it was consumed, false to let it
+    /// reach the normal map as well.
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        let _ = (address, value);
+        false
+    }
+}
+
+/// Installed MMIO overrides, most recently registered first
+#[derive(Default)]
+pub struct MmioHandlers(Vec<(RangeInclusive<u16>, Box<dyn MmioHandler>)>);
+
+// Snapshots carry the emulated state only: handlers stay with the live bus
+// (see Machine::load_state)
+impl Clone for MmioHandlers {
+    fn clone(&self) -> Self {
+        MmioHandlers::default()
+    }
+}
+
+impl MmioHandlers {
+    fn read(&self, address: u16) -> Option<u8> {
+        self.0.iter().filter(|(range, _)| range.contains(&address)).find_map(|(_, handler)| handler.read(address))
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        self.0.iter_mut().filter(|(range, _)| range.contains(&address)).any(|(_, handler)| handler.write(address, value))
+    }
+}
+
+/// Registers that can only be written; reads return 0xFF rather than the value
+const WRITE_ONLY_REGISTERS: [u16; 9] = [
+    0xFF13, 0xFF18, 0xFF1B, 0xFF1D, 0xFF20,  // NR13, NR23, NR31, NR33, NR41
+    HDMA1, HDMA2, HDMA3, HDMA4,
+];
+
+/// True for a write to LY, or one setting STAT's mode/coincidence bits
+/// (0-2) or NR52's channel status bits (0-3): none of them are the CPU's to change
+fn writes_read_only_bits(address: u16, value: u8) -> bool {
+    match address {
+        LY => true,
+        STAT => value & 0x07 != 0,
+        NR52 => value & 0x0F != 0,
+        _ => false,
+    }
+}
 
-#[derive(Debug)]
+/// Strict mode warnings kept until take_diagnostics; older ones are only counted
+const MAX_DIAGNOSTICS: usize = 256;
+
+/// An access the hardware silently ignores, flagged in strict mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// Write to 0000-7FFF on a cartridge without a bank controller
+    RomWrite,
+    /// Write to the unusable region at FEA0-FEFF
+    UnusableWrite,
+    /// Read of a write-only register
+    WriteOnlyRead,
+    /// Write to LY or to the read-only bits of STAT or NR52
+    ReadOnlyWrite,
+}
+
+/// One strict mode warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub violation: Violation,
+    pub pc: u16,  // Instruction that made the access
+    pub address: u16,
+    pub value: u8,  // Value written (0 for reads)
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.violation {
+            Violation::RomWrite => {
+                write!(f, "PC {:04X}: write of {:02X} to ROM at {:04X} (no MBC)", self.pc, self.value, self.address)
+            },
+            Violation::UnusableWrite => {
+                write!(f, "PC {:04X}: write of {:02X} to unusable memory at {:04X}", self.pc, self.value, self.address)
+            },
+            Violation::WriteOnlyRead => write!(f, "PC {:04X}: read of write-only register {:04X}", self.pc, self.address),
+            Violation::ReadOnlyWrite => {
+                write!(f, "PC {:04X}: write of {:02X} to read-only bits of {:04X}", self.pc, self.value, self.address)
+            },
+        }
+    }
+}
+
+/// Read and write counts per address, for finding hot spots such as STAT
+/// polling loops. Only kept while enabled with MMU::set_access_counting.
+#[derive(Clone)]
+pub struct AccessCounts {
+    reads: Vec<Cell<u32>>,  // Cells because reads only borrow the MMU
+    writes: Vec<u32>,
+}
+
+impl AccessCounts {
+    fn new() -> AccessCounts {
+        AccessCounts { reads: vec![Cell::new(0); 0x10000], writes: vec![0; 0x10000] }
+    }
+
+    fn record_read(&self, address: u16) {
+        let count = &self.reads[address as usize];
+        count.set(count.get().saturating_add(1));
+    }
+
+    fn record_write(&mut self, address: u16) {
+        let count = &mut self.writes[address as usize];
+        *count = count.saturating_add(1);
+    }
+
+    pub fn reads(&self, address: u16) -> u32 {
+        self.reads[address as usize].get()
+    }
+
+    pub fn writes(&self, address: u16) -> u32 {
+        self.writes[address as usize]
+    }
+
+    /// (address, reads, writes) for the `count` most accessed addresses, busiest first
+    pub fn hottest(&self, count: usize) -> Vec<(u16, u32, u32)> {
+        let mut hot: Vec<(u16, u32, u32)> = (0..=0xFFFF)
+            .map(|address| (address, self.reads(address), self.writes(address)))
+            .filter(|&(_, reads, writes)| reads > 0 || writes > 0)
+            .collect();
+        hot.sort_by(|a, b| (b.1 as u64 + b.2 as u64).cmp(&(a.1 as u64 + a.2 as u64)).then(a.0.cmp(&b.0)));
+        hot.truncate(count);
+        hot
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct CartridgeHeader {
     pub title: String,
+    pub cgb_flag: u8,
     pub cartridge_type: u8,
+    pub mapper: Mapper,
     pub rom_size: u8,
     pub ram_size: u8,
+    pub sgb_flag: u8,  // 0x03 if the game supports Super Game Boy functions
+    pub destination: u8,  // 0x00 Japan, 0x01 overseas
+    pub old_licensee: u8,
+    pub new_licensee: [u8; 2],  // ASCII, used when old_licensee is 0x33
+    pub version: u8,  // Mask ROM version
 }
 
+impl CartridgeHeader {
+    pub fn is_japanese(&self) -> bool {
+        self.destination == 0x00
+    }
+
+    /// "Japan" or "Overseas", from the destination code (0x014A)
+    pub fn region(&self) -> &'static str {
+        if self.is_japanese() { "Japan" } else { "Overseas" }
+    }
+
+    pub fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03
+    }
+
+    /// Publisher decoded from the licensee code, if it's a known one
+    pub fn publisher(&self) -> Option<&'static str> {
+        crate::licensee::publisher(self.old_licensee, self.new_licensee)
+    }
+
+    /// The licensee code as printed in listings: the two new-code characters or the old byte in hex
+    pub fn licensee_code(&self) -> String {
+        if self.old_licensee == crate::licensee::USE_NEW_LICENSEE {
+            String::from_utf8_lossy(&self.new_licensee).into_owned()
+        } else {
+            format!("{:02X}", self.old_licensee)
+        }
+    }
+
+    /// External RAM size in bytes, decoded from the RAM size byte (0x0149)
+    pub fn ram_size_bytes(&self) -> usize {
+        match self.ram_size {
+            0x01 => 0x800,     // 2 KB (unofficial, used by some homebrew)
+            0x02 => 0x2000,    // 8 KB, 1 bank
+            0x03 => 0x8000,    // 32 KB, 4 banks
+            0x04 => 0x20000,   // 128 KB, 16 banks
+            0x05 => 0x10000,   // 64 KB, 8 banks
+            _ => 0,            // No RAM
+        }
+    }
+
+    /// Hardware the cartridge asks for (CGB flag at 0x0143)
+    pub fn model(&self) -> HardwareModel {
+        HardwareModel::from_cgb_flag(self.cgb_flag)
+    }
+}
+
+/// Console the emulator behaves as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HardwareModel {
+    #[default]
+    Dmg,
+    Cgb,
+}
+
+impl HardwareModel {
+    /// 0x80 (CGB enhanced) and 0xC0 (CGB only) select CGB; anything else is a DMG cartridge
+    pub fn from_cgb_flag(flag: u8) -> HardwareModel {
+        match flag {
+            0x80 | 0xC0 => HardwareModel::Cgb,
+            _ => HardwareModel::Dmg,
+        }
+    }
+}
+
+/// Power-on contents of work RAM and HRAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamFill {
+    /// All zeros, for tests that want a blank slate
+    Zeros,
+    /// What a DMG shows: WRAM in alternating 8-byte runs of 0x00 and 0xFF
+    /// with bits flipped at random, and noise in HRAM. Some games seed
+    /// their own random number generators from it.
+    #[default]
+    Pattern,
+}
+
+/// Speed/fidelity profile, switching the costlier hardware details together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Accuracy {
+    /// Maximum speed: peripherals are ticked once per instruction rather
+    /// than per memory access, lines are drawn whole by the scanline
+    /// renderer, VRAM/OAM stay accessible in every PPU mode, and the DMG
+    /// OAM corruption and STAT write bugs are not emulated
+    Fast,
+    /// Everything the emulator models: sub-instruction timing (the MMU is
+    /// ticked as each opcode/operand byte is fetched), the pixel FIFO
+    /// renderer, VRAM/OAM locking during modes 2-3, and the OAM corruption
+    /// and STAT write bugs
+    #[default]
+    Accurate,
+}
+
+#[derive(Clone)]
 pub struct MMU {
     // Memory regions
-    rom_bank0: [u8; 0x4000],      // 0000-3FFF Fixed ROM bank
-    rom_bankn: [u8; 0x4000],      // 4000-7FFF Switchable ROM bank
-    vram: [u8; 0x2000],           // 8000-9FFF Video RAM
-    ext_ram: [u8; 0x2000],        // A000-BFFF External RAM
-    wram: [u8; 0x2000],           // C000-DFFF Work RAM
+    rom: Arc<[u8]>,               // Whole ROM image, shared by clones: bank 0 at 0000-3FFF, `rom_bank` at 4000-7FFF
+    vram: [[u8; 0x2000]; 2],      // 8000-9FFF Video RAM, bank 1 on CGB only
+    ext_ram: Vec<u8>,             // A000-BFFF External RAM (sized from header)
+    wram: [[u8; 0x1000]; 8],      // C000-CFFF Work RAM bank 0, D000-DFFF banks 1-7 (CGB)
     oam: [u8; 0xA0],              // FE00-FE9F Sprite info
     io_regs: [u8; 0x80],          // FF00-FF7F I/O Registers
     hram: [u8; 0x7F],             // FF80-FFFE High RAM
     ie_register: u8,              // FFFF Interrupt Enable
+    boot_rom: Option<[u8; 0x100]>, // 0000-00FF DMG boot ROM overlay (until FF50 is written)
+    ram_enabled: bool,            // External RAM enable (0000-1FFF = 0x0A)
+    ram_bank: u8,                 // External RAM bank (4000-5FFF); the upper ROM bank bits on MBC1
+    rom_bank: u16,                // ROM bank mapped at 4000-7FFF; the lower bits on MBC1
+    mbc1_mode: bool,              // MBC1 banking mode (6000-7FFF): 4000-5FFF also banks 0000-3FFF and RAM
+    forced_multicart: Option<bool>,
+    mapper: Mapper,               // Decoded from the cartridge header
     pub header: Option<CartridgeHeader>,
+    pub timer: Timer,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    pub serial: Serial,
+    pub joypad: Joypad,
+    pub sgb: SgbPackets,  // Super Game Boy packets sent through P1
+    pub cheats: Cheats,
+
+    pub accuracy: Accuracy,
+
+    // E000-FDFF mirrors C000-DDFF. When disabled, the region reads 0xFF and
+    // ignores writes, to catch stray accesses.
+    pub echo_ram_enabled: bool,
+
+    // Debugger write watchpoints. The most recent hit waits in watch_hit
+    // until the step loop takes it.
+    pub watchpoints: BTreeSet<u16>,
+    watch_hit: Option<WatchHit>,
+    access_counts: Option<AccessCounts>,
+    mmio: MmioHandlers,
+
+    // Strict mode: warn about accesses the hardware ignores. Reads only
+    // borrow the MMU, hence the RefCell.
+    pub strict: bool,
+    pub instruction_pc: u16,  // Set by the CPU to tag diagnostics
+    diagnostics: RefCell<VecDeque<Diagnostic>>,  // The last MAX_DIAGNOSTICS
+    dropped_diagnostics: Cell<usize>,  // Older ones discarded since take_diagnostics
 
     // LCD timing
     pub cycles: u32,
     pub scanline: u8,
     pub mode: u8,
+    mode3_cycles: u32,  // Length of mode 3 on the current line
+
+    // CGB double-speed mode (toggled by STOP with KEY1 bit 0 armed)
+    pub double_speed: bool,
+
+    // CGB VRAM DMA (HDMA1-HDMA5)
+    hdma_src: u16,
+    hdma_dst: u16,       // Offset into VRAM (0x0000-0x1FF0)
+    hdma_length: u8,     // Remaining 16-byte blocks minus one
+    hdma_active: bool,   // HBlank DMA in progress
+
+    ram_seed: u64,  // Seeds the power-on RAM pattern
+}
+
+impl Default for MMU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MMU {
     pub fn new() -> MMU {
-        MMU {
-            rom_bank0: [0; 0x4000],
-            rom_bankn: [0; 0x4000],
-            vram: [0; 0x2000],
-            ext_ram: [0; 0x2000],
-            wram: [0; 0x2000],
+        MMU::new_seeded(rng::DEFAULT_SEED)
+    }
+
+    /// A bus whose power-on WRAM and HRAM pattern comes from `seed`
+    pub fn new_seeded(seed: u64) -> MMU {
+        let mut mmu = MMU {
+            rom: vec![0; 0x8000].into(),
+            vram: [[0; 0x2000]; 2],
+            ext_ram: Vec::new(),
+            wram: [[0; 0x1000]; 8],
             oam: [0; 0xA0],
-            io_regs: [0; 0x80],
+            io_regs: Self::initial_io_regs(),
             hram: [0; 0x7F],
             ie_register: 0,
+            boot_rom: None,
+            ram_enabled: false,
+            ram_bank: 0,
+            rom_bank: 1,
+            mbc1_mode: false,
+            forced_multicart: None,
+            mapper: Mapper::default(),
             header: None,
+            timer: Timer::new(),
+            ppu: Ppu::new(),
+            apu: Apu::new(),
+            serial: Serial::new(),
+            joypad: Joypad::new(),
+            sgb: SgbPackets::new(),
+            cheats: Cheats::new(),
+            accuracy: Accuracy::default(),
+            echo_ram_enabled: true,
+            watchpoints: BTreeSet::new(),
+            watch_hit: None,
+            access_counts: None,
+            strict: false,
+            instruction_pc: 0,
+            diagnostics: RefCell::new(VecDeque::new()),
+            dropped_diagnostics: Cell::new(0),
+            mmio: MmioHandlers::default(),
             cycles: 0,
             scanline: 0,
             mode: 0,
+            mode3_cycles: MODE3_MIN_CYCLES,
+            double_speed: false,
+            hdma_src: 0,
+            hdma_dst: 0,
+            hdma_length: 0x7F,
+            hdma_active: false,
+            ram_seed: seed,
+        };
+        mmu.fill_ram_pattern(RamFill::default());
+        mmu
+    }
+
+    /// Overwrite work RAM and HRAM with power-on contents. VRAM is left
+    /// alone since the boot ROM clears it before any game runs.
+    pub fn fill_ram_pattern(&mut self, fill: RamFill) {
+        match fill {
+            RamFill::Zeros => {
+                self.wram = [[0; 0x1000]; 8];
+                self.hram = [0; 0x7F];
+            },
+            RamFill::Pattern => {
+                let mut rng = Rng::new(self.ram_seed);
+                for bank in self.wram.iter_mut() {
+                    for (i, byte) in bank.iter_mut().enumerate() {
+                        let base = if (i / 8) % 2 == 0 { 0x00 } else { 0xFF };
+                        *byte = base ^ (rng.next_u8() & rng.next_u8() & rng.next_u8());
+                    }
+                }
+                rng.fill(&mut self.hram);
+            },
         }
     }
 
+    /// I/O registers as the boot ROM leaves them: LCD on with the
+    /// background enabled (LCDC 0x91) and the standard palette (BGP 0xFC)
+    fn initial_io_regs() -> [u8; 0x80] {
+        let mut io_regs = [0; 0x80];
+        io_regs[REGION_IO.offset(LCDC)] = 0x91;
+        io_regs[REGION_IO.offset(BGP)] = 0xFC;
+        io_regs
+    }
+
     fn parse_header(&mut self) {
-        // Read cartridge header from ROM bank 0
-        let title = String::from_utf8_lossy(&self.rom_bank0[0x134..=0x143])
+        // Read cartridge header from ROM bank 0. On CGB cartridges the
+        // last title byte is the CGB flag.
+        let cgb_flag = self.rom[0x143];
+        let title_end = match HardwareModel::from_cgb_flag(cgb_flag) {
+            HardwareModel::Cgb => 0x142,
+            HardwareModel::Dmg => 0x143,
+        };
+        let title = String::from_utf8_lossy(&self.rom[0x134..=title_end])
             .trim_matches(char::from(0))
             .to_string();
         
-        let cartridge_type = self.rom_bank0[0x147];
-        let rom_size = self.rom_bank0[0x148];
-        let ram_size = self.rom_bank0[0x149];
+        let cartridge_type = self.rom[0x147];
+        let rom_size = self.rom[0x148];
+        let ram_size = self.rom[0x149];
 
         self.header = Some(CartridgeHeader {
             title,
+            cgb_flag,
             cartridge_type,
+            mapper: Mapper::from_byte(cartridge_type),
             rom_size,
             ram_size,
+            sgb_flag: self.rom[0x146],
+            destination: self.rom[0x14A],
+            old_licensee: self.rom[0x14B],
+            new_licensee: [self.rom[0x144], self.rom[0x145]],
+            version: self.rom[0x14C],
         });
     }
 
+    #[cfg(feature = "std")]
     pub fn load_rom(&mut self, filename: &str) -> std::io::Result<()> {
         let mut file = File::open(filename)?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
-        
-        // Load first ROM bank (0x0000-0x3FFF)
-        for (i, &byte) in buffer.iter().take(0x4000).enumerate() {
-            self.rom_bank0[i] = byte;
+        Ok(self.load_rom_bytes(&buffer)?)
+    }
+
+    /// Load a cartridge ROM image from memory (embedded ROMs, WASM)
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), RomError> {
+        if data.len() < 0x150 {
+            return Err(RomError::TooSmall(data.len()));
         }
-        
-        // Load second ROM bank (0x4000-0x7FFF)
-        if buffer.len() > 0x4000 {
-            for (i, &byte) in buffer[0x4000..].iter().take(0x4000).enumerate() {
-                self.rom_bankn[i] = byte;
-            }
+
+        // Keep the whole image for banking; pad short ROMs to two banks
+        let mut rom = data.to_vec();
+        if rom.len() < 0x8000 {
+            rom.resize(0x8000, 0);
         }
+        self.rom = rom.into();
+        self.rom_bank = 1;
 
         // Parse cartridge header
         self.parse_header();
+
+        // Allocate external RAM to match the header
+        self.mbc1_mode = false;
+        if let Some(Mapper::Mbc1 { multicart, .. }) = self.header.as_mut().map(|header| &mut header.mapper) {
+            *multicart = self.forced_multicart.unwrap_or_else(|| is_mbc1_multicart(&self.rom));
+        }
+        if let Some(ref header) = self.header {
+            self.mapper = header.mapper;
+            self.ext_ram = match self.mapper {
+                Mapper::Mbc2 { .. } => vec![0; 0x200],  // Built-in 512x4 bits
+                _ => vec![0; header.ram_size_bytes()],
+            };
+            // Without a mapper, cartridge RAM is always accessible
+            self.ram_enabled = matches!(self.mapper, Mapper::NoMbc { .. });
+            self.ram_bank = 0;
+        }
         
         if let Some(ref header) = self.header {
             println!("Loaded ROM: {}", header.title);
-            println!("Cartridge type: 0x{:02X}", header.cartridge_type);
+            println!("Cartridge type: 0x{:02X} ({})", header.cartridge_type, header.mapper);
             println!("ROM size: 0x{:02X}", header.rom_size);
-            println!("RAM size: 0x{:02X}", header.ram_size);
+            println!("RAM size: 0x{:02X} ({} KB)", header.ram_size, header.ram_size_bytes() / 1024);
+            println!("Publisher: {} ({})", header.publisher().unwrap_or("Unknown"), header.licensee_code());
+            println!("Region: {}, version {}, SGB: {}", header.region(), header.version,
+                if header.supports_sgb() { "yes" } else { "no" });
         }
+        // Some ROMs ship with a wrong global checksum, so only warn
+        if !self.verify_rom() {
+            println!("Warning: global checksum mismatch (header 0x{:04X}, computed 0x{:04X}); the ROM may be corrupt or truncated",
+                self.stored_checksum(), self.global_checksum());
+        }
+
+        Ok(())
+    }
+
+    /// 16-bit sum of every ROM byte except the checksum itself (0x014E-0x014F)
+    pub fn global_checksum(&self) -> u16 {
+        self.rom
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !(0x14E..=0x14F).contains(i))
+            .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte as u16))
+    }
+
+    /// Global checksum from the header, stored big-endian
+    fn stored_checksum(&self) -> u16 {
+        u16::from_be_bytes([self.rom[0x14E], self.rom[0x14F]])
+    }
+
+    /// True if the header's global checksum matches the ROM contents.
+    /// The boot ROM never checks this, so a mismatch doesn't stop a game running.
+    pub fn verify_rom(&self) -> bool {
+        self.global_checksum() == self.stored_checksum()
+    }
 
+    /// Load a 256-byte DMG boot ROM that overlays 0x0000-0x00FF until
+    /// the boot code writes to 0xFF50 to unmap it.
+    #[cfg(feature = "std")]
+    pub fn load_boot_rom(&mut self, filename: &str) -> std::io::Result<()> {
+        let mut file = File::open(filename)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        Ok(self.load_boot_rom_bytes(&buffer)?)
+    }
+
+    /// Boot ROM from memory, see `load_boot_rom`
+    pub fn load_boot_rom_bytes(&mut self, data: &[u8]) -> Result<(), RomError> {
+        let boot_rom: [u8; 0x100] = data.try_into().map_err(|_| RomError::BootRomSize(data.len()))?;
+        self.boot_rom = Some(boot_rom);
+        // Power-on state: the boot ROM turns the LCD on itself
+        self.io_regs[REGION_IO.offset(LCDC)] = 0x00;
         Ok(())
     }
 
+    /// Unmap the boot ROM without running it, leaving the I/O registers
+    /// as it would have (see `initial_io_regs`)
+    pub fn skip_boot_rom(&mut self) {
+        if self.boot_rom.take().is_some() {
+            self.io_regs[REGION_IO.offset(LCDC)] = 0x91;
+        }
+    }
+
+    /// True while the boot ROM is still mapped over the cartridge
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
+        if let Some(counts) = &self.access_counts {
+            counts.record_read(address);
+        }
+        if !self.mmio.0.is_empty() {
+            if let Some(value) = self.mmio.read(address) {
+                return value;
+            }
+        }
+        if self.strict && WRITE_ONLY_REGISTERS.contains(&address) {
+            self.diagnose(Violation::WriteOnlyRead, address, 0);
+        }
+        self.bus_read(address)
+    }
+
+    /// read_byte without counting the access, for the MMU's own reads
+    fn bus_read(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x3FFF => self.rom_bank0[address as usize],
-            0x4000..=0x7FFF => self.rom_bankn[(address - 0x4000) as usize],
-            0x8000..=0x9FFF => self.vram[(address - 0x8000) as usize],
-            0xA000..=0xBFFF => self.ext_ram[(address - 0xA000) as usize],
-            0xC000..=0xDFFF => self.wram[(address - 0xC000) as usize],
-            0xE000..=0xFDFF => self.wram[(address - 0xE000) as usize], // Echo RAM
-            0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize],
-            0xFF00..=0xFF7F => self.io_regs[(address - 0xFF00) as usize],
-            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize],
+            0x0000..=0x7FFF => match &self.boot_rom {
+                Some(boot_rom) if address < 0x0100 => boot_rom[address as usize],
+                // Game Genie codes patch what the cartridge returns
+                _ => self.cheats.patch_rom(address, self.read_rom(address)),
+            },
+            0x8000..=0x9FFF => {
+                if self.vram_blocked() {
+                    0xFF  // VRAM is in use by the PPU
+                } else {
+                    self.vram[self.vram_bank()][REGION_VRAM.offset(address)]
+                }
+            },
+            0xA000..=0xBFFF => match self.ext_ram_index(address) {
+                // MBC2 RAM is 4 bits wide; the upper nibble reads as 1s
+                Some(index) if matches!(self.mapper, Mapper::Mbc2 { .. }) => self.ext_ram[index] | 0xF0,
+                Some(index) => self.ext_ram[index],
+                None => 0xFF,  // No RAM present or RAM disabled
+            },
+            0xC000..=0xDFFF => {
+                let (bank, offset) = self.wram_location(REGION_WRAM.offset(address));
+                self.wram[bank][offset]
+            },
+            0xE000..=0xFDFF if self.echo_ram_enabled => {  // Echo RAM
+                let (bank, offset) = self.wram_location(REGION_ECHO.offset(address));
+                self.wram[bank][offset]
+            },
+            0xE000..=0xFDFF => 0xFF,  // Echo RAM disabled
+            0xFE00..=0xFE9F => {
+                if self.oam_blocked() {
+                    0xFF  // OAM is in use by the PPU
+                } else {
+                    self.oam[REGION_OAM.offset(address)]
+                }
+            },
+            // Unusable region. DMG reads 0x00 here while OAM is accessible and
+            // 0xFF while the PPU holds it. Not modelled: on DMG, reads and
+            // writes here during mode 2 also trigger the OAM corruption bug,
+            // and CGB revisions return echoes of the OAM bytes instead.
+            0xFEA0..=0xFEFF if self.oam_blocked() => 0xFF,
+            0xFEA0..=0xFEFF => 0x00,
+            0xFF00..=0xFF7F => self.read_io(address) | IO_READ_MASKS[REGION_IO.offset(address)],
+            0xFF80..=0xFFFE => self.hram[REGION_HRAM.offset(address)],
             0xFFFF => self.ie_register,
-            _ => 0xFF, // Unmapped memory returns 0xFF
+        }
+    }
+
+    /// Cartridge ROM as currently banked in at 0000-7FFF
+    fn read_rom(&self, address: u16) -> u8 {
+        let (low, high) = self.rom_banks();
+        match address {
+            0x0000..=0x3FFF => self.rom_byte(low, address as usize),
+            _ => self.rom_byte(high, REGION_ROM_BANK.offset(address)),
+        }
+    }
+
+    /// ROM banks mapped at 0000-3FFF and 4000-7FFF. MBC1 combines the 2-bit
+    /// register above the 5-bit one (4-bit on multicarts); in mode 1 the
+    /// upper bits also bank 0000-3FFF.
+    fn rom_banks(&self) -> (u16, u16) {
+        match self.mapper {
+            Mapper::Mbc1 { multicart, .. } => {
+                let shift = if multicart { 4 } else { 5 };
+                let upper = (self.ram_bank as u16) << shift;
+                let lower = self.rom_bank & ((1 << shift) - 1);
+                (if self.mbc1_mode { upper } else { 0 }, upper | lower)
+            },
+            _ => (0, self.rom_bank),
+        }
+    }
+
+    /// Force the MBC1 multicart (MBC1M) wiring on or off for ROMs loaded
+    /// from now on; None goes back to detecting it
+    pub fn force_multicart(&mut self, multicart: Option<bool>) {
+        self.forced_multicart = multicart;
+    }
+
+    /// Enable a Game Genie or GameShark code
+    pub fn add_cheat(&mut self, code: &str) -> Result<Cheat, CheatError> {
+        self.cheats.add(code)
+    }
+
+    /// Write every GameShark value into RAM (the machine does this once per frame).
+    /// Banks 0x80-0x87 target that WRAM bank at D000-DFFF.
+    pub fn apply_ram_cheats(&mut self) {
+        let patches: Vec<(u8, u16, u8)> = self.cheats.ram_patches().collect();
+        for (bank, address, value) in patches {
+            match address {
+                0xD000..=0xDFFF if bank & 0x80 != 0 => {
+                    let bank = ((bank & 0x07) as usize).max(1);
+                    self.wram[bank][(address - 0xD000) as usize] = value;
+                },
+                _ if GAMESHARK_RANGES.iter().any(|range| range.contains(&address)) => {
+                    self.write_byte(address, value)
+                },
+                _ => {},  // Not a RAM address; Cheat::parse rejects these
+            }
+        }
+    }
+
+    /// I/O register value before the fixed bits of IO_READ_MASKS are applied
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            P1 => self.joypad.read(),
+            DIV => self.timer.div(),
+            TIMA => self.timer.tima,
+            TMA => self.timer.tma,
+            TAC => self.timer.tac,
+            SB => self.serial.sb,
+            SC => self.serial.sc,
+            NR10..=0xFF3F => self.apu.read_register(address),
+            KEY1 => {
+                // Bit 7: current speed, bit 0: switch armed
+                let speed = if self.double_speed { 0x80 } else { 0x00 };
+                speed | (self.io_regs[REGION_IO.offset(address)] & 0x01)
+            },
+            BCPS..=OCPD => self.ppu.read_register(address),
+            HDMA5 => {
+                // Bit 7 clear while an HBlank DMA is active, low bits are the remaining length
+                let inactive = if self.hdma_active { 0x00 } else { 0x80 };
+                inactive | self.hdma_length
+            },
+            _ => self.io_regs[REGION_IO.offset(address)],
         }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        if let Some(counts) = &mut self.access_counts {
+            counts.record_write(address);
+        }
+        if !self.watchpoints.is_empty() && self.watchpoints.contains(&address) {
+            let old = self.bus_read(address);
+            self.watch_hit = Some(WatchHit { address, old, new: value });
+        }
+        if !self.mmio.0.is_empty() && self.mmio.write(address, value) {
+            return;
+        }
+        if self.strict && writes_read_only_bits(address, value) {
+            self.diagnose(Violation::ReadOnlyWrite, address, value);
+        }
+
         match address {
-            0x0000..=0x7FFF => (), // ROM is read-only
-            0x8000..=0x9FFF => self.vram[(address - 0x8000) as usize] = value,
-            0xA000..=0xBFFF => self.ext_ram[(address - 0xA000) as usize] = value,
-            0xC000..=0xDFFF => self.wram[(address - 0xC000) as usize] = value,
-            0xE000..=0xFDFF => self.wram[(address - 0xE000) as usize] = value, // Echo RAM
-            0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize] = value,
-            0xFF00..=0xFF7F => self.io_regs[(address - 0xFF00) as usize] = value,
-            0xFF40 => {  // LCDC
-                self.io_regs[(address - 0xFF00) as usize] = value;
-            },
-            0xFF41 => {  // STAT
+            0x0000..=0x7FFF => self.write_mapper(address, value),
+            0x8000..=0x9FFF if !self.vram_blocked() => {
+                self.vram[self.vram_bank()][REGION_VRAM.offset(address)] = value;
+            },
+            0x8000..=0x9FFF => (), // VRAM is in use by the PPU
+            0xA000..=0xBFFF => {
+                if let Some(index) = self.ext_ram_index(address) {
+                    self.ext_ram[index] = match self.mapper {
+                        Mapper::Mbc2 { .. } => value & 0x0F,
+                        _ => value,
+                    };
+                }
+            },
+            0xC000..=0xDFFF => {
+                let (bank, offset) = self.wram_location(REGION_WRAM.offset(address));
+                self.wram[bank][offset] = value;
+            },
+            0xE000..=0xFDFF if self.echo_ram_enabled => {  // Echo RAM
+                let (bank, offset) = self.wram_location(REGION_ECHO.offset(address));
+                self.wram[bank][offset] = value;
+            },
+            0xE000..=0xFDFF => (),  // Echo RAM disabled
+            0xFE00..=0xFE9F if !self.oam_blocked() => {
+                self.oam[REGION_OAM.offset(address)] = value;
+            },
+            0xFE00..=0xFE9F => (), // OAM is in use by the PPU
+            0xFEA0..=0xFEFF if self.strict => self.diagnose(Violation::UnusableWrite, address, value),
+            0xFEA0..=0xFEFF => (), // Unusable region: writes are ignored
+            LCDC => {
+                let was_enabled = self.lcd_enabled();
+                self.io_regs[REGION_IO.offset(address)] = value;
+                if was_enabled != self.lcd_enabled() {
+                    // Turning the LCD off parks it at line 0 in mode 0;
+                    // turning it back on starts a fresh frame from there
+                    self.cycles = 0;
+                    self.scanline = 0;
+                    self.mode = 0;
+                    self.io_regs[REGION_IO.offset(LY)] = 0;
+                    self.io_regs[REGION_IO.offset(STAT)] &= 0xF8;
+                }
+            },
+            STAT => {
                 // Only bits 3-6 are writable
-                let current = self.io_regs[(address - 0xFF00) as usize];
-                self.io_regs[(address - 0xFF00) as usize] = (value & 0x78) | (current & 0x87);
+                let current = self.io_regs[REGION_IO.offset(address)];
+                self.io_regs[REGION_IO.offset(address)] = (value & 0x78) | (current & 0x87);
+                if self.stat_write_bug(current) {
+                    self.request_interrupt(INT_STAT);
+                }
             },
-            0xFF44 => {  // LY is read-only
+            LY => {  // LY is read-only
                 // Reset LY when written to (this is Game Boy behavior)
                 self.scanline = 0;
-                self.io_regs[(address - 0xFF00) as usize] = 0;
+                self.io_regs[REGION_IO.offset(address)] = 0;
+            },
+            P1 => {
+                self.joypad.write(value);
+                self.sgb.write_p1(value);
             },
-            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize] = value,
+            SB => self.serial.sb = value,
+            SC => self.serial.write_control(value),
+            DIV => self.timer.reset_div(),
+            TIMA => self.timer.write_tima(value),
+            TMA => self.timer.tma = value,
+            TAC => self.timer.write_tac(value),
+            NR10..=0xFF3F => self.apu.write_register(address, value),
+            KEY1 => self.io_regs[REGION_IO.offset(address)] = value & 0x01,
+            VBK => self.io_regs[REGION_IO.offset(address)] = value & 0x01,
+            SVBK => self.io_regs[REGION_IO.offset(address)] = value & 0x07,
+            HDMA1 => self.hdma_src = (self.hdma_src & 0x00FF) | ((value as u16) << 8),
+            HDMA2 => self.hdma_src = (self.hdma_src & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3 => self.hdma_dst = (self.hdma_dst & 0x00FF) | (((value & 0x1F) as u16) << 8),
+            HDMA4 => self.hdma_dst = (self.hdma_dst & 0xFF00) | (value & 0xF0) as u16,
+            // VRAM DMA only exists on CGB; DMG ignores the register
+            HDMA5 if self.ppu.cgb_mode() => self.start_vram_dma(value),
+            BCPS..=OCPD => self.ppu.write_register(address, value),
+            BOOT => {
+                // Any write unmaps the boot ROM for good
+                self.boot_rom = None;
+                self.io_regs[REGION_IO.offset(address)] = value;
+            },
+            0xFF03..=0xFF7F => self.io_regs[REGION_IO.offset(address)] = value,
+            0xFF80..=0xFFFE => self.hram[REGION_HRAM.offset(address)] = value,
             0xFFFF => self.ie_register = value,
-            _ => (), // Ignore writes to unmapped memory
+        }
+    }
+
+    /// Writes to the ROM area go to the cartridge's bank controller
+    fn write_mapper(&mut self, address: u16, value: u8) {
+        match self.mapper {
+            Mapper::NoMbc { .. } if self.strict => self.diagnose(Violation::RomWrite, address, value),
+            Mapper::NoMbc { .. } => (), // ROM is read-only
+            Mapper::Mbc2 { .. } => {
+                // Address bit 8 selects between RAM enable and ROM bank
+                if address <= 0x3FFF {
+                    if address & 0x0100 == 0 {
+                        self.ram_enabled = (value & 0x0F) == 0x0A;
+                    } else {
+                        self.rom_bank = ((value & 0x0F) as u16).max(1);
+                    }
+                }
+            },
+            Mapper::Mbc5 { rumble, .. } => match address {
+                0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+                // 9-bit ROM bank; unlike MBC1, bank 0 can be mapped at 4000
+                0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+                0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | (((value & 0x01) as u16) << 8),
+                // On rumble carts bit 3 drives the motor instead of the RAM bank
+                0x4000..=0x5FFF => self.ram_bank = if rumble { value & 0x07 } else { value & 0x0F },
+                _ => (),
+            },
+            Mapper::Mbc1 { .. } => match address {
+                0x0000..=0x1FFF => self.ram_enabled = (value & 0x0F) == 0x0A,
+                // Bank 0 reads as 1, checked on all 5 bits even on multicarts
+                0x2000..=0x3FFF => self.rom_bank = ((value & 0x1F) as u16).max(1),
+                0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+                _ => self.mbc1_mode = value & 0x01 != 0,
+            },
+            _ => match address {
+                0x0000..=0x1FFF => self.ram_enabled = (value & 0x0F) == 0x0A, // RAM enable
+                0x4000..=0x5FFF => self.ram_bank = value & 0x0F, // RAM bank select
+                _ => (),
+            },
+        }
+    }
+
+    /// Handle a write to HDMA5. Bit 7 clear starts a general-purpose DMA that
+    /// copies (length+1)*16 bytes at once; bit 7 set arms an HBlank DMA that
+    /// copies 16 bytes at the start of every HBlank. Writing bit 7 clear while
+    /// an HBlank DMA is active cancels it instead.
+    /// Note: the CPU is not stalled during the transfer.
+    fn start_vram_dma(&mut self, value: u8) {
+        if self.hdma_active && value & 0x80 == 0 {
+            self.hdma_active = false;
+            return;
+        }
+
+        self.hdma_length = value & 0x7F;
+        if value & 0x80 != 0 {
+            self.hdma_active = true;
+        } else {
+            let blocks = self.hdma_length as u16 + 1;
+            for _ in 0..blocks {
+                self.vram_dma_block();
+            }
+            self.hdma_length = 0x7F;
+        }
+    }
+
+    /// Copy one 16-byte block from the DMA source to VRAM and advance both addresses
+    fn vram_dma_block(&mut self) {
+        let bank = self.vram_bank();
+        for i in 0..0x10 {
+            let byte = self.bus_read(self.hdma_src.wrapping_add(i));
+            let dst = ((self.hdma_dst + i) & 0x1FFF) as usize;
+            self.vram[bank][dst] = byte;
+        }
+        self.hdma_src = self.hdma_src.wrapping_add(0x10);
+        self.hdma_dst = (self.hdma_dst + 0x10) & 0x1FF0;
+    }
+
+    fn lcd_regs(&self) -> LcdRegs {
+        LcdRegs {
+            lcdc: self.io_regs[REGION_IO.offset(LCDC)],
+            scy: self.io_regs[REGION_IO.offset(SCY)],
+            scx: self.io_regs[REGION_IO.offset(SCX)],
+            bgp: self.io_regs[REGION_IO.offset(BGP)],
+            obp0: self.io_regs[REGION_IO.offset(OBP0)],
+            obp1: self.io_regs[REGION_IO.offset(OBP1)],
+            wy: self.io_regs[REGION_IO.offset(WY)],
+            wx: self.io_regs[REGION_IO.offset(WX)],
+        }
+    }
+
+    /// Draw the current line in one go (Accuracy::Fast)
+    fn render_scanline(&mut self) {
+        let sprites = self.select_sprites(self.scanline);
+        let regs = self.lcd_regs();
+        self.ppu.render_scanline(self.scanline, &self.vram, regs, &sprites);
+    }
+
+    /// Hand the current line to the pixel FIFO (Accuracy::Accurate)
+    fn start_fifo_line(&mut self) {
+        let sprites = self.select_sprites(self.scanline);
+        let regs = self.lcd_regs();
+        self.ppu.start_fifo_line(self.scanline, sprites, regs);
+    }
+
+    /// Sprites the OAM scan picks for scanline `line`, highest priority first.
+    /// LCDC bit 2 selects 8x16 sprites.
+    pub fn select_sprites(&self, line: u8) -> Vec<OamEntry> {
+        let tall = self.io_regs[REGION_IO.offset(LCDC)] & 0x04 != 0;
+        ppu::select_sprites(&self.oam, line, tall)
+    }
+
+    /// Length of mode 3 on `line` in cycles: 172, plus the SCX fine scroll
+    /// dropped at the start of the line, 6 if the window is on it, and 6-11
+    /// per sprite (the most when it starts at the beginning of a background tile)
+    pub fn mode3_length(&self, line: u8) -> u32 {
+        let lcdc = self.io_regs[REGION_IO.offset(LCDC)];
+        let scx = self.io_regs[REGION_IO.offset(SCX)];
+        let mut length = MODE3_MIN_CYCLES + (scx & 0x07) as u32;
+
+        let wy = self.io_regs[REGION_IO.offset(WY)];
+        let wx = self.io_regs[REGION_IO.offset(WX)];
+        if lcdc & 0x20 != 0 && wy <= line && wx <= 166 {
+            length += 6;
+        }
+
+        if lcdc & 0x02 != 0 {
+            for sprite in self.select_sprites(line).iter().filter(|sprite| sprite.x < 168) {
+                let offset = (sprite.x as u32 + scx as u32) % 8;
+                length += 11 - offset.min(5);
+            }
+        }
+        length
+    }
+
+    /// Run one block of an active HBlank DMA (called on entering mode 0)
+    fn hblank_dma_step(&mut self) {
+        if !self.hdma_active {
+            return;
+        }
+        self.vram_dma_block();
+        if self.hdma_length == 0 {
+            self.hdma_active = false;
+            self.hdma_length = 0x7F;
+        } else {
+            self.hdma_length -= 1;
+        }
+    }
+
+    /// LCDC bit 7: with the LCD off the PPU is stopped and memory is freely accessible
+    pub fn lcd_enabled(&self) -> bool {
+        self.io_regs[REGION_IO.offset(LCDC)] & 0x80 != 0
+    }
+
+    // DMG OAM corruption bug: while the PPU scans OAM in mode 2, putting an
+    // address in FE00-FEFF on the bus (a read, a write, or the 16-bit inc/dec
+    // unit passing through) garbles the OAM row the PPU is reading. OAM is
+    // treated as 20 rows of four 16-bit words; the PPU reads one row per
+    // M-cycle. Row 0 never corrupts. CGB is unaffected.
+    //
+    // Coverage is partial: only the write form is modelled, and only LD (HL-),A
+    // triggers it, being the one implemented instruction whose inc/dec unit
+    // can sweep through OAM. Plain reads and writes of OAM are blocked in mode
+    // 2 rather than corrupting it, and the read form (LD A,(HL+/-), POP) and
+    // the other 16-bit INC/DEC and PUSH paths need hooking as they're added.
+
+    /// Row the mode 2 scan is reading when `address` triggers the bug, if it does
+    fn oam_bug_row(&self, address: u16) -> Option<usize> {
+        if self.accuracy == Accuracy::Fast
+            || self.ppu.cgb_mode()
+            || !(0xFE00..=0xFEFF).contains(&address)
+            || !self.lcd_enabled()
+            || self.mode != 2
+        {
+            return None;
+        }
+        let row = (self.cycles / 4) as usize;
+        (1..20).contains(&row).then_some(row)
+    }
+
+    fn oam_word(&self, row: usize, word: usize) -> u16 {
+        let offset = row * 8 + word * 2;
+        u16::from_le_bytes([self.oam[offset], self.oam[offset + 1]])
+    }
+
+    fn set_oam_word(&mut self, row: usize, word: usize, value: u16) {
+        let offset = row * 8 + word * 2;
+        self.oam[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Corruption from a write or inc/dec of `address`: the row's first word
+    /// becomes ((a ^ c) & (b ^ c)) ^ c, with a its old value and b, c the first
+    /// and third words of the previous row, and the other three words are
+    /// copied from the previous row
+    pub fn oam_bug_write(&mut self, address: u16) {
+        if let Some(row) = self.oam_bug_row(address) {
+            let a = self.oam_word(row, 0);
+            let b = self.oam_word(row - 1, 0);
+            let c = self.oam_word(row - 1, 2);
+            self.set_oam_word(row, 0, ((a ^ c) & (b ^ c)) ^ c);
+            for word in 1..4 {
+                self.set_oam_word(row, word, self.oam_word(row - 1, word));
+            }
+        }
+    }
+
+    // DMG STAT write bug: for one cycle a write to STAT acts as if every
+    // interrupt source (bits 3-6) were enabled, so the write itself raises
+    // the STAT interrupt whenever a source's condition holds: HBlank, VBlank
+    // or LY=LYC. Games like Road Rash and Legend of Zerd depend on it; CGB
+    // doesn't do it.
+
+    /// True if writing STAT (currently `stat`) raises a spurious interrupt
+    fn stat_write_bug(&self, stat: u8) -> bool {
+        self.accuracy == Accuracy::Accurate
+            && !self.ppu.cgb_mode()
+            && self.lcd_enabled()
+            && (matches!(stat & 0x03, 0 | 1) || stat & 0x04 != 0)
+    }
+
+    /// True while the PPU owns OAM (mode 2 OAM scan and mode 3 pixel transfer).
+    /// Never under Accuracy::Fast.
+    fn oam_blocked(&self) -> bool {
+        self.accuracy == Accuracy::Accurate && self.lcd_enabled() && matches!(self.mode, 2 | 3)
+    }
+
+    /// True while the PPU owns VRAM (mode 3 pixel transfer). Never under Accuracy::Fast.
+    fn vram_blocked(&self) -> bool {
+        self.accuracy == Accuracy::Accurate && self.lcd_enabled() && self.mode == 3
+    }
+
+    /// VRAM bank currently selected by VBK bit 0
+    fn vram_bank(&self) -> usize {
+        (self.io_regs[REGION_IO.offset(VBK)] & 0x01) as usize
+    }
+
+    /// Read VRAM (0x8000-0x9FFF) from a specific bank regardless of VBK,
+    /// e.g. for the PPU fetching CGB background attributes from bank 1
+    pub fn read_vram_bank(&self, bank: u8, addr: u16) -> u8 {
+        self.vram[(bank & 0x01) as usize][(addr & 0x1FFF) as usize]
+    }
+
+    /// WRAM bank and offset for an offset into the 8 KB work RAM window.
+    /// The lower 4 KB is always bank 0; the upper 4 KB is the bank selected
+    /// by SVBK, where 0 selects bank 1.
+    fn wram_location(&self, offset: usize) -> (usize, usize) {
+        if offset < 0x1000 {
+            (0, offset)
+        } else {
+            let bank = (self.io_regs[REGION_IO.offset(SVBK)] & 0x07).max(1);
+            (bank as usize, offset - 0x1000)
+        }
+    }
+
+    /// Byte `offset` (0x0000-0x3FFF) of ROM bank `bank`; banks past the
+    /// end of the ROM mirror back to the start
+    fn rom_byte(&self, bank: u16, offset: usize) -> u8 {
+        let index = bank as usize * 0x4000 + offset;
+        self.rom[index % self.rom.len()]
+    }
+
+    /// Offset into `ext_ram` for an address in A000-BFFF, taking the
+    /// selected RAM bank into account. None if RAM is absent or disabled.
+    fn ext_ram_index(&self, address: u16) -> Option<usize> {
+        if self.ext_ram.is_empty() || !self.ram_enabled {
+            return None;
+        }
+        if let Mapper::Mbc2 { .. } = self.mapper {
+            // 512 entries, mirrored across the whole range
+            return Some(REGION_EXT_RAM.offset(address) & 0x01FF);
+        }
+        // MBC1 only banks RAM in mode 1
+        let bank = match self.mapper {
+            Mapper::Mbc1 { .. } if !self.mbc1_mode => 0,
+            _ => self.ram_bank as usize,
+        };
+        let offset = bank * 0x2000 + REGION_EXT_RAM.offset(address);
+        // Smaller RAM chips mirror across the address range
+        Some(offset % self.ext_ram.len())
+    }
+
+    /// Intercept CPU reads and writes in `range` with `handler` before the
+    /// normal memory map. Later registrations take precedence where ranges overlap.
+    pub fn register_mmio(&mut self, range: RangeInclusive<u16>, handler: Box<dyn MmioHandler>) {
+        self.mmio.0.insert(0, (range, handler));
+    }
+
+    /// Remove every MMIO override
+    pub fn clear_mmio(&mut self) {
+        self.mmio.0.clear();
+    }
+
+    /// Detach the MMIO overrides, e.g. to move them onto a restored snapshot
+    pub fn take_mmio(&mut self) -> MmioHandlers {
+        core::mem::take(&mut self.mmio)
+    }
+
+    pub fn set_mmio(&mut self, handlers: MmioHandlers) {
+        self.mmio = handlers;
+    }
+
+    /// Start counting reads and writes per address (from zero), or stop and
+    /// drop the counts. Off by default.
+    pub fn set_access_counting(&mut self, enabled: bool) {
+        self.access_counts = enabled.then(AccessCounts::new);
+    }
+
+    pub fn access_counts(&self) -> Option<&AccessCounts> {
+        self.access_counts.as_ref()
+    }
+
+    /// Copy of the emulated state for a save state. The ROM is shared rather
+    /// than copied; the framebuffer, host-side attachments (link cable,
+    /// audio output, MMIO handlers, access counts, strict mode warnings) and
+    /// debugger state (watchpoints, cheats) are left behind for
+    /// `adopt_host_state` to carry over on restore.
+    pub fn snapshot(&self) -> MMU {
+        let mut mmu = self.clone();
+        mmu.ppu.take_framebuffer();
+        mmu.watchpoints.clear();
+        mmu.watch_hit = None;
+        mmu.cheats = Cheats::new();
+        mmu.access_counts = None;
+        mmu.diagnostics.get_mut().clear();
+        mmu.dropped_diagnostics.set(0);
+        mmu
+    }
+
+    /// Take over what `snapshot` left behind from `live`, the bus this one
+    /// replaces, along with its debugger and emulation settings
+    pub fn adopt_host_state(&mut self, live: &mut MMU) {
+        if let Some(link) = live.serial.take_link() {
+            self.serial.set_link(link);
+        }
+        self.set_mmio(live.take_mmio());
+        self.apu.set_audio_output(live.apu.take_audio_output());
+        self.apu.set_recorder(live.apu.take_recorder());
+        self.ppu.set_framebuffer(live.ppu.take_framebuffer());
+        self.access_counts = live.access_counts.take();
+        self.diagnostics = live.diagnostics.take().into();
+        self.dropped_diagnostics = live.dropped_diagnostics.take().into();
+        self.watchpoints = core::mem::take(&mut live.watchpoints);
+        self.watch_hit = live.watch_hit.take();
+        self.cheats = core::mem::take(&mut live.cheats);
+        self.strict = live.strict;
+        self.accuracy = live.accuracy;
+        self.echo_ram_enabled = live.echo_ram_enabled;
+    }
+
+    /// Heap memory this bus owns, not counting the shared ROM image
+    pub fn heap_bytes(&self) -> usize {
+        let counts = self.access_counts.as_ref().map_or(0, |counts| (counts.reads.len() + counts.writes.len()) * 4);
+        self.ext_ram.len() + self.ppu.framebuffer().len() + counts
+    }
+
+    /// Log and keep a strict mode warning, dropping the oldest past MAX_DIAGNOSTICS
+    fn diagnose(&self, violation: Violation, address: u16, value: u8) {
+        let diagnostic = Diagnostic { violation, pc: self.instruction_pc, address, value };
+        println!("Warning: {}", diagnostic);
+        let mut diagnostics = self.diagnostics.borrow_mut();
+        if diagnostics.len() == MAX_DIAGNOSTICS {
+            diagnostics.pop_front();
+            self.dropped_diagnostics.set(self.dropped_diagnostics.get() + 1);
+        }
+        diagnostics.push_back(diagnostic);
+    }
+
+    /// Strict mode warnings since the last call, oldest first. Only the last
+    /// MAX_DIAGNOSTICS are kept; dropped_diagnostics counts the rest.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.dropped_diagnostics.set(0);
+        self.diagnostics.get_mut().drain(..).collect()
+    }
+
+    /// Warnings discarded since the last take_diagnostics to keep the log bounded
+    pub fn dropped_diagnostics(&self) -> usize {
+        self.dropped_diagnostics.get()
+    }
+
+    /// The last write to a watched address since the previous call, if any
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    /// Set a bit in the interrupt flag register (IF)
+    pub fn request_interrupt(&mut self, interrupt: u8) {
+        self.io_regs[REGION_IO.offset(IF)] |= interrupt;
+    }
+
+    /// The highest-priority interrupt both requested (IF) and enabled (IE),
+    /// as a bit number: 0 (VBlank) beats 1 (STAT), and so on up to 4 (Joypad)
+    pub fn pending_interrupt(&self) -> Option<u8> {
+        let pending = self.io_regs[REGION_IO.offset(IF)] & self.ie_register & 0x1F;
+        (pending != 0).then(|| pending.trailing_zeros() as u8)
+    }
+
+    /// Clear interrupt `bit` in IF once the CPU has started servicing it
+    pub fn acknowledge_interrupt(&mut self, bit: u8) {
+        self.io_regs[REGION_IO.offset(IF)] &= !(1 << bit);
+    }
+
+    /// Press or release a button, requesting the joypad interrupt on a press
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.joypad.set_button(button, pressed) {
+            self.request_interrupt(INT_JOYPAD);
+        }
+    }
+
+    /// Apply a whole joypad snapshot, requesting the interrupt for any new presses
+    pub fn set_joypad_state(&mut self, state: JoypadState) {
+        for button in Button::ALL {
+            self.set_button(button, state.is_pressed(button));
+        }
+    }
+
+    /// True if KEY1 bit 0 is set, so the next STOP switches CPU speed
+    pub fn speed_switch_armed(&self) -> bool {
+        self.io_regs[REGION_IO.offset(KEY1)] & 0x01 != 0
+    }
+
+    /// Toggle CGB double-speed mode and disarm the KEY1 switch bit
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.io_regs[REGION_IO.offset(KEY1)] &= !0x01;
+    }
+
+    /// Advance the clocked hardware (PPU timing, timer and APU) by `cycles`
+    /// CPU clock cycles. The CPU calls this for each memory access as an
+    /// instruction runs, so peripherals observe time passing within the
+    /// instruction. In double-speed mode the timer follows the CPU clock
+    /// while the PPU and APU keep running at normal speed.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.timer.step(cycles) {
+            self.request_interrupt(INT_TIMER);
+        }
+        self.step_serial(cycles);
+        let ppu_cycles = if self.double_speed { cycles / 2 } else { cycles };
+        self.apu.step(ppu_cycles);
+        self.update_lcd(ppu_cycles);
+    }
+
+    /// Advance the serial port, requesting the serial interrupt when a transfer completes.
+    /// The serial clock is derived from the CPU clock, so it also doubles in double speed.
+    pub fn step_serial(&mut self, cycles: u32) {
+        if self.serial.step(cycles) {
+            self.request_interrupt(INT_SERIAL);
         }
     }
 
     pub fn update_lcd(&mut self, cycles: u32) {
+        if !self.lcd_enabled() {
+            return;
+        }
         self.cycles += cycles;
 
-        if self.cycles >= 456 {  // One scanline takes 456 cycles
-            self.cycles -= 456;
+        if self.cycles >= LINE_CYCLES {
+            self.cycles -= LINE_CYCLES;
             self.scanline = (self.scanline + 1) % 154;
-            self.write_byte(LY, self.scanline);
-
-            // Mode 2: Scanning OAM - 80 cycles
-            if self.cycles <= 80 {
-                self.mode = 2;
-            }
-            // Mode 3: Drawing pixels - 172 cycles
-            else if self.cycles <= 252 {
-                self.mode = 3;
+            // Set LY directly: CPU writes to LY reset it
+            self.io_regs[REGION_IO.offset(LY)] = self.scanline;
+            if self.scanline == 144 {
+                self.ppu.end_frame();
+                self.request_interrupt(INT_VBLANK);
             }
-            // Mode 0: HBlank - 204 cycles
-            else {
-                self.mode = 0;
+        }
+
+        let fifo = self.accuracy == Accuracy::Accurate;
+        let drawing = self.scanline < 144 && self.cycles >= MODE2_CYCLES;
+
+        // The OAM scan has picked this line's sprites, so mode 3's length is known
+        if self.mode == 2 && drawing {
+            self.mode3_cycles = self.mode3_length(self.scanline);
+            if fifo {
+                self.start_fifo_line();
             }
+        }
+        // Under Accuracy::Accurate the pixel FIFO decides instead: feed it the
+        // dots of mode 3 elapsed so far, and mode 3 lasts until it has drawn the line
+        if fifo && drawing && self.mode != 0 {
+            let dots = self.cycles - MODE2_CYCLES;
+            let regs = self.lcd_regs();
+            self.ppu.run_fifo(dots, &self.vram, regs);
+            let fifo = self.ppu.fifo();
+            self.mode3_cycles = if fifo.active() { dots + 1 } else { fifo.dots() };
+        }
+
+        // Mode 1: VBlank (lines 144-153)
+        let mode = if self.scanline >= 144 {
+            1
+        }
+        // Mode 2: Scanning OAM - 80 cycles
+        else if self.cycles < MODE2_CYCLES {
+            2
+        }
+        // Mode 3: Drawing pixels - 172-295 cycles (see mode3_length and the FIFO above)
+        else if self.cycles < MODE2_CYCLES + self.mode3_cycles {
+            3
+        }
+        // Mode 0: HBlank - the rest of the line
+        else {
+            0
+        };
 
-            // Mode 1: VBlank (lines 144-153)
-            if self.scanline >= 144 {
-                self.mode = 1;
+        if mode != self.mode {
+            self.mode = mode;
+            if mode == 0 {
+                // Pixel transfer for this line is done
+                if !fifo {
+                    self.render_scanline();
+                }
+                self.hblank_dma_step();
             }
+        }
 
-            // Update LCD status register
-            let mut stat = self.read_byte(STAT) & 0xFC;  // Clear lower 2 bits
-            stat |= self.mode;
-            self.write_byte(STAT, stat);
+        // Update LCD status register (mode and LY=LYC coincidence bits
+        // are read-only to the CPU, so bypass the STAT write mask)
+        let mut stat = self.bus_read(STAT) & 0xF8;  // Clear lower 3 bits
+        stat |= self.mode;
+        if self.scanline == self.bus_read(LYC) {
+            stat |= 0x04;
         }
+        self.io_regs[REGION_IO.offset(STAT)] = stat;
+    }
+}
+
+/// MBC1M collection carts are 1MB images holding four 256KB games, each
+/// starting with its own header. Seeing bank 0's Nintendo logo again at
+/// the start of the second game gives the wiring away.
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const LOGO: core::ops::Range<usize> = 0x0104..0x0134;
+    const GAME_SIZE: usize = 0x40000;
+    if rom.len() != 4 * GAME_SIZE {
+        return false;
     }
+    let logo = &rom[LOGO];
+    logo.iter().any(|&b| b != 0x00 && b != 0xFF)
+        && rom[GAME_SIZE + LOGO.start..GAME_SIZE + LOGO.end] == *logo
 }
@@ -0,0 +1,87 @@
+//! Input recording and playback, for tool-assisted runs and deterministic
+//! regression tests.
+//!
+//! A recording is a text file with one `<frame> <buttons>` line per change
+//! of the joypad state: the frame counted from the start of the recording
+//! and the JoypadState bitmask in hex. Played back on a machine with the
+//! same ROM and RAM seed, it reproduces the run exactly.
+
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::joypad::JoypadState;
+
+/// Writes the joypad state to a recording whenever it changes
+pub struct InputRecorder {
+    file: File,
+    frame: u64,
+    last: Option<JoypadState>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &str) -> io::Result<InputRecorder> {
+        Ok(InputRecorder { file: File::create(path)?, frame: 0, last: None })
+    }
+
+    /// Log the state the frame about to run starts with
+    pub fn frame(&mut self, state: JoypadState) -> io::Result<()> {
+        if self.last != Some(state) {
+            writeln!(self.file, "{} {:02x}", self.frame, state.pressed)?;
+            self.last = Some(state);
+        }
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+/// Feeds a recording back one frame at a time
+pub struct InputPlayer {
+    events: Vec<(u64, JoypadState)>,
+    next: usize,
+    frame: u64,
+}
+
+impl InputPlayer {
+    pub fn open(path: &str) -> io::Result<InputPlayer> {
+        let text = std::fs::read_to_string(path)?;
+        InputPlayer::parse(&text).map_err(|line| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid input recording line: {}", line))
+        })
+    }
+
+    /// Parse a recording, returning the first bad line on error
+    pub fn parse(text: &str) -> Result<InputPlayer, String> {
+        let mut events = Vec::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let mut fields = line.split_whitespace();
+            let event = match (fields.next(), fields.next(), fields.next()) {
+                (Some(frame), Some(buttons), None) => frame.parse().ok().zip(u8::from_str_radix(buttons, 16).ok()),
+                _ => None,
+            };
+            match event {
+                Some((frame, pressed)) => events.push((frame, JoypadState { pressed })),
+                None => return Err(line.to_string()),
+            }
+        }
+        Ok(InputPlayer { events, next: 0, frame: 0 })
+    }
+
+    /// The state to apply before the next frame, if it changes then
+    pub fn frame(&mut self) -> Option<JoypadState> {
+        let mut state = None;
+        while let Some(&(frame, event)) = self.events.get(self.next) {
+            if frame > self.frame {
+                break;
+            }
+            state = Some(event);
+            self.next += 1;
+        }
+        self.frame += 1;
+        state
+    }
+
+    /// True once every event has been played
+    pub fn finished(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
@@ -0,0 +1,60 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// DMG/CGB refresh rate: 4194304 Hz / 70224 cycles per frame
+pub const FRAME_RATE: f64 = 4_194_304.0 / 70_224.0;
+
+/// Slowest supported speed; smaller positive speeds are clamped to it
+pub const MIN_SPEED: f32 = 0.01;
+
+/// Keeps emulation in step with wall-clock time by sleeping at the end of
+/// each frame. Only affects pacing; emulation results are identical.
+pub struct FrameLimiter {
+    pub speed: f32,       // 2.0 = fast-forward, 0.5 = slow motion
+    pub uncapped: bool,   // Turbo: never sleep
+    frame_start: Option<Instant>,
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameLimiter {
+    pub fn new() -> FrameLimiter {
+        FrameLimiter {
+            speed: 1.0,
+            uncapped: false,
+            frame_start: None,
+        }
+    }
+
+    /// Wall-clock length of one frame at the current speed, with the speed
+    /// clamped to `MIN_SPEED`. Zero when the speed isn't a positive number.
+    pub fn frame_duration(&self) -> Duration {
+        if self.speed.is_nan() || self.speed <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(1.0 / (FRAME_RATE * self.speed.max(MIN_SPEED) as f64))
+    }
+
+    /// How long to sleep after a frame that took `elapsed` to emulate
+    pub fn sleep_duration(&self, elapsed: Duration) -> Duration {
+        if self.uncapped {
+            return Duration::ZERO;
+        }
+        self.frame_duration().saturating_sub(elapsed)
+    }
+
+    /// Call once per emulated frame: sleeps for the rest of the frame time
+    pub fn wait(&mut self) {
+        if let Some(start) = self.frame_start {
+            let sleep = self.sleep_duration(start.elapsed());
+            if !sleep.is_zero() {
+                thread::sleep(sleep);
+            }
+        }
+        self.frame_start = Some(Instant::now());
+    }
+}
@@ -0,0 +1,578 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fifo::{self, BgPixel, FifoPixel, ObjPixel, PixelFifo};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// The PPU's OAM scan keeps at most this many sprites per scanline
+pub const MAX_SPRITES_PER_LINE: usize = 10;
+
+// CGB palette register addresses
+pub const BCPS: u16 = 0xFF68;  // Background palette index
+pub const BCPD: u16 = 0xFF69;  // Background palette data
+pub const OCPS: u16 = 0xFF6A;  // Sprite palette index
+pub const OCPD: u16 = 0xFF6B;  // Sprite palette data
+
+/// Color correction applied when converting CGB 15-bit colors to RGBA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorCorrection {
+    /// Raw colors, each 5-bit channel scaled linearly to 8 bits
+    None,
+    /// Approximates the CGB LCD: mixes channels and compresses the range,
+    /// which tames the oversaturated look of raw colors on modern displays
+    Cgb,
+    /// Maps luminance onto the green-tinted range of the original DMG LCD
+    Dmg,
+}
+
+/// Four RGBA colors for the DMG shades, lightest (0) to darkest (3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [[u8; 4]; 4],
+}
+
+impl Palette {
+    /// Neutral grey ramp
+    pub const GREYSCALE: Palette = Palette {
+        colors: [
+            [0xFF, 0xFF, 0xFF, 0xFF],
+            [0xAA, 0xAA, 0xAA, 0xFF],
+            [0x55, 0x55, 0x55, 0xFF],
+            [0x00, 0x00, 0x00, 0xFF],
+        ],
+    };
+
+    /// Original DMG pea-green LCD
+    pub const DMG_GREEN: Palette = Palette {
+        colors: [
+            [0x9B, 0xBC, 0x0F, 0xFF],
+            [0x8B, 0xAC, 0x0F, 0xFF],
+            [0x30, 0x62, 0x30, 0xFF],
+            [0x0F, 0x38, 0x0F, 0xFF],
+        ],
+    };
+
+    /// Game Boy Pocket's olive-grey LCD
+    pub const POCKET_GREY: Palette = Palette {
+        colors: [
+            [0xC4, 0xCF, 0xA1, 0xFF],
+            [0x8B, 0x95, 0x6D, 0xFF],
+            [0x4D, 0x53, 0x3C, 0xFF],
+            [0x1F, 0x1F, 0x1F, 0xFF],
+        ],
+    };
+
+    /// Pure black and white with widely spaced mid tones
+    pub const HIGH_CONTRAST: Palette = Palette {
+        colors: [
+            [0xFF, 0xFF, 0xFF, 0xFF],
+            [0xC0, 0xC0, 0xC0, 0xFF],
+            [0x40, 0x40, 0x40, 0xFF],
+            [0x00, 0x00, 0x00, 0xFF],
+        ],
+    };
+
+    /// User-supplied colors, lightest first
+    pub fn custom(colors: [[u8; 4]; 4]) -> Palette {
+        Palette { colors }
+    }
+
+    /// RGBA color for a DMG shade (0-3)
+    pub fn shade(&self, shade: u8) -> [u8; 4] {
+        self.colors[(shade & 0x03) as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GREYSCALE
+    }
+}
+
+/// Snapshot of the LCD I/O registers the renderer needs for one scanline
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LcdRegs {
+    pub lcdc: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub wy: u8,
+    pub wx: u8,
+}
+
+/// CGB palette memory: 8 palettes of 4 colors, 2 bytes (little-endian RGB555) per color,
+/// accessed through an index register with optional auto-increment
+#[derive(Clone)]
+struct PaletteRam {
+    data: [u8; 64],
+    index: u8,  // Bits 0-5: byte index, bit 7: auto-increment
+}
+
+impl PaletteRam {
+    fn new() -> PaletteRam {
+        PaletteRam { data: [0; 64], index: 0 }
+    }
+
+    fn read_index(&self) -> u8 {
+        self.index | 0x40  // Bit 6 is unused and reads as 1
+    }
+
+    fn write_index(&mut self, value: u8) {
+        self.index = value & 0xBF;
+    }
+
+    fn read_data(&self) -> u8 {
+        self.data[(self.index & 0x3F) as usize]
+    }
+
+    fn write_data(&mut self, value: u8) {
+        self.data[(self.index & 0x3F) as usize] = value;
+        if self.index & 0x80 != 0 {
+            self.index = 0x80 | ((self.index + 1) & 0x3F);
+        }
+    }
+
+    fn color(&self, palette: u8, color: u8) -> u16 {
+        let offset = (palette as usize & 0x07) * 8 + (color as usize & 0x03) * 2;
+        (self.data[offset] as u16) | ((self.data[offset + 1] as u16) << 8)
+    }
+}
+
+#[derive(Clone)]
+pub struct Ppu {
+    color_correction: ColorCorrection,
+    shade_palette: Palette,  // DMG shade colors
+    cgb_mode: bool,
+    bg_palettes: PaletteRam,
+    obj_palettes: PaletteRam,
+    framebuffer: Vec<u8>,  // SCREEN_WIDTH x SCREEN_HEIGHT RGBA pixels
+    frame_skip: u32,
+    frame_index: u32,  // Frames since the skip cycle started
+    frame_count: u64,
+    frame_ready: bool,  // A frame completed since take_frame_ready was last called
+    fifo: PixelFifo,
+    // Window progress for render_scanline; the FIFO keeps its own
+    window_triggered: bool,  // LY matched WY this frame
+    window_line: u8,  // Window rows drawn this frame
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu {
+            color_correction: ColorCorrection::None,
+            shade_palette: Palette::default(),
+            cgb_mode: false,
+            bg_palettes: PaletteRam::new(),
+            obj_palettes: PaletteRam::new(),
+            framebuffer: vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            frame_skip: 0,
+            frame_index: 0,
+            frame_count: 0,
+            frame_ready: false,
+            fifo: PixelFifo::new(),
+            window_triggered: false,
+            window_line: 0,
+        }
+    }
+
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.color_correction = correction;
+    }
+
+    pub fn color_correction(&self) -> ColorCorrection {
+        self.color_correction
+    }
+
+    /// Colors used for the four DMG shades
+    pub fn set_shade_palette(&mut self, palette: Palette) {
+        self.shade_palette = palette;
+    }
+
+    pub fn shade_palette(&self) -> Palette {
+        self.shade_palette
+    }
+
+    /// Render with CGB palettes and VRAM bank 1 attributes instead of BGP shades
+    pub fn set_cgb_mode(&mut self, enabled: bool) {
+        self.cgb_mode = enabled;
+    }
+
+    pub fn cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+
+    /// Render only one frame out of every `skip + 1`; the others keep their
+    /// timing but leave the framebuffer alone. 0 renders every frame.
+    pub fn set_frame_skip(&mut self, skip: u32) {
+        self.frame_skip = skip;
+        self.frame_index = 0;
+    }
+
+    pub fn frame_skip(&self) -> u32 {
+        self.frame_skip
+    }
+
+    /// True while the current frame is one frame skip leaves undrawn
+    pub fn frame_skipped(&self) -> bool {
+        self.frame_index != 0
+    }
+
+    /// Frames completed (VBlanks entered) since power-on
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// True once per completed frame: set on entering VBlank (line 144),
+    /// cleared by the call. Poll it to know when to present the framebuffer.
+    pub fn take_frame_ready(&mut self) -> bool {
+        core::mem::take(&mut self.frame_ready)
+    }
+
+    /// Called on entering VBlank to move on to the next frame
+    pub fn end_frame(&mut self) {
+        self.frame_count += 1;
+        self.frame_ready = true;
+        self.frame_index = if self.frame_index >= self.frame_skip { 0 } else { self.frame_index + 1 };
+    }
+
+    /// RGBA pixels, row-major, SCREEN_WIDTH x SCREEN_HEIGHT
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Detach the framebuffer, e.g. to move it onto a restored snapshot
+    pub fn take_framebuffer(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.framebuffer)
+    }
+
+    /// Attach a framebuffer. One of the wrong size (e.g. the empty one a
+    /// snapshot carries) is replaced by a blank screen so rendering can't
+    /// index past its end.
+    pub fn set_framebuffer(&mut self, framebuffer: Vec<u8>) {
+        self.framebuffer = if framebuffer.len() == SCREEN_WIDTH * SCREEN_HEIGHT * 4 {
+            framebuffer
+        } else {
+            vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4]
+        };
+    }
+
+    // region: CGB palette registers
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            BCPS => self.bg_palettes.read_index(),
+            BCPD => self.bg_palettes.read_data(),
+            OCPS => self.obj_palettes.read_index(),
+            OCPD => self.obj_palettes.read_data(),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            BCPS => self.bg_palettes.write_index(value),
+            BCPD => self.bg_palettes.write_data(value),
+            OCPS => self.obj_palettes.write_index(value),
+            OCPD => self.obj_palettes.write_data(value),
+            _ => (),
+        }
+    }
+
+    /// 15-bit color `color` (0-3) of CGB background palette `palette` (0-7)
+    pub fn bg_palette_color(&self, palette: u8, color: u8) -> u16 {
+        self.bg_palettes.color(palette, color)
+    }
+
+    /// 15-bit color `color` (0-3) of CGB sprite palette `palette` (0-7)
+    pub fn obj_palette_color(&self, palette: u8, color: u8) -> u16 {
+        self.obj_palettes.color(palette, color)
+    }
+    // endregion
+
+    // region: Rendering
+    /// Render scanline `line` into the framebuffer in one go, with `sprites`
+    /// as picked by select_sprites. Background, window and sprite pixels are
+    /// mixed the same way as in the pixel FIFO. On CGB, each map entry's
+    /// attribute byte in VRAM bank 1 selects the palette (bits 0-2), tile
+    /// bank (bit 3) and flips (bits 5-6).
+    pub fn render_scanline(&mut self, line: u8, vram: &[[u8; 0x2000]; 2], regs: LcdRegs, sprites: &[OamEntry]) {
+        if line == 0 {
+            self.window_triggered = false;
+            self.window_line = 0;
+        }
+        if regs.lcdc & 0x20 != 0 && regs.wy == line {
+            self.window_triggered = true;
+        }
+        if line as usize >= SCREEN_HEIGHT || self.frame_skipped() {
+            return;
+        }
+
+        // The window covers the line from WX-7 to the right edge
+        let window_x = (self.window_triggered && regs.lcdc & 0x20 != 0 && regs.wx <= 166).then(|| regs.wx.saturating_sub(7));
+
+        // Sprite pixels, in the order the FIFO would merge them
+        let mut objs = [ObjPixel::default(); SCREEN_WIDTH];
+        if regs.lcdc & 0x02 != 0 {
+            for sprite in sprites {
+                let colors = sprite.row(line, regs.lcdc & 0x04 != 0, vram, self.cgb_mode);
+                for (col, &color) in colors.iter().enumerate() {
+                    let x = sprite.x as usize + col;
+                    if !(8..SCREEN_WIDTH + 8).contains(&x) {
+                        continue;
+                    }
+                    let slot = &mut objs[x - 8];
+                    if slot.loses_to(sprite, color, self.cgb_mode) {
+                        *slot = ObjPixel { color, attrs: sprite.attrs, index: sprite.index };
+                    }
+                }
+            }
+        }
+
+        for (x, obj) in objs.iter().enumerate() {
+            let bg = match window_x {
+                Some(start) if x as u8 >= start => self.map_pixel(vram, regs.lcdc, 0x40, x as u8 - start, self.window_line),
+                _ => self.map_pixel(vram, regs.lcdc, 0x08, (x as u8).wrapping_add(regs.scx), line.wrapping_add(regs.scy)),
+            };
+            let pixel = fifo::mix(bg, Some(*obj), regs, self.cgb_mode);
+            let rgba = self.pixel_rgba(pixel, regs);
+            let offset = (line as usize * SCREEN_WIDTH + x) * 4;
+            self.framebuffer[offset..offset + 4].copy_from_slice(&rgba);
+        }
+        if window_x.is_some() {
+            self.window_line = self.window_line.wrapping_add(1);
+        }
+    }
+
+    /// Pixel (`x`, `y`) of the tile map LCDC bit `map_bit` selects
+    fn map_pixel(&self, vram: &[[u8; 0x2000]; 2], lcdc: u8, map_bit: u8, x: u8, y: u8) -> BgPixel {
+        let map_base: usize = if lcdc & map_bit != 0 { 0x1C00 } else { 0x1800 };
+        let map_addr = map_base + (y as usize / 8) * 32 + (x as usize / 8);
+        let tile = vram[0][map_addr];
+        let attrs = if self.cgb_mode { vram[1][map_addr] } else { 0 };
+
+        let mut row = (y % 8) as usize;
+        let mut col = (x % 8) as usize;
+        if attrs & 0x40 != 0 {
+            row = 7 - row;
+        }
+        if attrs & 0x20 != 0 {
+            col = 7 - col;
+        }
+
+        let bank = ((attrs >> 3) & 0x01) as usize;
+        let addr = tile_data_address(lcdc, tile) + row * 2;
+        let color = decode_tile_row(vram[bank][addr], vram[bank][addr + 1])[col];
+        BgPixel { color, attrs }
+    }
+
+    /// Start drawing `line` with the pixel FIFO (entering mode 3)
+    pub fn start_fifo_line(&mut self, line: u8, sprites: Vec<OamEntry>, regs: LcdRegs) {
+        self.fifo.start_line(line, sprites, regs);
+    }
+
+    /// Run the pixel FIFO until it has spent `dots` dots in mode 3 on the
+    /// current line or has drawn the whole line. `regs` are the registers
+    /// as they are now, so changes between calls affect the rest of the line.
+    pub fn run_fifo(&mut self, dots: u32, vram: &[[u8; 0x2000]; 2], regs: LcdRegs) {
+        while self.fifo.active() && self.fifo.dots() < dots {
+            let Some((x, pixel)) = self.fifo.tick(vram, regs, self.cgb_mode) else {
+                continue;
+            };
+            if self.fifo.line() as usize >= SCREEN_HEIGHT || self.frame_skipped() {
+                continue;
+            }
+            let rgba = self.pixel_rgba(pixel, regs);
+            let offset = (self.fifo.line() as usize * SCREEN_WIDTH + x as usize) * 4;
+            self.framebuffer[offset..offset + 4].copy_from_slice(&rgba);
+        }
+    }
+
+    /// Draw whatever is left of the current FIFO line
+    pub fn finish_fifo_line(&mut self, vram: &[[u8; 0x2000]; 2], regs: LcdRegs) {
+        self.run_fifo(u32::MAX, vram, regs);
+    }
+
+    pub fn fifo(&self) -> &PixelFifo {
+        &self.fifo
+    }
+
+    /// Color of a mixed pixel through the CGB palettes or the DMG palette registers
+    fn pixel_rgba(&self, pixel: FifoPixel, regs: LcdRegs) -> [u8; 4] {
+        match pixel {
+            FifoPixel::Blank => self.shade_palette.shade(0),
+            FifoPixel::Background { color, palette } if self.cgb_mode => {
+                self.rgb555_to_rgba(self.bg_palettes.color(palette, color))
+            },
+            FifoPixel::Background { color, .. } => self.shade_palette.shade((regs.bgp >> (color * 2)) & 0x03),
+            FifoPixel::Sprite { color, palette } if self.cgb_mode => {
+                self.rgb555_to_rgba(self.obj_palettes.color(palette, color))
+            },
+            FifoPixel::Sprite { color, palette } => {
+                let obp = if palette == 0 { regs.obp0 } else { regs.obp1 };
+                self.shade_palette.shade((obp >> (color * 2)) & 0x03)
+            },
+        }
+    }
+    // endregion
+
+    /// Convert a CGB color (bits 0-4 red, 5-9 green, 10-14 blue) to RGBA
+    /// using the selected color correction
+    pub fn rgb555_to_rgba(&self, color: u16) -> [u8; 4] {
+        let r = (color & 0x1F) as u32;
+        let g = ((color >> 5) & 0x1F) as u32;
+        let b = ((color >> 10) & 0x1F) as u32;
+
+        match self.color_correction {
+            ColorCorrection::None => {
+                // Replicate the top bits so 0x1F maps to 0xFF
+                let scale = |c: u32| ((c << 3) | (c >> 2)) as u8;
+                [scale(r), scale(g), scale(b), 0xFF]
+            },
+            ColorCorrection::Cgb => {
+                // Channel mixing curve from byuu's CGB color emulation
+                let red = (r * 26 + g * 4 + b * 2).min(960) >> 2;
+                let green = (g * 24 + b * 8).min(960) >> 2;
+                let blue = (r * 6 + g * 4 + b * 22).min(960) >> 2;
+                [red as u8, green as u8, blue as u8, 0xFF]
+            },
+            ColorCorrection::Dmg => {
+                // Luminance (0-31 scale, weights sum to 16) blended between the
+                // darkest and lightest DMG LCD shades
+                const DARK: [u32; 3] = [0x0F, 0x38, 0x0F];
+                const LIGHT: [u32; 3] = [0x9B, 0xBC, 0x0F];
+                let luma = (r * 5 + g * 9 + b * 2) / 16;
+                let mix = |i: usize| ((DARK[i] * (31 - luma) + LIGHT[i] * luma) / 31) as u8;
+                [mix(0), mix(1), mix(2), 0xFF]
+            },
+        }
+    }
+}
+
+/// Decode one row of a 2bpp tile into shade indices (0-3), leftmost pixel first.
+/// `low` holds bit 0 of each pixel and `high` bit 1, with pixel 0 in bit 7.
+pub fn decode_tile_row(low: u8, high: u8) -> [u8; 8] {
+    let mut row = [0; 8];
+    for (col, pixel) in row.iter_mut().enumerate() {
+        let bit = 7 - col;
+        *pixel = (((high >> bit) & 0x01) << 1) | ((low >> bit) & 0x01);
+    }
+    row
+}
+
+/// Decode a whole 8x8 tile (16 bytes, two bytes per row) into shade indices, indexed [row][col]
+pub fn decode_tile(bytes: &[u8; 16]) -> [[u8; 8]; 8] {
+    let mut tile = [[0; 8]; 8];
+    for (row, pair) in tile.iter_mut().zip(bytes.chunks_exact(2)) {
+        *row = decode_tile_row(pair[0], pair[1]);
+    }
+    tile
+}
+
+/// One 4-byte OAM sprite entry. `y` and `x` are the raw register values
+/// (the sprite's top-left corner is at (x - 8, y - 16) on screen).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OamEntry {
+    pub index: u8,  // Position in OAM (0-39)
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attrs: u8,
+}
+
+impl OamEntry {
+    /// Decode sprite `index` from its four OAM bytes (Y, X, tile, attributes)
+    pub fn from_bytes(index: u8, bytes: [u8; 4]) -> OamEntry {
+        OamEntry {
+            index,
+            y: bytes[0],
+            x: bytes[1],
+            tile: bytes[2],
+            attrs: bytes[3],
+        }
+    }
+
+    /// True if any of the sprite's pixels fall inside the 160x144 screen
+    pub fn on_screen(&self, tall: bool) -> bool {
+        let height = if tall { 16 } else { 8 };
+        let top = self.y as i16 - 16;
+        let left = self.x as i16 - 8;
+        top + height > 0 && top < SCREEN_HEIGHT as i16 && left + 8 > 0 && left < SCREEN_WIDTH as i16
+    }
+
+    /// Attribute bit 7: drawn behind background colors 1-3
+    pub fn behind_background(&self) -> bool {
+        self.attrs & 0x80 != 0
+    }
+
+    pub fn y_flip(&self) -> bool {
+        self.attrs & 0x40 != 0
+    }
+
+    pub fn x_flip(&self) -> bool {
+        self.attrs & 0x20 != 0
+    }
+
+    /// DMG palette: 0 for OBP0, 1 for OBP1
+    pub fn dmg_palette(&self) -> u8 {
+        (self.attrs >> 4) & 0x01
+    }
+
+    /// Colors (0-3) of the sprite's row on scanline `line`, leftmost
+    /// first, with flips applied. On CGB attribute bit 3 picks the VRAM bank.
+    pub fn row(&self, line: u8, tall: bool, vram: &[[u8; 0x2000]; 2], cgb: bool) -> [u8; 8] {
+        let height: u8 = if tall { 16 } else { 8 };
+        let mut row = line.wrapping_sub(self.y.wrapping_sub(16)) % height;
+        if self.y_flip() {
+            row = height - 1 - row;
+        }
+        let tile = if tall { self.tile & 0xFE } else { self.tile };
+        let bank = if cgb { ((self.attrs >> 3) & 0x01) as usize } else { 0 };
+        let addr = tile as usize * 16 + row as usize * 2;
+        let mut colors = decode_tile_row(vram[bank][addr], vram[bank][addr + 1]);
+        if self.x_flip() {
+            colors.reverse();
+        }
+        colors
+    }
+}
+
+/// Sprites drawn on scanline `line`, highest priority first.
+/// The first MAX_SPRITES_PER_LINE entries in OAM order whose rows cover the
+/// line are kept (off-screen X still uses up a slot), then ordered the DMG
+/// way: lower X wins, with the lower OAM index breaking ties.
+pub fn select_sprites(oam: &[u8], line: u8, tall: bool) -> Vec<OamEntry> {
+    let height: i16 = if tall { 16 } else { 8 };
+    let mut sprites: Vec<OamEntry> = oam
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(index, bytes)| OamEntry::from_bytes(index as u8, [bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .filter(|sprite| {
+            let top = sprite.y as i16 - 16;
+            (top..top + height).contains(&(line as i16))
+        })
+        .take(MAX_SPRITES_PER_LINE)
+        .collect();
+
+    // Stable sort keeps OAM order among equal X
+    sprites.sort_by_key(|sprite| sprite.x);
+    sprites
+}
+
+/// VRAM offset of a tile's data. LCDC bit 4 selects unsigned indexing from
+/// 0x8000, otherwise tiles are signed offsets from 0x9000.
+pub(crate) fn tile_data_address(lcdc: u8, tile: u8) -> usize {
+    if lcdc & 0x10 != 0 {
+        tile as usize * 16
+    } else {
+        (0x1000 + (tile as i8 as isize) * 16) as usize
+    }
+}
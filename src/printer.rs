@@ -0,0 +1,258 @@
+//! Game Boy Printer on the link port.
+//!
+//! Packets from the Game Boy are `88 33`, command, compression flag,
+//! 16-bit little-endian data length, data, 16-bit checksum (the sum of the
+//! command through the data), then two bytes during which the printer
+//! answers 0x81 (it's alive) and its status. Data packets carry 2bpp tiles,
+//! 20 to a 160-pixel row; a print command turns everything received since
+//! the last print into an image.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use image::{ColorType, ImageFormat};
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use crate::ppu::{decode_tile_row, Palette};
+use crate::serial::SerialLink;
+
+const CMD_INIT: u8 = 0x01;
+const CMD_PRINT: u8 = 0x02;
+const CMD_DATA: u8 = 0x04;
+const CMD_STATUS: u8 = 0x0F;
+
+// Status bits
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_UNPROCESSED: u8 = 0x08;
+
+/// A printout: `width` x `height` RGBA pixels, row-major
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Printer RAM: enough for a 160x144 picture
+const BUFFER_SIZE: usize = 0x2000;
+const TILES_PER_ROW: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+pub struct GbPrinter {
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,  // Sum of the bytes received so far
+    expected_checksum: u16,
+    status: u8,
+    buffer: Vec<u8>,  // Decompressed tile data waiting to be printed
+    images: Vec<Image>,
+    #[cfg(feature = "std")]
+    output_dir: Option<PathBuf>,
+}
+
+impl Default for GbPrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GbPrinter {
+    pub fn new() -> GbPrinter {
+        GbPrinter {
+            state: State::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            expected_checksum: 0,
+            status: 0,
+            buffer: Vec::new(),
+            images: Vec::new(),
+            #[cfg(feature = "std")]
+            output_dir: None,
+        }
+    }
+
+    /// Also save every printout as `print_NNN.png` in `dir`
+    #[cfg(feature = "std")]
+    pub fn save_to(mut self, dir: impl Into<PathBuf>) -> GbPrinter {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Every image printed so far, oldest first
+    pub fn images(&self) -> &[Image] {
+        &self.images
+    }
+
+    fn receive(&mut self, byte: u8) -> u8 {
+        match self.state {
+            State::Magic1 => {
+                if byte == 0x88 {
+                    self.state = State::Magic2;
+                }
+            },
+            State::Magic2 => self.state = if byte == 0x33 { State::Command } else { State::Magic1 },
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                self.state = State::Compression;
+            },
+            State::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.state = State::LengthLow;
+            },
+            State::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.state = State::LengthHigh;
+            },
+            State::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                self.state = if self.length == 0 { State::ChecksumLow } else { State::Data };
+            },
+            State::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.length as usize {
+                    self.state = State::ChecksumLow;
+                }
+            },
+            State::ChecksumLow => {
+                self.expected_checksum = byte as u16;
+                self.state = State::ChecksumHigh;
+            },
+            State::ChecksumHigh => {
+                self.expected_checksum |= (byte as u16) << 8;
+                if self.expected_checksum == self.checksum {
+                    self.status &= !STATUS_CHECKSUM_ERROR;
+                    self.run_command();
+                } else {
+                    self.status |= STATUS_CHECKSUM_ERROR;
+                }
+                self.state = State::Alive;
+            },
+            State::Alive => {
+                self.state = State::Status;
+                return 0x81;
+            },
+            State::Status => {
+                self.state = State::Magic1;
+                return self.status;
+            },
+        }
+        0x00
+    }
+
+    fn run_command(&mut self) {
+        match self.command {
+            CMD_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            },
+            CMD_DATA => {
+                let data = core::mem::take(&mut self.data);
+                if self.compressed {
+                    decompress(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+                self.status |= STATUS_UNPROCESSED;
+            },
+            CMD_PRINT => {
+                // Data: sheets, margins, palette, exposure
+                let palette = match self.data.get(2) {
+                    Some(&0) | None => 0xE4,  // 0 means the default palette
+                    Some(&palette) => palette,
+                };
+                let image = render(&self.buffer, palette);
+                self.buffer.clear();
+                self.status &= !STATUS_UNPROCESSED;
+                #[cfg(feature = "std")]
+                self.save(&image);
+                self.images.push(image);
+            },
+            CMD_STATUS => (),
+            _ => (),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn save(&self, image: &Image) {
+        if let Some(dir) = &self.output_dir {
+            let path = dir.join(format!("print_{:03}.png", self.images.len()));
+            let (width, height) = (image.width, image.height);
+            let saved = image::save_buffer_with_format(&path, &image.pixels, width, height, ColorType::Rgba8, ImageFormat::Png);
+            if let Err(e) = saved {
+                println!("Failed to save printout {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+impl SerialLink for GbPrinter {
+    /// The Game Boy always drives the clock; the printer only answers
+    fn exchange(&mut self, out: u8) -> u8 {
+        self.receive(out)
+    }
+}
+
+/// Printer RLE: a byte with bit 7 set repeats the next byte (byte & 0x7F) + 2
+/// times; otherwise (byte + 1) literal bytes follow
+fn decompress(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            let Some(&byte) = data.get(i) else { break };
+            out.extend(core::iter::repeat_n(byte, (control & 0x7F) as usize + 2));
+            i += 1;
+        } else {
+            let end = (i + control as usize + 1).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+}
+
+/// Lay out 16-byte tiles 20 to a row as a 160-pixel-wide RGBA image
+fn render(tiles: &[u8], palette: u8) -> Image {
+    let rows = tiles.len() / 16 / TILES_PER_ROW;
+    let width = TILES_PER_ROW * 8;
+    let height = rows * 8;
+    let shades = Palette::GREYSCALE;
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for tile_x in 0..TILES_PER_ROW {
+            let tile = ((y / 8) * TILES_PER_ROW + tile_x) * 16;
+            let row = tile + (y % 8) * 2;
+            for color in decode_tile_row(tiles[row], tiles[row + 1]) {
+                pixels.extend_from_slice(&shades.shade((palette >> (color * 2)) & 0x03));
+            }
+        }
+    }
+    Image { width: width as u32, height: height as u32, pixels }
+}
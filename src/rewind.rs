@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+
+use crate::machine::SaveState;
+
+/// Memory the snapshots of a rewind buffer may take up by default
+pub const DEFAULT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Ring of save states taken every `interval` frames, oldest dropped first.
+/// A snapshot holds the RAM, VRAM and registers but shares the ROM, so it
+/// comes to about 50 KB plus the cartridge RAM: the default of 10 seconds
+/// at one snapshot every 6 frames takes around 5 MB. On top of the snapshot
+/// count, the buffer is capped at a memory limit (`DEFAULT_MEMORY_LIMIT`
+/// unless changed), dropping the oldest snapshots to stay under it.
+pub struct RewindBuffer {
+    interval: u32,          // Frames between snapshots
+    capacity: usize,        // Maximum snapshots kept
+    memory_limit: usize,    // Maximum bytes kept
+    memory_used: usize,     // Bytes taken by `snapshots`
+    frames: u32,            // Frames since the last snapshot
+    snapshots: VecDeque<SaveState>,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new(6, 10)
+    }
+}
+
+impl RewindBuffer {
+    /// Snapshot every `interval` frames, keeping up to `seconds` of history
+    pub fn new(interval: u32, seconds: u32) -> RewindBuffer {
+        let interval = interval.max(1);
+        let capacity = ((seconds * 60) / interval).max(1) as usize;
+        RewindBuffer {
+            interval,
+            capacity,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            memory_used: 0,
+            frames: 0,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Bytes the snapshots currently take up
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Cap the snapshots at `bytes`, dropping the oldest if already over.
+    /// The newest snapshot is always kept.
+    pub fn set_memory_limit(&mut self, bytes: usize) {
+        self.memory_limit = bytes;
+        self.trim();
+    }
+
+    /// Called once per frame; stores `snapshot()` when the interval is reached
+    pub fn record<F: FnOnce() -> SaveState>(&mut self, snapshot: F) {
+        if self.frames == 0 {
+            let snapshot = snapshot();
+            self.memory_used += snapshot.size_bytes();
+            self.snapshots.push_back(snapshot);
+            self.trim();
+        }
+        self.frames = (self.frames + 1) % self.interval;
+    }
+
+    /// Drop the oldest snapshots until both limits hold
+    fn trim(&mut self) {
+        while self.snapshots.len() > self.capacity
+            || (self.memory_used > self.memory_limit && self.snapshots.len() > 1)
+        {
+            if let Some(oldest) = self.snapshots.pop_front() {
+                self.memory_used -= oldest.size_bytes();
+            }
+        }
+    }
+
+    /// Drop every snapshot, e.g. after switching ROMs
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.memory_used = 0;
+        self.snapshots.clear();
+    }
+
+    /// Remove and return the most recent snapshot
+    pub fn pop(&mut self) -> Option<SaveState> {
+        self.frames = 0;
+        let snapshot = self.snapshots.pop_back()?;
+        self.memory_used -= snapshot.size_bytes();
+        Some(snapshot)
+    }
+}
@@ -0,0 +1,42 @@
+//! Seedable pseudo-random numbers (xorshift64*) for power-on state, so
+//! runs only differ when they are given different seeds.
+
+/// Seed used when none is given
+pub const DEFAULT_SEED: u64 = 0x4442_4752_5553_5400;
+
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(DEFAULT_SEED)
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift gets stuck at zero, so scramble the seed away from it
+        let state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        Rng { state: if state == 0 { 0x9E37_79B9_7F4A_7C15 } else { state } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// The high bits are the most random ones
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        for byte in bytes {
+            *byte = self.next_u8();
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+// Serial register addresses
+pub const SB: u16 = 0xFF01;  // Serial transfer data
+pub const SC: u16 = 0xFF02;  // Serial transfer control
+
+/// The other end of a link cable.
+///
+/// The side driving the clock (SC bit 0 set) calls `exchange` when it
+/// starts a transfer: its byte goes out and the peer's byte comes back.
+/// The side waiting on the external clock calls `poll` on every step with
+/// its own SB; once the master has sent a byte it is returned and `out`
+/// has been handed back as the reply.
+pub trait SerialLink {
+    fn exchange(&mut self, out: u8) -> u8;
+
+    fn poll(&mut self, out: u8) -> Option<u8> {
+        let _ = out;
+        None
+    }
+}
+
+/// Link cable between two emulator instances over TCP.
+///
+/// Handshake, one transfer at a time: the master writes its byte tagged
+/// with a sequence number, and the slave answers with exactly one byte
+/// (its SB) under the same tag as soon as it polls and finds the master's
+/// byte waiting. If no answer arrives within the timeout, the master reads
+/// 0xFF as if no cable were connected; the late answer is recognised by
+/// its stale tag and skipped by the next transfer.
+#[cfg(feature = "std")]
+pub struct TcpLink {
+    stream: TcpStream,
+    sequence: u8,  // Tag of the master's last transfer
+    partial: Option<u8>,  // First half of a message cut off by a timeout
+}
+
+#[cfg(feature = "std")]
+impl TcpLink {
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Wait for a peer to connect to `addr`
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<TcpLink> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        TcpLink::from_stream(stream)
+    }
+
+    /// Connect to a peer listening on `addr`
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpLink> {
+        TcpLink::from_stream(TcpStream::connect(addr)?)
+    }
+
+    fn from_stream(stream: TcpStream) -> io::Result<TcpLink> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(Self::TIMEOUT))?;
+        Ok(TcpLink { stream, sequence: 0, partial: None })
+    }
+
+    /// Read one (tag, byte) message, keeping half of one for the next call
+    /// if the read times out in between
+    fn read_message(&mut self) -> Option<[u8; 2]> {
+        let mut message = [0; 2];
+        let mut filled = 0;
+        if let Some(byte) = self.partial.take() {
+            message[0] = byte;
+            filled = 1;
+        }
+        while filled < 2 {
+            match self.stream.read(&mut message[filled..]) {
+                Ok(read) if read > 0 => filled += read,
+                _ => {
+                    if filled == 1 {
+                        self.partial = Some(message[0]);
+                    }
+                    return None;
+                },
+            }
+        }
+        Some(message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl SerialLink for TcpLink {
+    fn exchange(&mut self, out: u8) -> u8 {
+        self.sequence = self.sequence.wrapping_add(1);
+        if self.stream.write_all(&[self.sequence, out]).is_err() {
+            return 0xFF;
+        }
+        // Answers to earlier transfers that timed out carry an older tag
+        while let Some([sequence, reply]) = self.read_message() {
+            if sequence == self.sequence {
+                return reply;
+            }
+        }
+        0xFF
+    }
+
+    fn poll(&mut self, out: u8) -> Option<u8> {
+        self.stream.set_nonblocking(true).ok()?;
+        let message = self.read_message();
+        self.stream.set_nonblocking(false).ok()?;
+        let [sequence, incoming] = message?;
+        self.stream.write_all(&[sequence, out]).ok()?;
+        Some(incoming)
+    }
+}
+
+/// Link port. A transfer started with the internal clock shifts SB out one
+/// bit per serial clock while shifting in the peer's byte (all 1s with no
+/// link attached), and requests the serial interrupt after 8 bits. With the
+/// external clock selected the transfer waits for the peer to clock it.
+pub struct Serial {
+    pub sb: u8,
+    pub sc: u8,
+    bits_remaining: u8,    // Bits left in the current transfer
+    clock_cycles: u32,     // Cycles accumulated towards the next serial clock
+    outgoing: u8,          // Byte being sent, captured when the transfer starts
+    incoming: u8,          // Byte being received from the peer
+    pub output: Vec<u8>,   // Every byte sent so far (test ROM output)
+    link: Option<Box<dyn SerialLink>>,
+}
+
+// Snapshots carry the emulated state only: the link cable stays with the
+// live serial port (see Machine::load_state)
+impl Clone for Serial {
+    fn clone(&self) -> Self {
+        Serial {
+            sb: self.sb,
+            sc: self.sc,
+            bits_remaining: self.bits_remaining,
+            clock_cycles: self.clock_cycles,
+            outgoing: self.outgoing,
+            incoming: self.incoming,
+            output: self.output.clone(),
+            link: None,
+        }
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            sb: 0,
+            sc: 0,
+            bits_remaining: 0,
+            clock_cycles: 0,
+            outgoing: 0,
+            incoming: 0xFF,
+            output: Vec::new(),
+            link: None,
+        }
+    }
+
+    /// Attach a link cable. Without one, transfers receive 0xFF.
+    pub fn set_link(&mut self, link: Box<dyn SerialLink>) {
+        self.link = Some(link);
+    }
+
+    /// Detach the link cable, e.g. to move it onto a restored snapshot
+    pub fn take_link(&mut self) -> Option<Box<dyn SerialLink>> {
+        self.link.take()
+    }
+
+    /// SC: bit 7 starts a transfer, bit 1 selects the CGB fast clock, bit 0 the internal clock
+    pub fn write_control(&mut self, value: u8) {
+        self.sc = value;
+        if value & 0x80 != 0 {
+            self.bits_remaining = 8;
+            self.clock_cycles = 0;
+            self.outgoing = self.sb;
+            self.incoming = 0xFF;
+            if value & 0x01 != 0 {
+                if let Some(link) = &mut self.link {
+                    self.incoming = link.exchange(self.sb);
+                }
+            }
+        }
+    }
+
+    /// True while a transfer is in progress (SC bit 7)
+    pub fn transferring(&self) -> bool {
+        self.sc & 0x80 != 0
+    }
+
+    /// Cycles per serial clock: 8192 Hz, or 262144 Hz with the CGB fast clock
+    fn clock_period(&self) -> u32 {
+        if self.sc & 0x02 != 0 {
+            16
+        } else {
+            512
+        }
+    }
+
+    /// Advance the serial clock by `cycles` clock cycles.
+    /// Returns true when a transfer completes and the serial interrupt should be requested.
+    pub fn step(&mut self, cycles: u32) -> bool {
+        if !self.transferring() {
+            return false;
+        }
+
+        // External clock: the peer completes the whole byte at once
+        if self.sc & 0x01 == 0 {
+            let received = match &mut self.link {
+                Some(link) => link.poll(self.sb),
+                None => None,
+            };
+            return match received {
+                Some(byte) => {
+                    self.sb = byte;
+                    self.finish_transfer();
+                    true
+                },
+                None => false,
+            };
+        }
+
+        self.clock_cycles += cycles;
+        let period = self.clock_period();
+        while self.clock_cycles >= period {
+            self.clock_cycles -= period;
+            self.bits_remaining -= 1;
+            let bit = (self.incoming >> self.bits_remaining) & 0x01;
+            self.sb = (self.sb << 1) | bit;
+            if self.bits_remaining == 0 {
+                self.finish_transfer();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn finish_transfer(&mut self) {
+        self.sc &= 0x7F;
+        self.output.push(self.outgoing);
+    }
+}
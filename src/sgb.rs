@@ -0,0 +1,90 @@
+//! Super Game Boy command packets.
+//!
+//! Games talk to the SGB by pulsing the P14/P15 select lines of the joypad
+//! register. Pulling both low resets the receiver; after that each bit is
+//! one line pulled low and released (P14 low for 0, P15 low for 1), least
+//! significant bit of each byte first. 128 bits make a 16-byte packet, which
+//! ends with a 0 stop bit. The first byte of the first packet holds the
+//! command (bits 3-7) and how many packets it spans (bits 0-2).
+//!
+//! There's no SGB emulation here: the packets are only captured, so the
+//! palette and border commands a game sends can be inspected.
+
+use alloc::vec::Vec;
+
+pub const PACKET_BYTES: usize = 16;
+const PACKET_BITS: usize = PACKET_BYTES * 8;
+
+/// One 16-byte packet as sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SgbPacket(pub [u8; PACKET_BYTES]);
+
+impl SgbPacket {
+    /// Command code (PAL01 = 0x00, MLT_REQ = 0x11, ...). Only meaningful
+    /// for the first packet of a command.
+    pub fn command(&self) -> u8 {
+        self.0[0] >> 3
+    }
+
+    /// Packets the command spans, this one included
+    pub fn length(&self) -> u8 {
+        self.0[0] & 0x07
+    }
+}
+
+/// Assembles packets from the values written to P1
+#[derive(Debug, Clone, Default)]
+pub struct SgbPackets {
+    bits: Option<usize>,  // Bits received since the reset pulse, None when idle
+    released: bool,  // Both lines went high since the last pulse
+    data: [u8; PACKET_BYTES],
+    packets: Vec<SgbPacket>,
+}
+
+impl SgbPackets {
+    pub fn new() -> SgbPackets {
+        SgbPackets::default()
+    }
+
+    /// Observe a write to P1
+    pub fn write_p1(&mut self, value: u8) {
+        match value & 0x30 {
+            0x00 => {
+                self.bits = Some(0);
+                self.released = false;
+                self.data = [0; PACKET_BYTES];
+            },
+            0x30 => self.released = true,
+            lines => {
+                let Some(bits) = self.bits else { return };
+                if !self.released {
+                    return;
+                }
+                self.released = false;
+                let one = lines == 0x10;  // P15 low
+                if bits == PACKET_BITS {
+                    // Stop bit
+                    if !one {
+                        self.packets.push(SgbPacket(self.data));
+                    }
+                    self.bits = None;
+                } else {
+                    if one {
+                        self.data[bits / 8] |= 1 << (bits % 8);
+                    }
+                    self.bits = Some(bits + 1);
+                }
+            },
+        }
+    }
+
+    /// Completed packets, oldest first
+    pub fn packets(&self) -> &[SgbPacket] {
+        &self.packets
+    }
+
+    /// Completed packets since the last call
+    pub fn take_packets(&mut self) -> Vec<SgbPacket> {
+        core::mem::take(&mut self.packets)
+    }
+}
@@ -0,0 +1,120 @@
+// Timer register addresses
+pub const DIV: u16  = 0xFF04;  // Divider
+pub const TIMA: u16 = 0xFF05;  // Timer counter
+pub const TMA: u16  = 0xFF06;  // Timer modulo
+pub const TAC: u16  = 0xFF07;  // Timer control
+
+/// DIV/TIMA timer unit. DIV is the upper byte of a free-running 16-bit
+/// counter; TIMA counts at the rate selected by TAC and reloads from TMA
+/// on overflow, requesting the timer interrupt.
+///
+/// TIMA is clocked by a falling edge detector on one bit of the counter
+/// (selected by TAC) ANDed with the enable bit. Anything that drops that
+/// signal also ticks TIMA: resetting DIV while the bit is set, or a TAC write
+/// that disables the timer or switches to a bit that is clear.
+///
+/// On overflow TIMA reads 0x00 for one machine cycle; only then is it
+/// reloaded from TMA and the interrupt requested. Writes to TIMA in that
+/// window are ignored.
+#[derive(Clone)]
+pub struct Timer {
+    counter: u16,  // Internal counter, DIV = counter >> 8
+    reload_delay: u8,  // Cycles until an overflowed TIMA reloads from TMA (0 = none pending)
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            counter: 0,
+            reload_delay: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+        }
+    }
+
+    pub fn div(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
+    /// Any write to DIV resets the internal counter
+    pub fn reset_div(&mut self) {
+        if self.signal() {
+            self.increment();
+        }
+        self.counter = 0;
+    }
+
+    /// Write TIMA, unless an overflow is waiting to reload it
+    pub fn write_tima(&mut self, value: u8) {
+        if self.reload_delay == 0 {
+            self.tima = value;
+        }
+    }
+
+    pub fn write_tac(&mut self, value: u8) {
+        let before = self.signal();
+        self.tac = value & 0x07;
+        if before && !self.signal() {
+            self.increment();
+        }
+    }
+
+    /// Counter bit watched for the frequency selected by TAC bits 0-1
+    fn selected_bit(&self) -> u16 {
+        match self.tac & 0x03 {
+            0 => 1 << 9,  // 4096 Hz, every 1024 cycles
+            1 => 1 << 3,  // 262144 Hz, every 16 cycles
+            2 => 1 << 5,  // 65536 Hz, every 64 cycles
+            _ => 1 << 7,  // 16384 Hz, every 256 cycles
+        }
+    }
+
+    /// Input to the falling edge detector
+    fn signal(&self) -> bool {
+        self.tac & 0x04 != 0 && self.counter & self.selected_bit() != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflow {
+            self.reload_delay = 4;
+        }
+    }
+
+    /// Advance the timer by `cycles` clock cycles.
+    /// Returns true if TIMA overflowed and the timer interrupt should be requested.
+    pub fn step(&mut self, cycles: u32) -> bool {
+        let mut interrupt = false;
+        let mut remaining = cycles;
+        // One machine cycle at a time: the lowest selectable bit is bit 3,
+        // so a 4-cycle step can't pass more than one falling edge
+        while remaining > 0 {
+            let step = remaining.min(4);
+            remaining -= step;
+            if self.reload_delay > 0 {
+                self.reload_delay = self.reload_delay.saturating_sub(step as u8);
+                if self.reload_delay == 0 {
+                    self.tima = self.tma;
+                    interrupt = true;
+                }
+            }
+            let before = self.signal();
+            self.counter = self.counter.wrapping_add(step as u16);
+            if before && !self.signal() {
+                self.increment();
+            }
+        }
+        interrupt
+    }
+}
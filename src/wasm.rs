@@ -0,0 +1,57 @@
+// Browser bindings. Everything here goes through in-memory ROM loading and
+// unpaced frames: std::fs and std::time are unavailable on wasm32.
+use wasm_bindgen::prelude::*;
+
+use crate::joypad::Button;
+use crate::machine::Machine;
+use crate::mmu::MMU;
+
+#[wasm_bindgen]
+pub struct WasmGb {
+    machine: Machine,
+}
+
+impl Default for WasmGb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmGb {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmGb {
+        WasmGb {
+            machine: Machine::new(),
+        }
+    }
+
+    /// Insert a cartridge and reset to the post-boot state
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        let mut mmu = MMU::new();
+        mmu.load_rom_bytes(data)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.machine = Machine::with_mmu(mmu);
+        Ok(())
+    }
+
+    /// Emulate one frame; the page paces calls with requestAnimationFrame
+    pub fn run_frame(&mut self) -> Result<(), JsValue> {
+        self.machine
+            .emulate_frame()
+            .map(|_| ())
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// RGBA pixels, 160x144
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.machine.ppu().framebuffer().to_vec()
+    }
+
+    /// Button index: 0 Right, 1 Left, 2 Up, 3 Down, 4 A, 5 B, 6 Select, 7 Start
+    pub fn set_button(&mut self, index: u8, pressed: bool) {
+        if let Some(button) = Button::from_index(index) {
+            self.machine.mmu_mut().set_button(button, pressed);
+        }
+    }
+}
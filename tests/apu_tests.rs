@@ -0,0 +1,305 @@
+use gbrust::mmu::MMU;
+
+#[test]
+fn test_channel2_square_wave() {
+    let mut mmu = MMU::new();
+
+    // Channel 2: 50% duty, max volume with no envelope, frequency 0x700
+    // Period per duty step = (2048 - 0x700) * 4 = 1024 cycles
+    mmu.write_byte(0xFF16, 0x80);
+    mmu.write_byte(0xFF17, 0xF0);
+    mmu.write_byte(0xFF18, 0x00);
+    mmu.write_byte(0xFF19, 0x87);  // Trigger
+    assert!(mmu.apu.channel2.enabled);
+
+    // Sample once per duty step over two full waveforms
+    let mut levels = Vec::new();
+    for _ in 0..16 {
+        mmu.tick(1024);
+        levels.push(mmu.apu.sample().0);
+    }
+
+    // 50% duty: four high steps and four low steps per waveform
+    let high: Vec<bool> = levels.iter().map(|&level| level > 0.0).collect();
+    assert_eq!(&high[0..8], &high[8..16]);
+    assert_eq!(high.iter().filter(|&&h| h).count(), 8);
+    assert!(levels.iter().any(|&level| level > 0.0));
+    assert!(levels.iter().any(|&level| level < 0.0));
+
+    // Pattern 10000111 stepped from position 1: 0,0,0,0,1,1,1,1
+    assert_eq!(&high[0..8], &[false, false, false, false, true, true, true, true]);
+}
+
+#[test]
+fn test_square_length_and_envelope() {
+    let mut mmu = MMU::new();
+
+    // Channel 1: length 62 (64 - 62 = 2 clocks), volume 2 decreasing every step
+    mmu.write_byte(0xFF11, 0x3E);
+    mmu.write_byte(0xFF12, 0x21);
+    mmu.write_byte(0xFF14, 0xC0);  // Trigger with length enabled
+    assert_eq!(mmu.apu.channel1.volume(), 2);
+
+    mmu.apu.channel1.clock_envelope();
+    assert_eq!(mmu.apu.channel1.volume(), 1);
+
+    mmu.apu.channel1.clock_length();
+    assert!(mmu.apu.channel1.enabled);
+    mmu.apu.channel1.clock_length();
+    assert!(!mmu.apu.channel1.enabled);
+
+    // A DAC-off envelope value (upper 5 bits clear) prevents triggering
+    mmu.write_byte(0xFF17, 0x00);
+    mmu.write_byte(0xFF19, 0x80);
+    assert!(!mmu.apu.channel2.enabled);
+}
+
+#[test]
+fn test_frame_sequencer_length() {
+    let mut mmu = MMU::new();
+
+    // Channel 2: length 61 (3 clocks), max volume, no envelope
+    mmu.write_byte(0xFF16, 0x3D);
+    mmu.write_byte(0xFF17, 0xF0);
+    mmu.write_byte(0xFF19, 0xC0);  // Trigger with length enabled
+    assert!(mmu.apu.channel2.enabled);
+
+    // Length is clocked on steps 0, 2 and 4; one step is 8192 cycles
+    for step in 0..4 {
+        mmu.tick(8192);
+        assert!(mmu.apu.channel2.enabled, "silent after step {}", step);
+    }
+    assert_eq!(mmu.apu.sequencer.step(), 4);
+    mmu.tick(8192);
+    assert!(!mmu.apu.channel2.enabled);
+    assert_eq!(mmu.apu.channel2.output(), 0);
+}
+
+#[test]
+fn test_channel1_sweep_down() {
+    let mut mmu = MMU::new();
+
+    // Sweep period 1, downward, shift 2: each step subtracts frequency / 4
+    mmu.write_byte(0xFF10, 0x1A);
+    mmu.write_byte(0xFF12, 0xF0);
+    mmu.write_byte(0xFF13, 0x00);
+    mmu.write_byte(0xFF14, 0x84);  // Trigger at frequency 0x400
+    assert!(mmu.apu.channel1.enabled);
+    assert_eq!(mmu.apu.channel1.frequency, 0x400);
+
+    // Sweep is clocked on sequencer steps 2 and 6
+    mmu.tick(8192 * 3);
+    assert_eq!(mmu.apu.channel1.frequency, 0x300);
+    mmu.tick(8192 * 4);
+    assert_eq!(mmu.apu.channel1.frequency, 0x240);
+    mmu.tick(8192 * 4);
+    assert_eq!(mmu.apu.channel1.frequency, 0x1B0);
+    assert!(mmu.apu.channel1.enabled);
+
+    // Upward shift 1 from 0x700 overflows in the check done on trigger
+    mmu.write_byte(0xFF10, 0x11);
+    mmu.write_byte(0xFF13, 0x00);
+    mmu.write_byte(0xFF14, 0x87);
+    assert!(!mmu.apu.channel1.enabled);
+}
+
+#[test]
+fn test_wave_channel_ramp() {
+    let mut mmu = MMU::new();
+
+    // Ramp waveform 0,1,2,...,15 repeated twice
+    for i in 0..16u16 {
+        let first = ((i * 2) % 16) as u8;
+        let second = ((i * 2 + 1) % 16) as u8;
+        mmu.write_byte(0xFF30 + i, (first << 4) | second);
+    }
+    assert_eq!(mmu.read_byte(0xFF30), 0x01);
+
+    // DAC on, 100% volume, frequency 0x700: (2048 - 0x700) * 2 = 512 cycles per sample
+    mmu.write_byte(0xFF1A, 0x80);
+    mmu.write_byte(0xFF1C, 0x20);
+    mmu.write_byte(0xFF1D, 0x00);
+    mmu.write_byte(0xFF1E, 0x87);  // Trigger
+    assert!(mmu.apu.channel3.enabled);
+
+    let mut samples = vec![mmu.apu.channel3.output()];
+    for _ in 0..20 {
+        mmu.apu.step(512);
+        samples.push(mmu.apu.channel3.output());
+    }
+    let expected: Vec<u8> = (0..21).map(|i| (i % 16) as u8).collect();
+    assert_eq!(samples, expected);
+
+    // 50% volume shifts each sample right by one
+    mmu.write_byte(0xFF1C, 0x40);
+    mmu.write_byte(0xFF1E, 0x87);
+    for _ in 0..15 {
+        mmu.apu.step(512);
+    }
+    assert_eq!(mmu.apu.channel3.output(), 15 >> 1);
+
+    // Muted output level produces silence
+    mmu.write_byte(0xFF1C, 0x00);
+    assert_eq!(mmu.apu.channel3.output(), 0);
+}
+
+fn lfsr_bits(mmu: &mut MMU, count: usize) -> String {
+    (0..count)
+        .map(|_| {
+            mmu.apu.channel4.clock_lfsr();
+            if mmu.apu.channel4.lfsr & 0x01 != 0 { '1' } else { '0' }
+        })
+        .collect()
+}
+
+#[test]
+fn test_noise_channel_lfsr() {
+    let mut mmu = MMU::new();
+
+    // 15-bit mode: DAC on at volume 15, divisor code 0, shift 0
+    mmu.write_byte(0xFF21, 0xF0);
+    mmu.write_byte(0xFF22, 0x00);
+    mmu.write_byte(0xFF23, 0x80);  // Trigger seeds the LFSR with all ones
+    assert!(mmu.apu.channel4.enabled);
+    assert_eq!(mmu.apu.channel4.lfsr, 0x7FFF);
+    assert_eq!(
+        lfsr_bits(&mut mmu, 48),
+        "111111111111110000000000000010000000000000110000"
+    );
+
+    // 7-bit mode
+    mmu.write_byte(0xFF22, 0x08);
+    mmu.write_byte(0xFF23, 0x80);
+    assert_eq!(mmu.apu.channel4.lfsr, 0x7FFF);
+    assert_eq!(
+        lfsr_bits(&mut mmu, 48),
+        "111111000000100000110000101000111100100010110011"
+    );
+
+    // Maximal-length sequences: 127 steps in 7-bit mode, 32767 in 15-bit mode
+    let sequence = lfsr_bits(&mut mmu, 254);
+    assert_eq!(sequence[..127], sequence[127..]);
+    mmu.write_byte(0xFF22, 0x00);
+    mmu.write_byte(0xFF23, 0x80);
+    lfsr_bits(&mut mmu, 32767);
+    assert_eq!(mmu.apu.channel4.lfsr, 0x7FFF);
+
+    // Divisor code 1 with shift 2: 16 << 2 = 64 cycles per LFSR clock
+    mmu.write_byte(0xFF22, 0x21);
+    mmu.write_byte(0xFF23, 0x80);
+    mmu.apu.step(63);
+    assert_eq!(mmu.apu.channel4.lfsr, 0x7FFF);
+    assert_eq!(mmu.apu.channel4.output(), 0);  // Bit 0 set: silent
+    mmu.apu.step(1);
+    assert_eq!(mmu.apu.channel4.lfsr, 0x3FFF);
+}
+
+#[test]
+fn test_sound_register_read_masks() {
+    let mut mmu = MMU::new();
+
+    // Frequency registers are write-only
+    mmu.write_byte(0xFF13, 0x42);
+    assert_eq!(mmu.read_byte(0xFF13), 0xFF);
+    assert_eq!(mmu.read_byte(0xFF1D), 0xFF);
+
+    // Only duty reads back from NR11, only length enable from NR14
+    mmu.write_byte(0xFF11, 0x80);
+    assert_eq!(mmu.read_byte(0xFF11), 0xBF);
+    mmu.write_byte(0xFF14, 0x40);
+    assert_eq!(mmu.read_byte(0xFF14), 0xFF);
+    mmu.write_byte(0xFF14, 0x00);
+    assert_eq!(mmu.read_byte(0xFF14), 0xBF);
+
+    // Envelopes read back in full
+    mmu.write_byte(0xFF12, 0x53);
+    assert_eq!(mmu.read_byte(0xFF12), 0x53);
+
+    // Unmapped sound addresses read 0xFF
+    assert_eq!(mmu.read_byte(0xFF15), 0xFF);
+    assert_eq!(mmu.read_byte(0xFF27), 0xFF);
+
+    // Other I/O gaps float high as well
+    mmu.write_byte(0xFF4C, 0x00);
+    assert_eq!(mmu.read_byte(0xFF4C), 0xFF);
+}
+
+#[test]
+fn test_nr52_power_and_status() {
+    let mut mmu = MMU::new();
+    assert_eq!(mmu.read_byte(0xFF26), 0xF0);
+
+    // Channel 2 playing shows in bit 1
+    mmu.write_byte(0xFF16, 0x80);
+    mmu.write_byte(0xFF17, 0xF0);
+    mmu.write_byte(0xFF19, 0x87);
+    assert_eq!(mmu.read_byte(0xFF26), 0xF2);
+    mmu.write_byte(0xFF30, 0x5A);
+
+    // Powering off stops the channel and zeroes the registers
+    mmu.write_byte(0xFF26, 0x00);
+    assert_eq!(mmu.read_byte(0xFF26), 0x70);
+    assert!(!mmu.apu.channel2.enabled);
+    assert_eq!(mmu.read_byte(0xFF16), 0x3F);
+    assert_eq!(mmu.read_byte(0xFF17), 0x00);
+    assert_eq!(mmu.read_byte(0xFF24), 0x00);
+    assert_eq!(mmu.read_byte(0xFF25), 0x00);
+    assert_eq!(mmu.apu.sample(), (0.0, 0.0));
+
+    // Writes are ignored while off, except to wave RAM
+    mmu.write_byte(0xFF17, 0xF0);
+    mmu.write_byte(0xFF19, 0x87);
+    assert_eq!(mmu.read_byte(0xFF17), 0x00);
+    assert!(!mmu.apu.channel2.enabled);
+    assert_eq!(mmu.read_byte(0xFF30), 0x5A);
+    mmu.write_byte(0xFF31, 0xA5);
+    assert_eq!(mmu.read_byte(0xFF31), 0xA5);
+
+    // Powered back on, the registers are writable again
+    mmu.write_byte(0xFF26, 0x80);
+    mmu.write_byte(0xFF17, 0xF0);
+    assert_eq!(mmu.read_byte(0xFF17), 0xF0);
+    assert_eq!(mmu.read_byte(0xFF26), 0xF0);
+}
+
+#[test]
+fn test_wav_recording() {
+    let mut mmu = MMU::new();
+    // Channel 2 square tone, as in test_channel2_square_wave
+    mmu.write_byte(0xFF16, 0x80);
+    mmu.write_byte(0xFF17, 0xF0);
+    mmu.write_byte(0xFF18, 0x00);
+    mmu.write_byte(0xFF19, 0x87);
+
+    // Not recording: ticks leave nothing behind
+    mmu.tick(1024);
+    assert!(mmu.apu.recorder().is_none());
+    assert!(mmu.apu.stop_recording().is_none());
+
+    // One emulated second at 44.1 kHz is exactly 44100 stereo frames
+    mmu.apu.start_recording(44_100);
+    for _ in 0..4_194_304 / 1024 {
+        mmu.tick(1024);
+    }
+    assert_eq!(mmu.apu.recorder().unwrap().frames(), 44_100);
+    let wav = mmu.apu.stop_recording().unwrap();
+
+    let u16_at = |offset: usize| u16::from_le_bytes([wav[offset], wav[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes(wav[offset..offset + 4].try_into().unwrap());
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32_at(4) as usize, wav.len() - 8);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u16_at(20), 1);  // PCM
+    assert_eq!(u16_at(22), 2);  // Stereo
+    assert_eq!(u32_at(24), 44_100);
+    assert_eq!(u32_at(28), 44_100 * 4);
+    assert_eq!(u16_at(34), 16);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32_at(40), 44_100 * 4);
+    assert_eq!(wav.len(), 44 + 44_100 * 4);
+
+    // The tone swings both ways
+    let samples: Vec<i16> = wav[44..].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+    assert!(samples.iter().any(|&s| s > 1000));
+    assert!(samples.iter().any(|&s| s < -1000));
+}
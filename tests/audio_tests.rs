@@ -0,0 +1,79 @@
+use gbrust::apu::Apu;
+use gbrust::audio::{AudioBuffer, RingBuffer};
+
+// One second of emulated time in clock cycles
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+#[test]
+fn test_resampled_length() {
+    for rate in [44100, 48000] {
+        // 440 Hz tone on channel 2: frequency = 2048 - 131072 / 440
+        let mut apu = Apu::new();
+        let ring = apu.enable_audio_output(rate, rate as usize);
+        apu.write_register(0xFF16, 0x80);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF18, 0xD6);
+        apu.write_register(0xFF19, 0x86);
+
+        for _ in 0..(CYCLES_PER_SECOND / 16) {
+            apu.step(16);
+        }
+
+        // Exactly one second of output, and the tone is audible
+        assert_eq!(ring.len(), rate as usize);
+        let mut out = vec![0.0; rate as usize * 2];
+        assert_eq!(ring.drain(&mut out), rate as usize);
+        assert!(out.iter().any(|&s| s > 0.0));
+        assert!(out.iter().any(|&s| s < 0.0));
+    }
+}
+
+#[test]
+fn test_panning_and_master_volume() {
+    let mut apu = Apu::new();
+
+    // Channel 2 constantly high: 12.5% duty at step 7
+    apu.write_register(0xFF16, 0x00);
+    apu.write_register(0xFF17, 0xF0);
+    apu.write_register(0xFF19, 0x80);
+    apu.step(7 * 8192);
+    assert_eq!(apu.channel2.output(), 15);
+
+    // Channel 2 on the left only, left volume 7, right volume 3
+    apu.write_register(0xFF25, 0x20);
+    apu.write_register(0xFF24, 0x73);
+    let (left, right) = apu.sample();
+    assert_eq!(left, 0.25);
+    assert_eq!(right, 0.0);
+
+    // Halving the left master volume halves the output
+    apu.write_register(0xFF24, 0x33);
+    assert_eq!(apu.sample().0, 0.125);
+}
+
+#[test]
+fn test_ring_overrun_and_underrun() {
+    let ring = RingBuffer::new(2);
+    assert!(ring.push(0.1, 0.2));
+    assert!(ring.push(0.3, 0.4));
+
+    // Overrun: the newest frame is dropped
+    assert!(!ring.push(0.5, 0.6));
+    assert_eq!(ring.len(), 2);
+
+    // Underrun: the last frame is repeated
+    let mut out = [0.0; 8];
+    assert_eq!(ring.drain(&mut out), 2);
+    assert_eq!(out, [0.1, 0.2, 0.3, 0.4, 0.3, 0.4, 0.3, 0.4]);
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn test_box_filter_average() {
+    // 2 native samples per output sample
+    let mut audio = AudioBuffer::new(524_288, 4);
+    let ring = audio.ring();
+    audio.push_native((1.0, 0.0));
+    audio.push_native((0.0, -1.0));
+    assert_eq!(ring.pop(), Some((0.5, -0.5)));
+}
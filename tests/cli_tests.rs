@@ -0,0 +1,77 @@
+use gbrust::cli::Options;
+use gbrust::machine::Machine;
+
+fn write_temp_file(name: &str, data: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("gbrust_{}_{}", std::process::id(), name));
+    std::fs::write(&path, data).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// The documented DMG post-boot registers, with the boot ROM unmapped
+fn assert_post_boot(machine: &Machine) {
+    let cpu = &machine.cpu;
+    assert_eq!((cpu.a, cpu.f), (0x01, 0xB0));
+    assert_eq!((cpu.b, cpu.c), (0x00, 0x13));
+    assert_eq!((cpu.d, cpu.e), (0x00, 0xD8));
+    assert_eq!((cpu.h, cpu.l), (0x01, 0x4D));
+    assert_eq!(cpu.sp, 0xFFFE);
+    assert_eq!(cpu.pc, 0x0100);
+    assert!(!machine.mmu().boot_rom_mapped());
+    assert_eq!(machine.mmu().read_byte(0xFF40), 0x91);
+}
+
+#[test]
+fn test_parse_options() {
+    let options = Options::parse(&args(&["game.gb"]), None).unwrap();
+    assert_eq!(options, Options { rom: "game.gb".into(), boot_rom: None, fast_boot: false });
+
+    // GBRUST_BOOT_ROM is the fallback; --boot wins over it
+    let options = Options::parse(&args(&["game.gb"]), Some("env.bin".into())).unwrap();
+    assert_eq!(options.boot_rom.as_deref(), Some("env.bin"));
+    let options = Options::parse(&args(&["--boot", "dmg.bin", "game.gb"]), Some("env.bin".into())).unwrap();
+    assert_eq!(options.boot_rom.as_deref(), Some("dmg.bin"));
+    assert!(!options.fast_boot);
+
+    let options = Options::parse(&args(&["game.gb", "--fast-boot"]), Some("env.bin".into())).unwrap();
+    assert!(options.fast_boot);
+
+    assert!(Options::parse(&args(&[]), None).is_err());
+    assert!(Options::parse(&args(&["game.gb", "--boot"]), None).is_err());
+    assert!(Options::parse(&args(&["--fast-boot", "--boot", "dmg.bin", "game.gb"]), None).is_err());
+    assert!(Options::parse(&args(&["--turbo", "game.gb"]), None).is_err());
+    assert!(Options::parse(&args(&["a.gb", "b.gb"]), None).is_err());
+}
+
+#[test]
+fn test_fast_boot() {
+    let rom = write_temp_file("fast_boot.gb", &[0; 0x8000]);
+    let boot = write_temp_file("fast_boot.bin", &[0x18; 0x100]);  // JR: spins forever
+
+    // No boot ROM: fast boot is the default
+    let machine = Options::parse(&args(&[&rom]), None).unwrap().start().unwrap();
+    assert_post_boot(&machine);
+
+    // A boot ROM runs from power-on...
+    let machine = Options::parse(&args(&[&rom]), Some(boot.clone())).unwrap().start().unwrap();
+    assert!(machine.mmu().boot_rom_mapped());
+    assert_eq!(machine.cpu.pc, 0x0000);
+    assert_eq!(machine.mmu().read_byte(0x0000), 0x18);
+
+    // ...unless --fast-boot skips it
+    let machine = Options::parse(&args(&["--fast-boot", &rom]), Some(boot.clone())).unwrap().start().unwrap();
+    assert_post_boot(&machine);
+    assert_eq!(machine.mmu().read_byte(0x0000), 0x00);
+
+    // A missing GBRUST_BOOT_ROM doesn't matter when it's skipped
+    let missing = std::env::temp_dir().join("gbrust_missing_boot.bin").to_string_lossy().into_owned();
+    assert!(Options::parse(&args(&[&rom]), Some(missing.clone())).unwrap().start().is_err());
+    let machine = Options::parse(&args(&["--fast-boot", &rom]), Some(missing)).unwrap().start().unwrap();
+    assert_post_boot(&machine);
+
+    std::fs::remove_file(rom).unwrap();
+    std::fs::remove_file(boot).unwrap();
+}
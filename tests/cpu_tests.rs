@@ -1,4 +1,6 @@
-use gbrust::cpu::CPU;
+#![allow(clippy::bool_assert_comparison)]
+
+use gbrust::cpu::{opcode_length, CPUError, LogLevel, CPU};
 
 // Flag bit positions (copied from cpu.rs since they're private)
 const ZERO_FLAG: u8 = 7;
@@ -244,17 +246,14 @@ fn test_ld_hl_dec_a() {
     let mut mmu = gbrust::mmu::MMU::new();
     
     // Initialize CPU and MMU state
-    cpu.set_mmu(mmu);
     cpu.a = 0x42;
     cpu.h = 0x80;  // Changed from 0x20 to 0x80 to write to VRAM instead of ROM
     cpu.l = 0x00;  // HL = 0x8000 (start of VRAM)
     
-    cpu.ld_hl_dec_a().unwrap();
+    cpu.ld_hl_dec_a(&mut mmu);
     
     // Check if value was written to memory
-    if let Some(ref mmu) = cpu.mmu {
-        assert_eq!(mmu.read_byte(0x8000), 0x42);
-    }
+    assert_eq!(mmu.read_byte(0x8000), 0x42);
     
     // Check if HL was decremented
     assert_eq!(cpu.h, 0x7F);
@@ -343,19 +342,15 @@ fn test_rst_18() {
     // - SP should be decremented by 2
     // - PC should jump to 0x0018
     let mut cpu = CPU::new();
-    let mmu = gbrust::mmu::MMU::new();
-    
-    cpu.set_mmu(mmu);
+    let mut mmu = gbrust::mmu::MMU::new();
     cpu.sp = 0xFFFE;
     cpu.pc = 0x1234;
     
-    cpu.rst_18().unwrap();
+    cpu.rst_18(&mut mmu);
     
     // Check if PC was correctly pushed to stack
-    if let Some(ref mmu) = cpu.mmu {
-        assert_eq!(mmu.read_byte(0xFFFD), 0x12);  // High byte
-        assert_eq!(mmu.read_byte(0xFFFC), 0x34);  // Low byte
-    }
+    assert_eq!(mmu.read_byte(0xFFFD), 0x12);  // High byte
+    assert_eq!(mmu.read_byte(0xFFFC), 0x34);  // Low byte
     
     // Check if SP was decremented
     assert_eq!(cpu.sp, 0xFFFC);
@@ -372,19 +367,15 @@ fn test_rst_38() {
     // - SP should be decremented by 2
     // - PC should jump to 0x0038
     let mut cpu = CPU::new();
-    let mmu = gbrust::mmu::MMU::new();
-    
-    cpu.set_mmu(mmu);
+    let mut mmu = gbrust::mmu::MMU::new();
     cpu.sp = 0xFFFE;
     cpu.pc = 0x1234;
     
-    cpu.rst_38().unwrap();
+    cpu.rst_38(&mut mmu);
     
     // Check if PC was correctly pushed to stack
-    if let Some(ref mmu) = cpu.mmu {
-        assert_eq!(mmu.read_byte(0xFFFD), 0x12);  // High byte
-        assert_eq!(mmu.read_byte(0xFFFC), 0x34);  // Low byte
-    }
+    assert_eq!(mmu.read_byte(0xFFFD), 0x12);  // High byte
+    assert_eq!(mmu.read_byte(0xFFFC), 0x34);  // Low byte
     
     // Check if SP was decremented
     assert_eq!(cpu.sp, 0xFFFC);
@@ -415,11 +406,58 @@ fn test_di() {
 #[test]
 fn test_ei() {
     // Test enabling interrupts
-    // Expected: interrupt_enabled flag should be true after execution
+    // Expected:
+    // - interrupt_enabled only becomes true after the instruction following EI
+    // - DI straight after EI cancels it
     let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    for (i, byte) in [0xFB, 0x00, 0xFB, 0xF3, 0x00].into_iter().enumerate() {
+        mmu.write_byte(0xC000 + i as u16, byte);  // EI; NOP; EI; DI; NOP
+    }
+    cpu.pc = 0xC000;
     cpu.interrupt_enabled = false;
-    cpu.ei();
+
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(cpu.interrupt_enabled, false);
+    cpu.step(&mut mmu).unwrap();
     assert_eq!(cpu.interrupt_enabled, true);
+
+    cpu.interrupt_enabled = false;
+    cpu.step(&mut mmu).unwrap();
+    cpu.step(&mut mmu).unwrap();
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(cpu.interrupt_enabled, false);
+}
+
+#[test]
+fn test_ei_ret() {
+    // Test a handler ending in EI; RET with another interrupt already requested
+    // Expected: RET returns before the interrupt is dispatched, so the
+    // handler isn't re-entered and the pushed return address is the caller's
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.write_byte(0xC100, 0xFB);  // EI
+    mmu.write_byte(0xC101, 0xC9);  // RET
+    mmu.write_byte(0xDFEE, 0x00);  // Return address 0xC000
+    mmu.write_byte(0xDFEF, 0xC0);
+    mmu.write_byte(0xFFFF, 0x04);  // IE: Timer
+    mmu.write_byte(0xFF0F, 0x00);
+    mmu.request_interrupt(gbrust::mmu::INT_TIMER);
+    cpu.pc = 0xC100;
+    cpu.sp = 0xDFEE;
+    cpu.interrupt_enabled = false;
+
+    cpu.step(&mut mmu).unwrap();  // EI
+    assert_eq!(cpu.pc, 0xC101);
+    cpu.step(&mut mmu).unwrap();  // RET
+    assert_eq!(cpu.pc, 0xC000);
+    assert_eq!(cpu.sp, 0xDFF0);
+    assert_eq!(cpu.interrupt_enabled, true);
+
+    assert_eq!(cpu.step(&mut mmu).unwrap(), 20);
+    assert_eq!(cpu.pc, 0x0050);
+    assert_eq!(mmu.read_byte(0xDFEE), 0x00);
+    assert_eq!(mmu.read_byte(0xDFEF), 0xC0);
 }
 
 #[test]
@@ -428,25 +466,19 @@ fn test_ldh_n_a() {
     // Expected:
     // - Memory at FF00+n should contain value of A
     let mut cpu = CPU::new();
-    let mmu = gbrust::mmu::MMU::new();
-    
-    cpu.set_mmu(mmu);
+    let mut mmu = gbrust::mmu::MMU::new();
     cpu.a = 0x42;
 
     let n: u8 = 0x80;
     
     // Store A at FF80
     cpu.pc = 0;
-    if let Some(ref mut mmu) = cpu.mmu {
-        mmu.write_byte(cpu.pc, 0x80); // Write offset 0x80 as immediate value
-    }
+    mmu.write_byte(cpu.pc, 0x80); // Write offset 0x80 as immediate value
     
-    cpu.ldh_n_a(n).unwrap();
+    cpu.ldh_n_a(&mut mmu, n);
     
     // Check if value was written to high RAM
-    if let Some(ref mmu) = cpu.mmu {
-        assert_eq!(mmu.read_byte(0xFF80), 0x42);
-    }
+    assert_eq!(mmu.read_byte(0xFF80), 0x42);
 }
 
 #[test]
@@ -455,25 +487,19 @@ fn test_ldh_a_n() {
     // Expected:
     // - A should contain value from memory at FF00+n
     let mut cpu = CPU::new();
-    let mmu = gbrust::mmu::MMU::new();
-    
-    cpu.set_mmu(mmu);
+    let mut mmu = gbrust::mmu::MMU::new();
     cpu.a = 0;  // Clear A
     
     let n: u8 = 0x80;
     
     // Store value at FF80
-    if let Some(ref mut mmu) = cpu.mmu {
-        mmu.write_byte(0xFF80, 0x42);
-    }
+    mmu.write_byte(0xFF80, 0x42);
     
     // Load value from FF80 into A
     cpu.pc = 0;
-    if let Some(ref mut mmu) = cpu.mmu {
-        mmu.write_byte(cpu.pc, 0x80);  // Write offset 0x80 as immediate value
-    }
+    mmu.write_byte(cpu.pc, 0x80);  // Write offset 0x80 as immediate value
     
-    cpu.ldh_a_n(n).unwrap();
+    cpu.ldh_a_n(&mmu, n);
     
     // Check if A was loaded correctly
     assert_eq!(cpu.a, 0x42);
@@ -508,4 +534,645 @@ fn test_cp_n() {
     cpu.a = 0x10;
     cpu.cp_n(0x01);
     assert_eq!(cpu.get_flag(HALF_CARRY_FLAG), true);
-}
\ No newline at end of file
+}
+#[test]
+fn test_subtract_flags_exhaustive() {
+    // Reference: do the borrow arithmetic in i32 and read the flags off the signs
+    fn reference(a: u8, value: u8, carry: bool) -> (u8, [bool; 4]) {
+        let carry = carry as i32;
+        let full = a as i32 - value as i32 - carry;
+        let half = (a & 0x0F) as i32 - (value & 0x0F) as i32 - carry;
+        let result = full as u8;
+        (result, [result == 0, true, half < 0, full < 0])
+    }
+    fn flags(cpu: &CPU) -> [bool; 4] {
+        [
+            cpu.get_flag(ZERO_FLAG),
+            cpu.get_flag(SUBTRACT_FLAG),
+            cpu.get_flag(HALF_CARRY_FLAG),
+            cpu.get_flag(CARRY_FLAG),
+        ]
+    }
+
+    let mut cpu = CPU::new();
+    for a in 0..=255u8 {
+        for value in 0..=255u8 {
+            for carry in [false, true] {
+                let (sbc_result, sbc_flags) = reference(a, value, carry);
+                let (sub_result, sub_flags) = reference(a, value, false);
+                let case = format!("A={:02X} n={:02X} C={}", a, value, carry);
+
+                // SUB and CP ignore the incoming carry
+                cpu.a = a;
+                cpu.set_flag(CARRY_FLAG, carry);
+                cpu.sub_n(value);
+                assert_eq!((cpu.a, flags(&cpu)), (sub_result, sub_flags), "SUB {}", case);
+
+                cpu.a = a;
+                cpu.set_flag(CARRY_FLAG, carry);
+                cpu.sbc_a_n(value);
+                assert_eq!((cpu.a, flags(&cpu)), (sbc_result, sbc_flags), "SBC {}", case);
+
+                cpu.a = a;
+                cpu.set_flag(CARRY_FLAG, carry);
+                cpu.cp_n(value);
+                assert_eq!((cpu.a, flags(&cpu)), (a, sub_flags), "CP {}", case);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ldh_c_a() {
+    // Test storing A in high RAM (FF00+C)
+    // Expected:
+    // - Memory at FF00+C should contain value of A
+    // - Writing to LY (FF44) resets it to 0 instead of storing A
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.a = 0x42;
+
+    // Store A at FF80
+    cpu.c = 0x80;
+    cpu.ldh_c_a(&mut mmu);
+    assert_eq!(mmu.read_byte(0xFF80), 0x42);
+
+    // Store A at FF44 (LY), which resets on write
+    cpu.c = 0x44;
+    cpu.ldh_c_a(&mut mmu);
+    assert_eq!(mmu.read_byte(0xFF44), 0x00);
+}
+
+#[test]
+fn test_ldh_a_c() {
+    // Test loading A from high RAM (FF00+C)
+    // Expected:
+    // - A should contain value from memory at FF00+C
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.a = 0;  // Clear A
+
+    // Store value at FF80
+    mmu.write_byte(0xFF80, 0x42);
+
+    cpu.c = 0x80;
+    cpu.ldh_a_c(&mmu);
+
+    // Check if A was loaded correctly
+    assert_eq!(cpu.a, 0x42);
+}
+
+#[test]
+fn test_ld_nn_sp() {
+    // Test storing SP at an absolute address
+    // Expected:
+    // - Low byte of SP at nn, high byte at nn+1
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.sp = 0xFFF8;
+
+    // Place the operand (0xC000, little-endian) in WRAM and execute from there
+    cpu.pc = 0xC100;
+    mmu.write_byte(0xC100, 0x00);
+    mmu.write_byte(0xC101, 0xC0);
+
+    let cycles = cpu.execute(&mut mmu, 0x08).unwrap();
+    assert_eq!(cycles, 20);
+    assert_eq!(cpu.pc, 0xC102);
+
+    assert_eq!(mmu.read_byte(0xC000), 0xF8);
+    assert_eq!(mmu.read_byte(0xC001), 0xFF);
+}
+
+#[test]
+fn test_add_sp_e() {
+    let mut cpu = CPU::new();
+
+    // Test case 1: positive offset with carry out of the low byte
+    // 0x00FF + 0x01: low byte 0xFF + 0x01 carries from bits 3 and 7
+    cpu.sp = 0x00FF;
+    cpu.set_flag(ZERO_FLAG, true);
+    cpu.set_flag(SUBTRACT_FLAG, true);
+    cpu.add_sp_e(0x01);
+    assert_eq!(cpu.sp, 0x0100);
+    assert_eq!(cpu.get_flag(ZERO_FLAG), false);
+    assert_eq!(cpu.get_flag(SUBTRACT_FLAG), false);
+    assert_eq!(cpu.get_flag(HALF_CARRY_FLAG), true);
+    assert_eq!(cpu.get_flag(CARRY_FLAG), true);
+
+    // Test case 2: negative offset (-1)
+    // 0xFFF8 - 1 = 0xFFF7, but flags come from 0xF8 + 0xFF (unsigned):
+    // low nibble 0x8 + 0xF carries, low byte 0xF8 + 0xFF carries
+    cpu.sp = 0xFFF8;
+    cpu.add_sp_e(0xFF);
+    assert_eq!(cpu.sp, 0xFFF7);
+    assert_eq!(cpu.get_flag(HALF_CARRY_FLAG), true);
+    assert_eq!(cpu.get_flag(CARRY_FLAG), true);
+
+    // Test case 3: negative offset with no low-byte carry
+    // 0x1000 - 2 = 0x0FFE, but 0x00 + 0xFE produces no carries
+    cpu.sp = 0x1000;
+    cpu.add_sp_e(0xFE);
+    assert_eq!(cpu.sp, 0x0FFE);
+    assert_eq!(cpu.get_flag(HALF_CARRY_FLAG), false);
+    assert_eq!(cpu.get_flag(CARRY_FLAG), false);
+}
+
+#[test]
+fn test_ld_hl_sp_e() {
+    let mut cpu = CPU::new();
+
+    // Test case 1: positive offset with half carry only
+    // 0xFFF8 + 0x08: 0x8 + 0x8 carries from bit 3, 0xF8 + 0x08 carries from bit 7
+    cpu.sp = 0xFFF8;
+    cpu.ld_hl_sp_e(0x08);
+    assert_eq!(cpu.h, 0x00);
+    assert_eq!(cpu.l, 0x00);
+    assert_eq!(cpu.sp, 0xFFF8);  // SP should not be modified
+    assert_eq!(cpu.get_flag(HALF_CARRY_FLAG), true);
+    assert_eq!(cpu.get_flag(CARRY_FLAG), true);
+
+    // Test case 2: positive offset with no carries
+    cpu.sp = 0xC000;
+    cpu.ld_hl_sp_e(0x05);
+    assert_eq!(cpu.h, 0xC0);
+    assert_eq!(cpu.l, 0x05);
+    assert_eq!(cpu.get_flag(ZERO_FLAG), false);
+    assert_eq!(cpu.get_flag(HALF_CARRY_FLAG), false);
+    assert_eq!(cpu.get_flag(CARRY_FLAG), false);
+
+    // Test case 3: negative offset (-16) with carry but no half carry
+    // 0xC010 - 16 = 0xC000; flags from 0x10 + 0xF0 (carry from bit 7, no half carry)
+    cpu.sp = 0xC010;
+    cpu.ld_hl_sp_e(0xF0);
+    assert_eq!(cpu.h, 0xC0);
+    assert_eq!(cpu.l, 0x00);
+    assert_eq!(cpu.get_flag(HALF_CARRY_FLAG), false);
+    assert_eq!(cpu.get_flag(CARRY_FLAG), true);
+}
+
+#[test]
+fn test_ld_sp_hl() {
+    // Test loading HL into SP
+    // Expected: SP should equal HL (0xDFF0), flags untouched
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.ld_hl_nn(0xDFF0);
+    cpu.f = 0xB0;
+
+    let cycles = cpu.execute(&mut mmu, 0xF9).unwrap();
+    assert_eq!(cycles, 8);
+    assert_eq!(cpu.sp, 0xDFF0);
+    assert_eq!(cpu.f, 0xB0);
+}
+
+#[test]
+fn test_rst_00() {
+    // Test RST 00H instruction via the decoder
+    // Expected:
+    // - PC should be pushed onto stack
+    // - SP should be decremented by 2
+    // - PC should jump to 0x0000
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.sp = 0xFFFE;
+    cpu.pc = 0x1234;
+
+    let cycles = cpu.execute(&mut mmu, 0xC7).unwrap();
+    assert_eq!(cycles, 16);
+
+    // Check if PC was correctly pushed to stack
+    assert_eq!(mmu.read_byte(0xFFFD), 0x12);  // High byte
+    assert_eq!(mmu.read_byte(0xFFFC), 0x34);  // Low byte
+
+    assert_eq!(cpu.sp, 0xFFFC);
+    assert_eq!(cpu.pc, 0x0000);
+
+    // Every RST opcode should jump to the vector encoded in bits 3-5
+    for (opcode, vector) in [(0xCF, 0x08), (0xD7, 0x10), (0xDF, 0x18), (0xE7, 0x20),
+                             (0xEF, 0x28), (0xF7, 0x30), (0xFF, 0x38)] {
+        cpu.sp = 0xFFFE;
+        cpu.execute(&mut mmu, opcode).unwrap();
+        assert_eq!(cpu.pc, vector);
+        assert_eq!(cpu.sp, 0xFFFC);
+    }
+}
+
+#[test]
+fn test_power_on() {
+    // Test the two reset states
+    // Expected:
+    // - initialize: post-boot registers, PC at the 0x0100 entry point
+    // - power_on (for a boot ROM): zeroed registers, PC at 0x0000
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    assert_eq!(cpu.a, 0x01);
+    assert_eq!(cpu.sp, 0xFFFE);
+    assert_eq!(cpu.pc, 0x0100);
+
+    cpu.power_on();
+    assert_eq!(cpu.a, 0x00);
+    assert_eq!(cpu.f, 0x00);
+    assert_eq!(cpu.sp, 0x0000);
+    assert_eq!(cpu.pc, 0x0000);
+}
+
+#[test]
+fn test_trace_log() {
+    // Test tracing three NOPs executed from WRAM
+    // Expected: three lines, one per instruction, with the PC advancing
+    let path = std::env::temp_dir().join(format!("gbrust_{}_trace.log", std::process::id()));
+    let mut mmu = gbrust::mmu::MMU::new();
+    for address in 0xC000..0xC003 {
+        mmu.write_byte(address, 0x00);  // NOP
+    }
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    cpu.pc = 0xC000;
+    cpu.set_trace(Some(path.to_str().unwrap())).unwrap();
+
+    for _ in 0..3 {
+        cpu.step(&mut mmu).unwrap();
+    }
+    cpu.set_trace(None).unwrap();
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines, vec![
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C000 OP:00",
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C001 OP:00",
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C002 OP:00",
+    ]);
+}
+
+#[test]
+fn test_trace_halt_and_interrupt() {
+    // Test that idle HALT steps and interrupt dispatch aren't traced
+    // Expected: one line for HALT, then one for the first handler instruction
+    let path = std::env::temp_dir().join(format!("gbrust_{}_trace_halt.log", std::process::id()));
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.write_byte(0xC000, 0x76);  // HALT
+    mmu.write_byte(0xFFFF, 0x04);  // IE: Timer
+    mmu.write_byte(0xFF0F, 0x00);
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    cpu.pc = 0xC000;
+    cpu.interrupt_enabled = true;
+    cpu.set_trace(Some(path.to_str().unwrap())).unwrap();
+
+    for _ in 0..3 {
+        cpu.step(&mut mmu).unwrap();  // HALT, then two idle steps
+    }
+    mmu.request_interrupt(gbrust::mmu::INT_TIMER);
+    cpu.step(&mut mmu).unwrap();  // Wake up and dispatch to 0x0050
+    assert_eq!(cpu.pc, 0x0050);
+    cpu.step(&mut mmu).unwrap();
+    cpu.set_trace(None).unwrap();
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("SP:FFFE PC:C000 OP:76"), "{}", lines[0]);
+    assert!(lines[1].contains("SP:FFFC PC:0050 "), "{}", lines[1]);
+}
+
+#[test]
+fn test_doctor_line() {
+    // Test the exact Gameboy Doctor log format
+    // Expected: registers in fixed columns followed by the 4 bytes at PC
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.initialize();
+    cpu.pc = 0xC000;
+    mmu.write_byte(0xC000, 0x00);
+    mmu.write_byte(0xC001, 0xC3);
+    mmu.write_byte(0xC002, 0x13);
+    mmu.write_byte(0xC003, 0x02);
+
+    assert_eq!(
+        cpu.doctor_line(&mmu),
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C000 PCMEM:00,C3,13,02"
+    );
+
+    // The trace log can use the same layout
+    let path = std::env::temp_dir().join(format!("gbrust_{}_doctor.log", std::process::id()));
+    cpu.trace_format = gbrust::cpu::TraceFormat::Doctor;
+    cpu.set_trace(Some(path.to_str().unwrap())).unwrap();
+    cpu.step(&mut mmu).unwrap();
+    drop(cpu);  // Flushes the trace
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(log, "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C000 PCMEM:00,C3,13,02\n");
+}
+
+#[test]
+fn test_state_string() {
+    // Test the register dump of a freshly initialized CPU
+    // Expected: same text print_state writes to stdout
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    assert_eq!(
+        cpu.state_string(),
+        "\nCPU State:\n\
+         AF: 01B0 BC: 0013 DE: 00D8 HL: 014D\n\
+         PC: 0100 SP: FFFE\n\
+         Flags: Z:1 N:0 H:1 C:1\n\
+         Interrupts: Disabled\n\n"
+    );
+}
+
+#[test]
+fn test_ld_nn_a_ticks_per_access() {
+    // Test that LD (nn),A advances the PPU as its bytes are fetched
+    // Expected:
+    // - The two operand fetches inside execute tick the PPU by 4 cycles each
+    // - A full step charges the remaining cycles, 16 in total
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.a = 0x42;
+
+    // LD (0xC000),A at 0xC100
+    cpu.pc = 0xC100;
+    mmu.write_byte(0xC100, 0xEA);
+    mmu.write_byte(0xC101, 0x00);
+    mmu.write_byte(0xC102, 0xC0);
+
+    // Opcode fetch: one access
+    let opcode = cpu.fetch_byte(&mut mmu);
+    assert_eq!(mmu.cycles, 4);
+
+    // Operand fetches: two more accesses
+    let cycles = cpu.execute(&mut mmu, opcode).unwrap();
+    assert_eq!(cycles, 16);
+    assert_eq!(mmu.cycles, 12);
+    assert_eq!(mmu.read_byte(0xC000), 0x42);
+
+    // A full step adds up to exactly the instruction's cycle count
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.pc = 0xC100;
+    mmu.write_byte(0xC100, 0xEA);
+    mmu.write_byte(0xC101, 0x00);
+    mmu.write_byte(0xC102, 0xC0);
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(mmu.cycles, 16);
+}
+
+#[test]
+fn test_stop_speed_switch() {
+    // Test the CGB speed switch through STOP
+    // Expected:
+    // - Without KEY1 armed, STOP leaves the speed alone
+    // - With KEY1 bit 0 armed, STOP toggles double speed and disarms the bit
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    cpu.pc = 0xC000;  // STOP's padding byte is read from zeroed WRAM
+
+    cpu.execute(&mut mmu, 0x10).unwrap();
+    assert_eq!(mmu.double_speed, false);
+    assert_eq!(cpu.pc, 0xC001);
+
+    mmu.write_byte(0xFF4D, 0x01);
+    cpu.execute(&mut mmu, 0x10).unwrap();
+    assert_eq!(mmu.double_speed, true);
+    assert_eq!(mmu.read_byte(0xFF4D) & 0x81, 0x80);  // Double speed, disarmed
+
+    // Arming again switches back to normal speed
+    mmu.write_byte(0xFF4D, 0x01);
+    cpu.execute(&mut mmu, 0x10).unwrap();
+    assert_eq!(mmu.double_speed, false);
+}
+
+fn coverage_grid(name: &str, table: &[bool; 256]) -> String {
+    let count = table.iter().filter(|&&implemented| implemented).count();
+    let mut grid = format!("{} opcodes: {}/256 implemented\n   ", name, count);
+    for low in 0..16 {
+        grid.push_str(&format!(" {:X}", low));
+    }
+    for high in 0..16 {
+        grid.push_str(&format!("\n{:X}x ", high));
+        for low in 0..16 {
+            grid.push_str(if table[high * 16 + low] { " #" } else { " ." });
+        }
+    }
+    grid
+}
+
+#[test]
+fn test_opcode_coverage() {
+    // Prints the coverage grids (run with --nocapture). '#' = implemented, '.' = missing
+    let (base, cb) = gbrust::cpu::implemented_opcodes();
+    println!("{}\n", coverage_grid("Base", &base));
+    println!("{}", coverage_grid("CB", &cb));
+
+    // Spot-check opcodes that must keep decoding
+    for opcode in [0x00, 0x06, 0x08, 0x10, 0xC7, 0xCB, 0xE0, 0xE2, 0xE8, 0xEA, 0xF2, 0xF8, 0xF9, 0xFE, 0xFF] {
+        assert_eq!(base[opcode], true, "opcode {:02X} no longer decodes", opcode);
+    }
+    // Never-valid opcodes
+    for opcode in [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD] {
+        assert_eq!(base[opcode], false);
+    }
+}
+
+#[test]
+fn test_unknown_opcode_carries_pc() {
+    // Test that decode errors report the address of the faulting instruction
+    // Expected: 0xD3 at 0xC123 and CB-prefixed ops report the prefix address
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.write_byte(0xC123, 0xD3);
+    mmu.write_byte(0xC200, 0xCB);
+    mmu.write_byte(0xC201, 0x37);
+
+    cpu.pc = 0xC123;
+    match cpu.step(&mut mmu) {
+        Err(CPUError::UnknownOpcode { opcode, pc }) => {
+            assert_eq!(opcode, 0xD3);
+            assert_eq!(pc, 0xC123);
+        },
+        other => panic!("expected UnknownOpcode, got {:?}", other),
+    }
+
+    cpu.pc = 0xC200;
+    let err = cpu.step(&mut mmu).unwrap_err();
+    assert!(matches!(err, CPUError::UnknownCBOpcode { opcode: 0x37, .. }));
+    assert_eq!(err.pc(), Some(0xC200));
+
+    // Direct execute calls work it out from PC, which points past the opcode
+    cpu.pc = 0xC124;
+    assert_eq!(cpu.execute(&mut mmu, 0xD3).unwrap_err().pc(), Some(0xC123));
+    cpu.pc = 0xC202;
+    assert_eq!(cpu.execute_cb(0x37).unwrap_err().pc(), Some(0xC200));
+}
+
+#[test]
+fn test_opcode_length() {
+    assert_eq!(opcode_length(0x00), 1);  // NOP
+    assert_eq!(opcode_length(0x06), 2);  // LD B,n
+    assert_eq!(opcode_length(0xC3), 3);  // JP nn
+    assert_eq!(opcode_length(0xCB), 2);  // CB-prefixed, e.g. BIT 7,H
+    assert_eq!(opcode_length(0xCD), 3);  // CALL nn
+    assert_eq!(opcode_length(0xE0), 2);  // LDH (n),A
+    assert_eq!(opcode_length(0xD3), 1);  // Unused
+
+    // Every length is 1-3, and 3-byte instructions are the ones taking a 16-bit operand
+    assert!((0..=255u8).all(|opcode| (1..=3).contains(&opcode_length(opcode))));
+    assert_eq!((0..=255u8).filter(|&opcode| opcode_length(opcode) == 3).count(), 17);
+}
+
+#[test]
+fn test_log_levels() {
+    // Test that Errors only logs failures and that debug_mode still means Verbose
+    // Expected: a NOP logs nothing at Errors, a decode line at Instructions;
+    // an unknown opcode logs the error
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.write_byte(0xC000, 0x00);  // NOP
+    mmu.write_byte(0xC001, 0xD3);  // Unknown
+    mmu.write_byte(0xC002, 0x00);  // NOP
+    cpu.capture_log();
+
+    cpu.log_level = LogLevel::Errors;
+    cpu.pc = 0xC000;
+    cpu.step(&mut mmu).unwrap();
+    assert!(cpu.take_log().is_empty());
+    assert!(cpu.step(&mut mmu).is_err());
+    assert_eq!(cpu.take_log(), vec!["CPU Error: Unknown opcode: 0xd3 at 0xc001".to_string()]);
+
+    cpu.log_level = LogLevel::Instructions;
+    cpu.pc = 0xC000;
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(cpu.take_log(), vec!["NOP - No operation".to_string()]);
+
+    cpu.log_level = LogLevel::Off;
+    cpu.debug_mode = true;
+    assert_eq!(cpu.effective_log_level(), LogLevel::Verbose);
+    cpu.pc = 0xC002;
+    cpu.step(&mut mmu).unwrap();
+    let log = cpu.take_log();
+    assert_eq!(log.len(), 3);  // Registers, the next instruction's bytes, the decode
+    assert_eq!(log[1], "Next instruction at C002: 00");
+}
+
+#[test]
+fn test_interrupt_priority() {
+    // Test that simultaneous interrupts are serviced in priority order, one at a time
+    // Expected: VBlank (0x40) goes first and clears IME; Timer (0x50) only
+    // follows once the handler returns with RETI
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.write_byte(0xC000, 0x00);  // NOP
+    mmu.write_byte(0xC100, 0xD9);  // RETI, standing in for the VBlank handler
+    mmu.write_byte(0xFFFF, 0x05);  // IE: VBlank and Timer
+    mmu.write_byte(0xFF0F, 0x00);
+    mmu.request_interrupt(gbrust::mmu::INT_TIMER);
+    mmu.request_interrupt(gbrust::mmu::INT_VBLANK);
+    cpu.pc = 0xC000;
+    cpu.sp = 0xDFF0;
+    cpu.interrupt_enabled = true;
+
+    assert_eq!(cpu.step(&mut mmu).unwrap(), 20);
+    assert_eq!(cpu.pc, 0x0040);
+    assert_eq!(cpu.interrupt_enabled, false);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x1F, 0x04);  // Timer still pending
+    assert_eq!(mmu.read_byte(0xDFEE), 0x00);  // Return address 0xC000
+    assert_eq!(mmu.read_byte(0xDFEF), 0xC0);
+
+    // With IME clear the handler runs undisturbed
+    cpu.pc = 0xC100;
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(cpu.pc, 0xC000);
+    assert_eq!(cpu.interrupt_enabled, true);
+
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(cpu.pc, 0x0050);
+    assert_eq!(mmu.pending_interrupt(), None);
+}
+
+#[test]
+fn test_halt_wakeup() {
+    // Test that HALT waits for an interrupt, even with IME clear
+    // Expected: PC stays put while halted; a request resumes execution without dispatching
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.write_byte(0xC000, 0x76);  // HALT
+    mmu.write_byte(0xC001, 0x00);  // NOP
+    mmu.write_byte(0xFFFF, 0x04);  // IE: Timer
+    mmu.write_byte(0xFF0F, 0x00);
+    cpu.pc = 0xC000;
+    cpu.interrupt_enabled = false;
+
+    cpu.step(&mut mmu).unwrap();
+    assert!(cpu.halted);
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(cpu.pc, 0xC001);
+
+    mmu.request_interrupt(gbrust::mmu::INT_TIMER);
+    cpu.step(&mut mmu).unwrap();
+    assert!(!cpu.halted);
+    assert_eq!(cpu.pc, 0xC002);
+}
+
+#[test]
+fn test_initialize_leaves_halt() {
+    // Test that a reset drops HALT and the previous program's IME
+    // Expected: with an interrupt pending, the first step after initialize
+    // runs the instruction at 0x0100 instead of idling or dispatching
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100] = 0x00;  // NOP
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+    mmu.write_byte(0xC000, 0x76);  // HALT
+    mmu.write_byte(0xFFFF, 0x04);  // IE: Timer
+    mmu.write_byte(0xFF0F, 0x00);
+    let mut cpu = CPU::new();
+    cpu.pc = 0xC000;
+    cpu.sp = 0xDFF0;
+    cpu.interrupt_enabled = true;
+    cpu.step(&mut mmu).unwrap();
+    assert!(cpu.halted);
+
+    cpu.initialize();
+    assert!(!cpu.halted);
+    assert_eq!(cpu.interrupt_enabled, false);
+    mmu.request_interrupt(gbrust::mmu::INT_TIMER);
+    assert_eq!(cpu.step(&mut mmu).unwrap(), 4);
+    assert_eq!(cpu.pc, 0x0101);
+    assert_eq!(cpu.instructions, 2);
+
+    // The boot ROM path resets the same way
+    cpu.halted = true;
+    cpu.interrupt_enabled = true;
+    cpu.power_on();
+    assert!(!cpu.halted);
+    assert_eq!(cpu.interrupt_enabled, false);
+}
+
+#[test]
+fn test_exec_hook() {
+    // Test that the hook runs once per instruction, before it executes
+    // Expected: 3 calls for NOP, LD B,n, NOP with PC at each opcode; none once cleared
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut cpu = CPU::new();
+    let mut mmu = gbrust::mmu::MMU::new();
+    for (i, byte) in [0x00, 0x06, 0x42, 0x00].into_iter().enumerate() {
+        mmu.write_byte(0xC000 + i as u16, byte);
+    }
+    cpu.pc = 0xC000;
+
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let seen = calls.clone();
+    cpu.set_exec_hook(Box::new(move |cpu, opcode| seen.borrow_mut().push((cpu.pc, opcode, cpu.b))));
+    for _ in 0..3 {
+        cpu.step(&mut mmu).unwrap();
+    }
+    // LD B,n hadn't run yet when the hook saw it
+    assert_eq!(*calls.borrow(), vec![(0xC000, 0x00, 0x00), (0xC001, 0x06, 0x00), (0xC003, 0x00, 0x42)]);
+
+    cpu.clear_exec_hook();
+    cpu.pc = 0xC000;
+    cpu.step(&mut mmu).unwrap();
+    assert_eq!(calls.borrow().len(), 3);
+}
@@ -0,0 +1,353 @@
+use gbrust::cpu::{CallFrame, CPU};
+use gbrust::debugger::{
+    backtrace, describe_lcdc, describe_stat, disassemble, hotspots, parse_crash_pc, set_register, stats, step_over_target,
+    write_crash_dump, Breakpoint, Finish, HangDetector, OpcodeBreaks, Register, SymbolTable,
+};
+use gbrust::machine::Machine;
+use gbrust::mmu::WatchHit;
+
+#[test]
+fn test_set_register() {
+    let mut cpu = CPU::new();
+
+    set_register(&mut cpu, "a", 0xFF).unwrap();
+    set_register(&mut cpu, "PC", 0x0150).unwrap();
+    set_register(&mut cpu, "hl", 0xC123).unwrap();
+    assert_eq!(cpu.a, 0xFF);
+    assert_eq!(cpu.pc, 0x0150);
+    assert_eq!((cpu.h, cpu.l), (0xC1, 0x23));
+
+    // F has no low nibble
+    set_register(&mut cpu, "f", 0xFF).unwrap();
+    assert_eq!(cpu.f, 0xF0);
+
+    // Out of range and unknown registers are rejected without changes
+    assert!(set_register(&mut cpu, "b", 0x100).is_err());
+    assert!(set_register(&mut cpu, "x", 0x01).is_err());
+    assert_eq!(cpu.b, 0x00);
+}
+
+#[test]
+fn test_write_watchpoint() {
+    let mut machine = Machine::new();
+    {
+        let mmu = machine.mmu_mut();
+        mmu.write_byte(0xC000, 0x11);
+        mmu.write_byte(0xC100, 0x77);  // LD (HL),A
+        mmu.watchpoints.insert(0xC000);
+    }
+    machine.cpu.pc = 0xC100;
+    machine.cpu.a = 0x42;
+    machine.cpu.h = 0xC0;
+    machine.cpu.l = 0x00;
+
+    machine.step().unwrap();
+    let hit = machine.mmu_mut().take_watch_hit().unwrap();
+    assert_eq!(hit, WatchHit { address: 0xC000, old: 0x11, new: 0x42 });
+    assert_eq!(machine.mmu().read_byte(0xC000), 0x42);
+
+    // The hit is reported once, and unwatched writes don't trip it
+    assert!(machine.mmu_mut().take_watch_hit().is_none());
+    machine.mmu_mut().write_byte(0xC001, 0x00);
+    assert!(machine.mmu_mut().take_watch_hit().is_none());
+}
+
+#[test]
+fn test_conditional_breakpoint() {
+    let symbols = SymbolTable::default();
+    let mut cpu = CPU::new();
+    cpu.pc = 0x0150;
+
+    // Unconditional breakpoints only look at PC
+    let plain = Breakpoint::parse("0x0150", &symbols).unwrap();
+    assert!(plain.condition.is_none());
+    assert!(plain.hit(&cpu));
+
+    let conditional = Breakpoint::parse("0x0150 if a==0x42", &symbols).unwrap();
+    assert_eq!(conditional.to_string(), "0x0150 if a==0x42");
+    assert_eq!(conditional.condition.as_ref().unwrap().register, Register::A);
+    cpu.a = 0x41;
+    assert!(!conditional.hit(&cpu));
+    cpu.a = 0x42;
+    assert!(conditional.hit(&cpu));
+
+    // 16-bit pairs compare as a whole
+    let pair = Breakpoint::parse("150 if hl == C000", &symbols).unwrap();
+    cpu.h = 0xC0;
+    cpu.l = 0x00;
+    assert!(pair.hit(&cpu));
+    cpu.pc = 0x0151;
+    assert!(!pair.hit(&cpu));
+
+    assert!(Breakpoint::parse("0x0150 if x==1", &symbols).is_err());
+    assert!(Breakpoint::parse("0x0150 if a", &symbols).is_err());
+}
+
+#[test]
+fn test_call_stack() {
+    let mut machine = Machine::new();
+    {
+        let mmu = machine.mmu_mut();
+        // C100: CALL C200, C200: RET
+        for (i, byte) in [0xCD, 0x00, 0xC2].iter().enumerate() {
+            mmu.write_byte(0xC100 + i as u16, *byte);
+        }
+        mmu.write_byte(0xC200, 0xC9);
+    }
+    machine.cpu.pc = 0xC100;
+
+    machine.step().unwrap();
+    assert_eq!(machine.cpu.pc, 0xC200);
+    assert_eq!(machine.cpu.call_stack, vec![CallFrame { caller: 0xC100, target: 0xC200 }]);
+    assert_eq!(backtrace(&machine.cpu, &SymbolTable::default()), "#0 0xC200 called from 0xC100\n");
+
+    machine.step().unwrap();
+    assert_eq!(machine.cpu.pc, 0xC103);
+    assert!(machine.cpu.call_stack.is_empty());
+    assert_eq!(machine.cpu.unbalanced_ret, None);
+
+    // Returning again pops a frame that was never pushed
+    machine.cpu.pc = 0xC200;
+    machine.step().unwrap();
+    assert_eq!(machine.cpu.unbalanced_ret, Some(0xC200));
+}
+
+#[test]
+fn test_execution_stats() {
+    // Empty cartridge: NOP, NOP, NOP
+    let mut machine = Machine::new();
+    for _ in 0..3 {
+        machine.step().unwrap();
+    }
+    assert_eq!(machine.cpu.instructions, 3);
+    assert_eq!(machine.cpu.total_cycles, 12);
+    assert_eq!(machine.cpu.opcode_counts[0x00], 3);
+    assert_eq!(machine.cpu.opcode_counts.iter().sum::<u64>(), 3);
+    assert_eq!(stats(&machine.cpu), "Instructions: 3\nCycles: 12\n  00: 3 (100.0%)\n");
+
+    machine.cpu.reset_stats();
+    assert_eq!(machine.cpu.instructions, 0);
+    assert_eq!(machine.cpu.opcode_counts[0x00], 0);
+}
+
+#[test]
+fn test_hang_detection() {
+    // C100: JR $-2 (jumps to itself)
+    let mut machine = Machine::new();
+    machine.mmu_mut().write_byte(0xC100, 0x18);
+    machine.mmu_mut().write_byte(0xC101, 0xFE);
+    machine.cpu.pc = 0xC100;
+
+    let mut hang = HangDetector::new(50);
+    let mut tripped = None;
+    for step in 0..100 {
+        machine.step().unwrap();
+        assert_eq!(machine.cpu.pc, 0xC100);
+        if let Some(address) = hang.observe(machine.cpu.pc) {
+            tripped = Some((step, address));
+            break;
+        }
+    }
+    assert_eq!(tripped, Some((49, 0xC100)));  // The 50th instruction in the loop
+
+    // Straight-line code keeps moving and never trips
+    let mut hang = HangDetector::new(50);
+    assert!((0..1000u16).all(|i| hang.observe(0x0150 + i * 16).is_none()));
+}
+
+#[test]
+fn test_symbol_table() {
+    let symbols = SymbolTable::parse(
+        "; File generated by rgblink\n\
+         00:0150 Main\n\
+         00:0040 VBlankHandler\n\
+         01:4000 Bank1Start ; banked code\n\
+         not a symbol\n",
+    );
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.label(0x0040), Some("VBlankHandler"));
+    assert_eq!(symbols.address("Main"), Some(0x0150));
+    assert_eq!(symbols.label(0x4000), Some("Bank1Start"));
+    assert_eq!(symbols.label(0x1234), None);
+    assert_eq!(symbols.describe(0x0150), "0x0150 (Main)");
+    assert_eq!(symbols.describe(0x0151), "0x0151");
+
+    // Breakpoints accept labels as well as addresses
+    assert_eq!(Breakpoint::parse("VBlankHandler", &symbols).unwrap().address, 0x0040);
+    assert_eq!(Breakpoint::parse("0x0041", &symbols).unwrap().address, 0x0041);
+    assert!(Breakpoint::parse("Missing", &symbols).is_err());
+}
+
+#[test]
+fn test_describe_lcd_registers() {
+    // 0x91: the post-boot LCDC
+    assert_eq!(
+        describe_lcdc(0x91),
+        "LCD: on\nWindow tile map: 0x9800\nWindow: off\nTile data: 0x8000 (unsigned)\n\
+         BG tile map: 0x9800\nSprite size: 8x8\nSprites: off\nBG: on\n"
+    );
+    let all = describe_lcdc(0x6E);
+    assert!(all.starts_with("LCD: off\nWindow tile map: 0x9C00\nWindow: on\nTile data: 0x8800 (signed)\n"));
+    assert!(all.contains("BG tile map: 0x9C00\nSprite size: 8x16\nSprites: on\nBG: off\n"));
+
+    assert_eq!(describe_stat(0x85), "Mode: 1 (VBlank)\nLY=LYC: yes\nSTAT interrupts: none\n");
+    assert_eq!(describe_stat(0x4B), "Mode: 3 (Drawing)\nLY=LYC: no\nSTAT interrupts: LYC HBlank\n");
+}
+
+#[test]
+fn test_crash_dump() {
+    // LD A,$42 ; LD B,$07 ; then 0xD3, which doesn't exist on the SM83
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0105].copy_from_slice(&[0x3E, 0x42, 0x06, 0x07, 0xD3]);
+    let mut mmu = gbrust::mmu::MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+    let mut machine = Machine::with_mmu(mmu);
+    machine.step().unwrap();
+    machine.step().unwrap();
+    let error = machine.step().unwrap_err();
+
+    let path = std::env::temp_dir().join(format!("gbrust_crash_{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    write_crash_dump(path, &error, &machine.cpu, machine.mmu()).unwrap();
+    let dump = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines[0], "GBRust crash dump");
+    assert!(lines[1].starts_with("Error: Unknown opcode"));
+    assert_eq!(lines[2], "PC: 0x0104");
+    assert_eq!(lines[3], "AF: 0x42B0  BC: 0x0713  DE: 0x00D8  HL: 0x014D");
+    assert_eq!(lines[4], "SP: 0xFFFE  Flags: Z-HC");
+    assert_eq!(lines[5], "Opcode: 0xD3");
+    assert_eq!(lines[6], "Memory 0x00FC-0x010C: 00 00 00 00 3E 42 06 07 [D3] 00 00 00 00 00 00 00 00");
+    assert_eq!(lines[7], "Disassembly:");
+    assert_eq!(lines[8], "> 0x0104: DB $D3");
+    assert_eq!(lines[9], "  0x0105: NOP");
+
+    // `t` reads the PC back, from new dumps and old PC-only files alike
+    assert_eq!(parse_crash_pc(&dump), Some(0x0104));
+    assert_eq!(parse_crash_pc("0150"), Some(0x0150));
+
+    assert_eq!(disassemble(machine.mmu(), 0x0100), ("LD A,$42".to_string(), 2));
+}
+
+#[test]
+fn test_opcode_breakpoint() {
+    // C100: NOP, NOP, RST 38H
+    let mut machine = Machine::new();
+    machine.mmu_mut().write_byte(0xC100, 0x00);
+    machine.mmu_mut().write_byte(0xC101, 0x00);
+    machine.mmu_mut().write_byte(0xC102, 0xFF);
+    machine.cpu.pc = 0xC100;
+
+    let mut breaks = OpcodeBreaks::new();
+    assert_eq!(breaks.check(machine.mmu(), 0xC102), None);
+    assert!(breaks.insert(0xFF));
+    assert!(!breaks.insert(0xFF));
+
+    let mut tripped = None;
+    for _ in 0..10 {
+        if let Some(opcode) = breaks.check(machine.mmu(), machine.cpu.pc) {
+            tripped = Some((machine.cpu.pc, opcode));
+            break;
+        }
+        machine.step().unwrap();
+    }
+    // Caught before the RST runs
+    assert_eq!(tripped, Some((0xC102, 0xFF)));
+    assert_eq!(machine.cpu.pc, 0xC102);
+
+    assert!(breaks.remove(0xFF));
+    assert!(breaks.is_empty());
+}
+
+#[test]
+fn test_step_over_call() {
+    // C100: CALL C200 ; C103: NOP    C200: INC B ; INC B ; RET
+    let mut machine = Machine::new();
+    for (address, byte) in [(0xC100, 0xCD), (0xC101, 0x00), (0xC102, 0xC2), (0xC103, 0x00), (0xC200, 0x04), (0xC201, 0x04), (0xC202, 0xC9)] {
+        machine.mmu_mut().write_byte(address, byte);
+    }
+    machine.cpu.pc = 0xC100;
+    machine.cpu.b = 0;
+
+    let target = step_over_target(machine.mmu(), machine.cpu.pc);
+    assert_eq!(target, Some(0xC103));
+    let mut steps = 0;
+    while Some(machine.cpu.pc) != target {
+        machine.step().unwrap();
+        steps += 1;
+    }
+    // The whole subroutine ran: CALL, INC B, INC B, RET
+    assert_eq!(machine.cpu.pc, 0xC103);
+    assert_eq!(machine.cpu.b, 2);
+    assert_eq!(steps, 4);
+
+    // Anything else is a normal step
+    assert_eq!(step_over_target(machine.mmu(), 0xC103), None);
+    assert_eq!(step_over_target(machine.mmu(), 0xC200), None);
+}
+
+#[test]
+fn test_finish() {
+    // C100: CALL C200 ; C103: NOP    C200: INC B ; CALL C300 ; INC B ; RET    C300: RET
+    let mut machine = Machine::new();
+    let program = [
+        (0xC100, 0xCD), (0xC101, 0x00), (0xC102, 0xC2), (0xC103, 0x00),
+        (0xC200, 0x04), (0xC201, 0xCD), (0xC202, 0x00), (0xC203, 0xC3), (0xC204, 0x04), (0xC205, 0xC9),
+        (0xC300, 0xC9),
+    ];
+    for (address, byte) in program {
+        machine.mmu_mut().write_byte(address, byte);
+    }
+    machine.cpu.pc = 0xC100;
+    machine.cpu.sp = 0xDFF0;
+    machine.cpu.b = 0;
+
+    // Into the subroutine: CALL, INC B
+    machine.step().unwrap();
+    machine.step().unwrap();
+    assert_eq!(machine.cpu.pc, 0xC201);
+
+    // The nested CALL's RET doesn't count: only the one leaving this frame
+    let finish = Finish::new(&machine.cpu);
+    loop {
+        let opcode = machine.mmu().read_byte(machine.cpu.pc);
+        machine.step().unwrap();
+        if finish.returned(opcode, &machine.cpu) {
+            break;
+        }
+    }
+    assert_eq!(machine.cpu.pc, 0xC103);
+    assert_eq!(machine.cpu.b, 2);
+    assert_eq!(machine.cpu.sp, 0xDFF0);
+}
+
+#[test]
+fn test_access_hotspots() {
+    let mut machine = Machine::new();
+    assert!(machine.mmu().access_counts().is_none());
+
+    machine.mmu_mut().set_access_counting(true);
+    for _ in 0..5 {
+        machine.mmu().read_byte(0xFF44);
+    }
+    machine.mmu_mut().write_byte(0xC000, 0x01);
+    machine.mmu_mut().write_byte(0xC000, 0x02);
+
+    let counts = machine.mmu().access_counts().unwrap();
+    assert_eq!(counts.reads(0xFF44), 5);
+    assert_eq!(counts.writes(0xFF44), 0);
+    assert_eq!(counts.writes(0xC000), 2);
+    assert_eq!(counts.hottest(1), vec![(0xFF44, 5, 0)]);
+
+    let listing = hotspots(machine.mmu(), &SymbolTable::default());
+    assert!(listing.lines().nth(1).unwrap().starts_with("0xFF44"));
+
+    // The PPU's own STAT/LYC reads aren't counted
+    machine.mmu_mut().update_lcd(456);
+    assert_eq!(machine.mmu().access_counts().unwrap().reads(0xFF41), 0);
+
+    machine.mmu_mut().set_access_counting(false);
+    assert!(machine.mmu().access_counts().is_none());
+}
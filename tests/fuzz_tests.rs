@@ -0,0 +1,58 @@
+use gbrust::cpu::CPU;
+use gbrust::mmu::MMU;
+use gbrust::rng::Rng;
+use proptest::prelude::*;
+
+/// Registers a random machine starts from
+#[derive(Debug, Clone)]
+struct Registers {
+    a: u8,
+    f: u8,
+    bc: u16,
+    de: u16,
+    hl: u16,
+    sp: u16,
+    pc: u16,
+}
+
+fn registers() -> impl Strategy<Value = Registers> {
+    (any::<u8>(), any::<u8>(), any::<u16>(), any::<u16>(), any::<u16>(), any::<u16>(), any::<u16>())
+        .prop_map(|(a, f, bc, de, hl, sp, pc)| Registers { a, f: f & 0xF0, bc, de, hl, sp, pc })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    // Random ROM contents (including the header, so random mappers) and
+    // random register states: every step must return Ok or a CPUError.
+    // The memory contents come from `seed`, so proptest can shrink it and
+    // report a failing case compactly.
+    #[test]
+    fn test_execute_never_panics(seed in any::<u64>(), regs in registers()) {
+        let mut rng = Rng::new(seed);
+        let mut rom = vec![0; 0x8000];
+        rng.fill(&mut rom);
+        let mut mmu = MMU::new();
+        mmu.load_rom_bytes(&rom).unwrap();
+        // Random bytes in work RAM and HRAM for code that jumps there
+        for address in (0xC000..0xE000).chain(0xFF80..0xFFFF) {
+            mmu.write_byte(address, rng.next_u8());
+        }
+
+        let mut cpu = CPU::new();
+        cpu.a = regs.a;
+        cpu.f = regs.f;
+        [cpu.b, cpu.c] = regs.bc.to_be_bytes();
+        [cpu.d, cpu.e] = regs.de.to_be_bytes();
+        [cpu.h, cpu.l] = regs.hl.to_be_bytes();
+        cpu.sp = regs.sp;
+        cpu.pc = regs.pc;
+
+        for _ in 0..2000 {
+            if cpu.step(&mut mmu).is_err() {
+                // Restart somewhere else instead of stopping at the first unknown opcode
+                cpu.pc = rng.next_u64() as u16;
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// Golden-image PPU regression tests: run a small ROM for a fixed number of
+// frames and compare the framebuffer with a reference PNG in tests/golden/.
+// Set GBRUST_UPDATE_GOLDEN=1 to rewrite the references from the current output.
+use std::path::Path;
+
+use gbrust::machine::Machine;
+use gbrust::mmu::MMU;
+use gbrust::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use image::{ColorType, ImageFormat};
+
+/// Build a 32KB ROM-only cartridge with `program` at the 0x0100 entry point
+fn build_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom
+}
+
+/// Run `rom` for `frames` frames and return the framebuffer
+fn render(rom: &[u8], frames: u32) -> Vec<u8> {
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(rom).unwrap();
+    let mut machine = Machine::with_mmu(mmu);
+    machine.limiter.uncapped = true;
+    for _ in 0..frames {
+        machine.run_frame().unwrap();
+    }
+    machine.ppu().framebuffer().to_vec()
+}
+
+/// Compare `framebuffer` with tests/golden/<name>.png, allowing each color
+/// channel to differ by up to `tolerance`
+fn assert_golden(name: &str, framebuffer: &[u8], tolerance: u8) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name));
+    if std::env::var_os("GBRUST_UPDATE_GOLDEN").is_some() {
+        let (width, height) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        image::save_buffer_with_format(&path, framebuffer, width, height, ColorType::Rgba8, ImageFormat::Png).unwrap();
+        eprintln!("updated {}", path.display());
+        return;
+    }
+
+    let reference = image::open(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run with GBRUST_UPDATE_GOLDEN=1 to create it)", path.display(), e))
+        .to_rgba8();
+    assert_eq!(reference.dimensions(), (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32));
+
+    let mismatches: Vec<usize> = framebuffer
+        .chunks_exact(4)
+        .zip(reference.as_raw().chunks_exact(4))
+        .enumerate()
+        .filter(|(_, (got, want))| got.iter().zip(want.iter()).any(|(a, b)| a.abs_diff(*b) > tolerance))
+        .map(|(index, _)| index)
+        .collect();
+    if let Some(&first) = mismatches.first() {
+        let (x, y) = (first % SCREEN_WIDTH, first / SCREEN_WIDTH);
+        panic!(
+            "{}: {} pixels differ, first at ({}, {}): got {:?}, expected {:?}",
+            name,
+            mismatches.len(),
+            x,
+            y,
+            &framebuffer[first * 4..first * 4 + 4],
+            &reference.as_raw()[first * 4..first * 4 + 4]
+        );
+    }
+}
+
+#[test]
+fn test_background_golden() {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x00, 0xE0, 0x40,  // LD A,0 ; LDH (LCDC),A    - LCD off
+        0x21, 0x1F, 0x80,        // LD HL,0x801F              - end of tile 1
+        0x06, 0x10,              // LD B,16
+        0x3E, 0xF0,              // LD A,0xF0                 - 4 dark, 4 light pixels
+        0x32, 0x05, 0x20, 0xFC,  // loop: LD (HL-),A ; DEC B ; JR NZ,loop
+        0x21, 0x3F, 0x98,        // LD HL,0x983F              - end of map rows 0-1
+        0x06, 0x40,              // LD B,64
+        0x3E, 0x01,              // LD A,1
+        0x32, 0x05, 0x20, 0xFC,  // loop: LD (HL-),A ; DEC B ; JR NZ,loop
+        0x3E, 0x04, 0xE0, 0x43,  // LD A,4 ; LDH (SCX),A
+        0x3E, 0xE4, 0xE0, 0x47,  // LD A,0xE4 ; LDH (BGP),A
+        0x3E, 0x91, 0xE0, 0x40,  // LD A,0x91 ; LDH (LCDC),A - LCD on, tiles at 0x8000
+        0x18, 0xFE,              // JR -2
+    ];
+    let framebuffer = render(&build_rom(&program), 3);
+    assert_golden("background", &framebuffer, 0);
+}
@@ -0,0 +1,553 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gbrust::cpu::CallFrame;
+use gbrust::frontend::Frontend;
+use gbrust::joypad::{Button, JoypadState};
+use gbrust::machine::Machine;
+use gbrust::mmu::{Accuracy, HardwareModel, MMU};
+use gbrust::ppu::{Palette, SCREEN_HEIGHT, SCREEN_WIDTH};
+use gbrust::serial::{SerialLink, TcpLink};
+
+#[test]
+fn test_step_advances_cpu_and_ppu() {
+    // Empty cartridge: every byte is 0x00 (NOP, 4 cycles)
+    let mut machine = Machine::new();
+    assert_eq!(machine.cpu.pc, 0x0100);
+    assert_eq!(machine.mmu().scanline, 0);
+
+    assert_eq!(machine.step().unwrap(), 4);
+    assert_eq!(machine.cpu.pc, 0x0101);
+
+    // One scanline is 456 cycles = 114 NOPs
+    for _ in 1..114 {
+        machine.step().unwrap();
+    }
+    assert_eq!(machine.cpu.pc, 0x0100 + 114);
+    assert_eq!(machine.mmu().scanline, 1);
+    assert_eq!(machine.mmu().read_byte(0xFF44), 1);
+
+    // The timer is ticked alongside: DIV counts every 256 cycles
+    assert_eq!(machine.timer().div(), (456 / 256) as u8);
+}
+
+// In-process link cable: the master's exchange takes the byte the slave
+// has offered by polling, and hands its own byte to the slave
+#[derive(Default)]
+struct Wire {
+    offered: Option<u8>,    // Slave's SB, published while it waits
+    delivered: Option<u8>,  // Master's byte, waiting for the slave
+}
+
+struct LoopbackLink(Rc<RefCell<Wire>>);
+
+impl SerialLink for LoopbackLink {
+    fn exchange(&mut self, out: u8) -> u8 {
+        let mut wire = self.0.borrow_mut();
+        wire.delivered = Some(out);
+        wire.offered.take().unwrap_or(0xFF)
+    }
+
+    fn poll(&mut self, out: u8) -> Option<u8> {
+        let mut wire = self.0.borrow_mut();
+        let received = wire.delivered.take();
+        wire.offered = if received.is_some() { None } else { Some(out) };
+        received
+    }
+}
+
+#[test]
+fn test_serial_link_exchange() {
+    let wire = Rc::new(RefCell::new(Wire::default()));
+    let mut master = Machine::new();
+    let mut slave = Machine::new();
+    master.mmu_mut().serial.set_link(Box::new(LoopbackLink(Rc::clone(&wire))));
+    slave.mmu_mut().serial.set_link(Box::new(LoopbackLink(Rc::clone(&wire))));
+
+    // Slave arms a transfer on the external clock and waits
+    slave.mmu_mut().write_byte(0xFF01, 0x5A);
+    slave.mmu_mut().write_byte(0xFF02, 0x80);
+    slave.step().unwrap();
+    assert!(slave.mmu().serial.transferring());
+
+    // Master starts the transfer on its internal clock
+    master.mmu_mut().write_byte(0xFF01, 0xC3);
+    master.mmu_mut().write_byte(0xFF02, 0x81);
+    while master.mmu().serial.transferring() {
+        master.step().unwrap();
+    }
+    slave.step().unwrap();
+
+    // Both sides received the other's byte and got the serial interrupt
+    assert_eq!(master.mmu().read_byte(0xFF01), 0x5A);
+    assert_eq!(slave.mmu().read_byte(0xFF01), 0xC3);
+    assert!(!slave.mmu().serial.transferring());
+    assert_eq!(master.mmu().read_byte(0xFF0F) & 0x08, 0x08);
+    assert_eq!(slave.mmu().read_byte(0xFF0F) & 0x08, 0x08);
+}
+
+#[test]
+fn test_tcp_link_skips_late_replies() {
+    // Grab a free port, then let the slave listen on it
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let (go, wait) = std::sync::mpsc::channel();
+    let slave = std::thread::spawn(move || {
+        let mut link = TcpLink::listen(("127.0.0.1", port)).unwrap();
+        wait.recv().unwrap();
+        let mut poll = |out| loop {
+            if let Some(byte) = link.poll(out) {
+                break byte;
+            }
+        };
+        // The first transfer is only answered after the master gave up on it
+        assert_eq!(poll(0x11), 0xAA);
+        assert_eq!(poll(0x33), 0x22);
+    });
+    let mut master = loop {
+        if let Ok(link) = TcpLink::connect(("127.0.0.1", port)) {
+            break link;
+        }
+    };
+
+    assert_eq!(master.exchange(0xAA), 0xFF);  // Timed out
+    go.send(()).unwrap();
+    // The late 0x11 must not be taken as the answer to the next transfer
+    assert_eq!(master.exchange(0x22), 0x33);
+    slave.join().unwrap();
+}
+
+#[test]
+fn test_run_frame() {
+    // The first frame runs from line 0 to the start of VBlank
+    let mut machine = Machine::new();
+    machine.limiter.uncapped = true;
+    let cycles = machine.run_frame().unwrap();
+    assert_eq!(cycles, 144 * 456);
+    assert_eq!(machine.mmu().scanline, 144);
+    assert_eq!(machine.cpu.pc, 0x0100 + (144 * 456 / 4) as u16);
+
+    // Later ones run from VBlank to VBlank
+    let cycles = machine.run_frame().unwrap();
+    assert_eq!(cycles, 70224);
+    assert_eq!(machine.mmu().scanline, 144);
+}
+
+#[test]
+fn test_frame_advance() {
+    let mut machine = Machine::new();
+    let frames = machine.ppu().frame_count();
+    assert_eq!(machine.frame_cycles(), 70224);
+
+    // Every frame returns on entering VBlank, so the boundary doesn't drift
+    for frame in 1..=5 {
+        machine.emulate_frame().unwrap();
+        assert_eq!(machine.mmu().scanline, 144);
+        assert_eq!(machine.mmu().mode, 1);
+        assert_eq!(machine.ppu().frame_count(), frames + frame);
+    }
+
+    // With the LCD off there is no VBlank; a frame stops after 70224 cycles
+    machine.mmu_mut().write_byte(0xFF40, 0x00);
+    let start = machine.cpu.total_cycles;
+    machine.emulate_frame().unwrap();
+    assert_eq!(machine.cpu.total_cycles - start, 70224);
+    assert_eq!(machine.ppu().frame_count(), frames + 5);
+}
+
+#[test]
+fn test_rewind() {
+    let mut machine = Machine::new();
+    machine.limiter.uncapped = true;
+    machine.enable_rewind(1, 10);
+    assert_eq!(machine.rewind_buffer().unwrap().capacity(), 600);
+
+    machine.run_frame().unwrap();
+    machine.mmu_mut().write_byte(0xC000, 0x11);
+    machine.run_frame().unwrap();
+
+    // State at the end of frame 2 is what the next frame records
+    let expected = machine.save_state();
+    machine.run_frame().unwrap();
+    machine.mmu_mut().write_byte(0xC000, 0x22);
+    assert_ne!(machine.cpu.save_state(), expected.cpu);
+
+    // Rewinding restores it, including memory
+    assert!(machine.rewind());
+    assert_eq!(machine.cpu.save_state(), expected.cpu);
+    assert_eq!(machine.cpu.pc, expected.cpu.pc);
+    assert_eq!(machine.mmu().read_byte(0xC000), 0x11);
+    assert_eq!(machine.timer().div(), expected.mmu.timer.div());
+
+    // Each rewind pops one snapshot until the buffer runs out
+    assert_eq!(machine.rewind_buffer().unwrap().len(), 2);
+    assert!(machine.rewind());
+    assert!(machine.rewind());
+    assert_eq!(machine.cpu.pc, 0x0100);
+    assert!(!machine.rewind());
+}
+
+#[test]
+fn test_rewind_keeps_debugger_state() {
+    let mut machine = Machine::new();
+    machine.limiter.uncapped = true;
+    machine.enable_rewind(1, 10);
+    machine.run_frame().unwrap();
+
+    // Settings changed after the snapshot was taken survive rewinding to it
+    machine.mmu_mut().watchpoints.insert(0xC000);
+    machine.mmu_mut().cheats.add("014200C0").unwrap();
+    machine.mmu_mut().strict = true;
+    machine.set_accuracy(Accuracy::Fast);
+    machine.cpu.call_stack.push_back(CallFrame { caller: 0x0150, target: 0x0200 });
+
+    assert!(machine.rewind());
+    assert!(machine.mmu().watchpoints.contains(&0xC000));
+    assert_eq!(machine.mmu().cheats.codes().len(), 1);
+    assert!(machine.mmu().strict);
+    assert_eq!(machine.accuracy(), Accuracy::Fast);
+    assert!(machine.cpu.call_stack.is_empty());
+}
+
+#[test]
+fn test_rewind_memory_limit() {
+    // 1 MB cartridge: snapshots share the ROM and leave the framebuffer behind
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(&vec![0; 0x100000]).unwrap();
+    let mut machine = Machine::with_mmu(mmu);
+    machine.limiter.uncapped = true;
+    let size = machine.save_state().size_bytes();
+    assert!(size < 100 * 1024, "snapshot is {} bytes", size);
+
+    machine.enable_rewind(1, 10);
+    for _ in 0..10 {
+        machine.run_frame().unwrap();
+    }
+    let buffer = machine.rewind_buffer().unwrap();
+    assert_eq!(buffer.len(), 10);
+    assert_eq!(buffer.memory_used(), size * 10);
+
+    // Lowering the limit drops the oldest snapshots to fit
+    machine.rewind_buffer_mut().unwrap().set_memory_limit(size * 4);
+    machine.run_frame().unwrap();
+    let buffer = machine.rewind_buffer().unwrap();
+    assert_eq!(buffer.len(), 4);
+    assert!(buffer.memory_used() <= size * 4);
+
+    // The live framebuffer survives a rewind
+    assert!(machine.rewind());
+    assert_eq!(machine.ppu().framebuffer().len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+    assert_eq!(machine.rewind_buffer().unwrap().memory_used(), size * 3);
+
+    // A plain clone keeps its framebuffer and can go on rendering
+    let mut copy = machine.mmu().clone();
+    for _ in 0..456 / 4 {
+        copy.update_lcd(4);
+    }
+    assert_eq!(copy.ppu.framebuffer().len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+}
+
+#[test]
+fn test_load_rom_switches_cartridge() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x134..0x138].copy_from_slice(b"NEXT");
+    let path = std::env::temp_dir().join(format!("gbrust_{}_switch.gb", std::process::id()));
+    std::fs::write(&path, &rom).unwrap();
+
+    let mut machine = Machine::new();
+    machine.step().unwrap();
+    assert_eq!(machine.cpu.pc, 0x0101);
+
+    // Loading restarts the CPU on the new cartridge
+    machine.load_rom(path.to_str().unwrap()).unwrap();
+    assert_eq!(machine.cpu.pc, 0x0100);
+    assert_eq!(machine.mmu().header.as_ref().unwrap().title, "NEXT");
+
+    // A failed load keeps the previous ROM
+    assert!(machine.load_rom("/nonexistent/rom.gb").is_err());
+    assert_eq!(machine.mmu().header.as_ref().unwrap().title, "NEXT");
+}
+
+/// Counts what the run loop hands over, and holds A from the third poll onwards
+#[derive(Default)]
+struct RecordingFrontend {
+    frames: u32,
+    polls: u32,
+    samples: usize,
+    last_frame_len: usize,
+}
+
+impl Frontend for RecordingFrontend {
+    fn present(&mut self, framebuffer: &[u8]) {
+        self.frames += 1;
+        self.last_frame_len = framebuffer.len();
+    }
+
+    fn poll_input(&mut self) -> JoypadState {
+        self.polls += 1;
+        JoypadState::default().with(Button::A, self.polls >= 3)
+    }
+
+    fn queue_audio(&mut self, samples: &[f32]) {
+        self.samples += samples.len();
+    }
+}
+
+#[test]
+fn test_run_with_frontend() {
+    let mut machine = Machine::new();
+    machine.limiter.uncapped = true;
+    machine.mmu_mut().apu.enable_audio_output(48_000, 4096);
+    let mut frontend = RecordingFrontend::default();
+
+    let cycles = machine.run(&mut frontend, 5).unwrap();
+    assert_eq!(cycles, 144 * 456 + 4 * 70224);
+    assert_eq!(frontend.frames, 5);
+    assert_eq!(frontend.polls, 5);
+    assert_eq!(frontend.last_frame_len, 160 * 144 * 4);
+    // 48 kHz at ~59.7 frames per second is ~804 stereo frames per frame,
+    // fewer for the first one, which stops at line 144
+    assert!((7900..8000).contains(&frontend.samples), "{} samples", frontend.samples);
+    assert!(machine.mmu().joypad.is_pressed(Button::A));
+}
+
+#[test]
+fn test_screenshot() {
+    // BGP 0xFF turns the blank background solid black
+    let mut machine = Machine::new();
+    machine.limiter.uncapped = true;
+    machine.mmu_mut().write_byte(0xFF47, 0xFF);
+    machine.run_frame().unwrap();
+
+    let path = std::env::temp_dir().join(format!("gbrust_screenshot_{}.png", std::process::id()));
+    let path = path.to_str().unwrap();
+    machine.screenshot(path).unwrap();
+    let image = image::open(path).unwrap().to_rgba8();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(image.dimensions(), (160, 144));
+    assert_eq!(image.as_raw(), machine.ppu().framebuffer());
+    assert!(image.pixels().all(|pixel| pixel.0 == [0x00, 0x00, 0x00, 0xFF]));
+}
+
+#[test]
+fn test_hardware_model_detection() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x134..0x138].copy_from_slice(b"COLR");
+    rom[0x143] = 0xC0;  // CGB only
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+    let mut machine = Machine::with_mmu(mmu);
+
+    assert_eq!(machine.model(), HardwareModel::Cgb);
+    assert_eq!(machine.cpu.a, 0x11);
+    assert!(machine.ppu().cgb_mode());
+    assert_eq!(machine.mmu().header.as_ref().unwrap().title, "COLR");
+
+    // Forcing DMG restarts with the DMG post-boot registers
+    machine.force_model(Some(HardwareModel::Dmg));
+    assert_eq!(machine.model(), HardwareModel::Dmg);
+    assert_eq!(machine.cpu.a, 0x01);
+    assert!(!machine.ppu().cgb_mode());
+
+    // Without a CGB flag the cartridge runs as DMG
+    assert_eq!(Machine::new().model(), HardwareModel::Dmg);
+    assert_eq!(HardwareModel::from_cgb_flag(0x80), HardwareModel::Cgb);
+    assert_eq!(HardwareModel::from_cgb_flag(0x42), HardwareModel::Dmg);
+}
+
+#[test]
+fn test_accuracy_profiles() {
+    let mut machine = Machine::new();
+    assert_eq!(machine.accuracy(), Accuracy::Accurate);
+
+    // Run into mode 3, where VRAM is locked
+    machine.mmu_mut().write_byte(0xFF40, 0x11);
+    machine.mmu_mut().write_byte(0x8000, 0x42);
+    machine.mmu_mut().write_byte(0xFF40, 0x91);
+    machine.mmu_mut().update_lcd(4);
+    for _ in 0..25 {
+        machine.mmu_mut().update_lcd(4);
+    }
+    assert_eq!(machine.mmu().mode, 3);
+    assert_eq!(machine.mmu().read_byte(0x8000), 0xFF);
+
+    // Fast skips the lock
+    machine.set_accuracy(Accuracy::Fast);
+    assert_eq!(machine.accuracy(), Accuracy::Fast);
+    assert_eq!(machine.mmu().read_byte(0x8000), 0x42);
+
+    // Timing is the same in total, just charged per instruction
+    let mut accurate = Machine::new();
+    let mut fast = Machine::new();
+    fast.set_accuracy(Accuracy::Fast);
+    for _ in 0..1000 {
+        accurate.step().unwrap();
+        fast.step().unwrap();
+    }
+    assert_eq!(accurate.cpu.total_cycles, fast.cpu.total_cycles);
+    assert_eq!(accurate.mmu().scanline, fast.mmu().scanline);
+}
+
+#[test]
+fn test_accuracy_profiles_draw_the_same_frame() {
+    // Background, window and sprites (overlapping, flipped, OBP1, behind the background)
+    let render = |accuracy: Accuracy| {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);  // JR -2
+        let mut mmu = MMU::new();
+        mmu.load_rom_bytes(&rom).unwrap();
+        let mut machine = Machine::with_mmu(mmu);
+        machine.set_accuracy(accuracy);
+        let mmu = machine.mmu_mut();
+        mmu.write_byte(0xFF40, 0x00);
+        let tiles: [[u8; 2]; 3] = [[0x55, 0x33], [0x00, 0xFF], [0xF0, 0x0F]];
+        for (tile, bytes) in tiles.iter().enumerate() {
+            for row in 0..8 {
+                let address = 0x8010 + tile as u16 * 16 + row * 2;
+                mmu.write_byte(address, bytes[0]);
+                mmu.write_byte(address + 1, bytes[1]);
+            }
+        }
+        for address in 0x9800..0x9C00 {
+            mmu.write_byte(address, 1);
+            mmu.write_byte(address + 0x400, 2);
+        }
+        let sprites = [(26, 28, 0x00), (66, 92, 0x10), (76, 108, 0x80), (26, 32, 0x20)];
+        for (i, &(y, x, attrs)) in sprites.iter().enumerate() {
+            let oam = 0xFE00 + i as u16 * 4;
+            mmu.write_byte(oam, y);
+            mmu.write_byte(oam + 1, x);
+            mmu.write_byte(oam + 2, 3);
+            mmu.write_byte(oam + 3, attrs);
+        }
+        for (address, value) in [(0xFF42, 5), (0xFF43, 3), (0xFF47, 0xE4), (0xFF48, 0xE4), (0xFF49, 0x1B), (0xFF4A, 40), (0xFF4B, 87)] {
+            mmu.write_byte(address, value);
+        }
+        mmu.write_byte(0xFF40, 0xF3);
+        machine.emulate_frame().unwrap();
+        machine.emulate_frame().unwrap();
+        machine.ppu().framebuffer().to_vec()
+    };
+
+    let fast = render(Accuracy::Fast);
+    let accurate = render(Accuracy::Accurate);
+    let pixel = |x: usize, y: usize| fast[(y * SCREEN_WIDTH + x) * 4..(y * SCREEN_WIDTH + x) * 4 + 4].to_vec();
+    assert_eq!(pixel(100, 100), Palette::GREYSCALE.shade(2));  // Window
+    assert_eq!(pixel(20, 10), Palette::GREYSCALE.shade(1));  // Sprite
+    assert!(fast == accurate, "Fast and Accurate frames differ");
+}
+
+#[test]
+fn test_seeded_power_on_ram() {
+    let wram = |machine: &Machine| (0xC000..0xE000).map(|address| machine.mmu().read_byte(address)).collect::<Vec<u8>>();
+
+    let a = Machine::new_seeded(1234);
+    let b = Machine::new_seeded(1234);
+    let c = Machine::new_seeded(5678);
+    assert_eq!(wram(&a), wram(&b));
+    assert_ne!(wram(&a), wram(&c));
+
+    // Power-on RAM isn't blank, and the unseeded machine is reproducible too
+    assert!(wram(&a).iter().any(|&byte| byte != 0));
+    assert_eq!(wram(&Machine::new()), wram(&Machine::new()));
+}
+
+#[test]
+fn test_input_record_and_replay() {
+    // Stores the direction bits read from P1 downwards from DFFF forever:
+    // LD HL,DFFF; loop: LD A,20; LDH (00),A; LDH A,(00); LD (HL-),A; JR loop
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x10C].copy_from_slice(&[0x21, 0xFF, 0xDF, 0x3E, 0x20, 0xE0, 0x00, 0xF0, 0x00, 0x32, 0x18, 0xF7]);
+    let boot = |seed| {
+        let mut mmu = MMU::new_seeded(seed);
+        mmu.load_rom_bytes(&rom).unwrap();
+        Machine::with_mmu(mmu)
+    };
+    let wram = |machine: &Machine| (0xC000..=0xDFFF).map(|addr| machine.mmu().read_byte(addr)).collect::<Vec<u8>>();
+    let path = std::env::temp_dir().join(format!("gbrust_input_{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let mut recorded = boot(7);
+    recorded.record_input(path).unwrap();
+    let presses = [(1, Button::Right), (2, Button::Up), (4, Button::Left)];
+    for frame in 0..6 {
+        for (at, button) in presses {
+            if at == frame {
+                recorded.mmu_mut().set_button(button, true);
+            }
+        }
+        if frame == 3 {
+            recorded.mmu_mut().set_joypad_state(JoypadState::default());
+        }
+        recorded.emulate_frame().unwrap();
+    }
+    recorded.stop_input_recording();
+
+    let mut replayed = boot(7);
+    replayed.play_input(path).unwrap();
+    for _ in 0..6 {
+        replayed.emulate_frame().unwrap();
+    }
+    std::fs::remove_file(path).unwrap();
+
+    assert!(!replayed.is_playing_input());
+    assert_eq!(replayed.cpu.save_state(), recorded.cpu.save_state());
+    assert_eq!(wram(&replayed), wram(&recorded));
+
+    // Without the input the run differs
+    let mut idle = boot(7);
+    for _ in 0..6 {
+        idle.emulate_frame().unwrap();
+    }
+    assert_ne!(wram(&idle), wram(&recorded));
+}
+
+#[test]
+fn test_on_frame_callback() {
+    // Once per 70224-cycle frame, with the framebuffer
+    let frames = Rc::new(RefCell::new(0));
+    let count = frames.clone();
+    let mut machine = Machine::new();
+    for address in 0xC000..0xC010 {
+        machine.mmu_mut().write_byte(address, 0x00);
+    }
+    machine.mmu_mut().write_byte(0xC010, 0x18);  // JR C000
+    machine.mmu_mut().write_byte(0xC011, 0xEE);
+    machine.cpu.pc = 0xC000;
+    machine.on_frame(Box::new(move |framebuffer| {
+        assert_eq!(framebuffer.len(), 160 * 144 * 4);
+        *count.borrow_mut() += 1;
+    }));
+
+    let mut cycles = 0u64;
+    while cycles < 70224 * 5 {
+        cycles += machine.step().unwrap() as u64;
+    }
+    assert_eq!(*frames.borrow(), 5);
+
+    // Without a callback the flag is still there for the caller to poll
+    machine.clear_on_frame();
+    while cycles < 70224 * 6 {
+        cycles += machine.step().unwrap() as u64;
+    }
+    assert!(machine.take_frame_ready());
+    assert!(!machine.take_frame_ready());
+    assert_eq!(*frames.borrow(), 5);
+}
+
+#[test]
+fn test_on_frame_at_vblank() {
+    // The callback runs as the PPU enters VBlank, with the whole frame drawn
+    let frames = Rc::new(RefCell::new(0));
+    let count = frames.clone();
+    let mut machine = Machine::new();
+    machine.on_frame(Box::new(move |_| *count.borrow_mut() += 1));
+    for _ in 0..3 {
+        while !machine.take_frame_ready() {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.mmu().scanline, 144);
+        assert_eq!(machine.mmu().mode, 1);
+    }
+    assert_eq!(*frames.borrow(), 3);
+}
@@ -1,4 +1,4 @@
-use gbrust::mmu::MMU;
+use gbrust::mmu::{Accuracy, MmioHandler, RamFill, Violation, MMU};
 
 #[test]
 fn test_memory_regions() {
@@ -24,3 +24,1104 @@ fn test_memory_regions() {
     mmu.write_byte(0xFFFF, 0x42);
     assert_eq!(mmu.read_byte(0xFFFF), 0x42);
 }
+
+fn write_temp_file(name: &str, data: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("gbrust_{}_{}", std::process::id(), name));
+    std::fs::write(&path, data).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_boot_rom_overlay() {
+    let mut mmu = MMU::new();
+
+    // Cartridge with 0xAA at 0x0000, boot ROM with 0x31 at 0x0000
+    let mut rom = vec![0; 0x8000];
+    rom[0x0000] = 0xAA;
+    rom[0x0100] = 0xBB;
+    let rom_path = write_temp_file("boot_overlay.gb", &rom);
+    let mut boot = vec![0; 0x100];
+    boot[0x0000] = 0x31;
+    let boot_path = write_temp_file("boot_overlay.bin", &boot);
+
+    mmu.load_rom(&rom_path).unwrap();
+    mmu.load_boot_rom(&boot_path).unwrap();
+
+    // Boot ROM covers 0x0000-0x00FF only
+    assert!(mmu.boot_rom_mapped());
+    assert_eq!(mmu.read_byte(0x0000), 0x31);
+    assert_eq!(mmu.read_byte(0x0100), 0xBB);
+
+    // Writing FF50 unmaps the boot ROM
+    mmu.write_byte(0xFF50, 0x01);
+    assert!(!mmu.boot_rom_mapped());
+    assert_eq!(mmu.read_byte(0x0000), 0xAA);
+
+    // A boot ROM of the wrong size is rejected
+    let bad_path = write_temp_file("boot_bad.bin", &[0; 0x80]);
+    assert!(mmu.load_boot_rom(&bad_path).is_err());
+}
+
+#[test]
+fn test_ext_ram_banking() {
+    let mut mmu = MMU::new();
+
+    // No cartridge RAM: reads return 0xFF and writes are dropped
+    mmu.write_byte(0xA000, 0x42);
+    assert_eq!(mmu.read_byte(0xA000), 0xFF);
+
+    // MBC1+RAM+BATTERY with 32 KB of RAM (4 banks)
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x03;
+    let rom_path = write_temp_file("ram_banking.gb", &rom);
+    mmu.load_rom(&rom_path).unwrap();
+    assert_eq!(mmu.header.as_ref().unwrap().ram_size_bytes(), 0x8000);
+
+    // RAM is disabled until 0x0A is written to 0000-1FFF
+    assert_eq!(mmu.read_byte(0xA000), 0xFF);
+    mmu.write_byte(0x0000, 0x0A);
+    // MBC1 only banks RAM in banking mode 1
+    mmu.write_byte(0x6000, 0x01);
+
+    // Bank 0
+    mmu.write_byte(0x4000, 0x00);
+    mmu.write_byte(0xA000, 0x11);
+
+    // Bank 3
+    mmu.write_byte(0x4000, 0x03);
+    assert_eq!(mmu.read_byte(0xA000), 0x00);
+    mmu.write_byte(0xA000, 0x33);
+
+    // Bank 3 must not alias bank 0
+    mmu.write_byte(0x4000, 0x00);
+    assert_eq!(mmu.read_byte(0xA000), 0x11);
+    mmu.write_byte(0x4000, 0x03);
+    assert_eq!(mmu.read_byte(0xA000), 0x33);
+
+    // Disabling RAM hides it again
+    mmu.write_byte(0x0000, 0x00);
+    assert_eq!(mmu.read_byte(0xA000), 0xFF);
+}
+
+#[test]
+fn test_timer_tick() {
+    let mut mmu = MMU::new();
+
+    // DIV increments every 256 cycles and resets on write
+    mmu.tick(256);
+    assert_eq!(mmu.read_byte(0xFF04), 0x01);
+    mmu.write_byte(0xFF04, 0x42);
+    assert_eq!(mmu.read_byte(0xFF04), 0x00);
+
+    // TIMA at 262144 Hz (every 16 cycles), overflowing into TMA
+    mmu.write_byte(0xFF06, 0x80);  // TMA
+    mmu.write_byte(0xFF05, 0xFE);  // TIMA
+    mmu.write_byte(0xFF07, 0x05);  // Enabled, 16 cycles
+    mmu.tick(16);
+    assert_eq!(mmu.read_byte(0xFF05), 0xFF);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0);
+    mmu.tick(20);  // Overflow, then one machine cycle before the reload
+    assert_eq!(mmu.read_byte(0xFF05), 0x80);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0x04);  // Timer interrupt requested
+}
+
+#[test]
+fn test_tima_reload_delay() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFF04, 0x00);
+    mmu.write_byte(0xFF06, 0x80);  // TMA
+    mmu.write_byte(0xFF05, 0xFF);  // TIMA
+    mmu.write_byte(0xFF07, 0x05);  // Enabled, 16 cycles
+    mmu.write_byte(0xFF0F, 0x00);
+
+    // TIMA overflows to 0x00 and stays there for one machine cycle
+    mmu.tick(16);
+    assert_eq!(mmu.read_byte(0xFF05), 0x00);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0);
+
+    // A write in the window is ignored; the reload and interrupt come after it
+    mmu.write_byte(0xFF05, 0x12);
+    mmu.tick(4);
+    assert_eq!(mmu.read_byte(0xFF05), 0x80);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0x04);
+
+    // Writes work normally again
+    mmu.write_byte(0xFF05, 0x12);
+    assert_eq!(mmu.read_byte(0xFF05), 0x12);
+}
+
+#[test]
+fn test_div_write_ticks_tima() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFF04, 0x00);
+    mmu.write_byte(0xFF05, 0x00);
+    mmu.write_byte(0xFF07, 0x05);  // Enabled, watching counter bit 3
+
+    // Bit 3 is set after 8 cycles: resetting DIV makes it fall and ticks TIMA
+    mmu.tick(8);
+    assert_eq!(mmu.read_byte(0xFF05), 0x00);
+    mmu.write_byte(0xFF04, 0x00);
+    assert_eq!(mmu.read_byte(0xFF05), 0x01);
+
+    // With the bit clear the reset does nothing
+    mmu.tick(4);
+    mmu.write_byte(0xFF04, 0x00);
+    assert_eq!(mmu.read_byte(0xFF05), 0x01);
+
+    // Disabling the timer while the bit is set is a falling edge too
+    mmu.tick(8);
+    mmu.write_byte(0xFF07, 0x01);
+    assert_eq!(mmu.read_byte(0xFF05), 0x02);
+}
+
+#[test]
+fn test_pending_interrupt() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFF0F, 0x00);
+    mmu.write_byte(0xFFFF, 0x1C);  // Timer, Serial, Joypad
+    assert_eq!(mmu.pending_interrupt(), None);
+
+    // Requested but not enabled doesn't count; the lowest enabled bit wins
+    mmu.write_byte(0xFF0F, 0x13);
+    assert_eq!(mmu.pending_interrupt(), Some(4));
+    mmu.write_byte(0xFF0F, 0x1B);
+    assert_eq!(mmu.pending_interrupt(), Some(3));
+    mmu.acknowledge_interrupt(3);
+    assert_eq!(mmu.pending_interrupt(), Some(4));
+}
+
+#[test]
+fn test_double_speed_tick() {
+    // In double speed the timer keeps pace with the CPU clock, the PPU runs at half rate
+    let mut mmu = MMU::new();
+    mmu.double_speed = true;
+    mmu.tick(512);
+    assert_eq!(mmu.read_byte(0xFF04), 0x02);
+    assert_eq!(mmu.cycles, 256);
+}
+
+#[test]
+fn test_wram_banking() {
+    let mut mmu = MMU::new();
+    mmu.fill_ram_pattern(RamFill::Zeros);
+
+    // Bank 0 at C000 is fixed
+    mmu.write_byte(0xC000, 0x99);
+
+    // SVBK = 1
+    mmu.write_byte(0xFF70, 0x01);
+    mmu.write_byte(0xD000, 0x11);
+
+    // SVBK = 2
+    mmu.write_byte(0xFF70, 0x02);
+    assert_eq!(mmu.read_byte(0xD000), 0x00);
+    mmu.write_byte(0xD000, 0x22);
+    assert_eq!(mmu.read_byte(0xF000), 0x22);  // Echo RAM follows the selected bank
+
+    // Banks 1 and 2 must not alias
+    mmu.write_byte(0xFF70, 0x01);
+    assert_eq!(mmu.read_byte(0xD000), 0x11);
+    mmu.write_byte(0xFF70, 0x02);
+    assert_eq!(mmu.read_byte(0xD000), 0x22);
+
+    // Selecting bank 0 behaves as bank 1
+    mmu.write_byte(0xFF70, 0x00);
+    assert_eq!(mmu.read_byte(0xD000), 0x11);
+    assert_eq!(mmu.read_byte(0xC000), 0x99);
+}
+
+#[test]
+fn test_vram_banking() {
+    let mut mmu = MMU::new();
+
+    // VBK = 0
+    mmu.write_byte(0x8000, 0x11);
+
+    // VBK = 1
+    mmu.write_byte(0xFF4F, 0x01);
+    assert_eq!(mmu.read_byte(0xFF4F), 0xFF);
+    assert_eq!(mmu.read_byte(0x8000), 0x00);
+    mmu.write_byte(0x8000, 0x22);
+
+    // Banks are independent
+    mmu.write_byte(0xFF4F, 0x00);
+    assert_eq!(mmu.read_byte(0xFF4F), 0xFE);
+    assert_eq!(mmu.read_byte(0x8000), 0x11);
+
+    // Direct bank access ignores VBK
+    assert_eq!(mmu.read_vram_bank(0, 0x8000), 0x11);
+    assert_eq!(mmu.read_vram_bank(1, 0x8000), 0x22);
+}
+
+#[test]
+fn test_stat_mode_timing() {
+    let mut mmu = MMU::new();
+    let mode = |mmu: &MMU| mmu.read_byte(0xFF41) & 0x03;
+
+    // Mode 2 for dots 0-79, mode 3 from dot 80, HBlank from dot 252
+    mmu.update_lcd(76);
+    assert_eq!(mode(&mmu), 2);
+    mmu.update_lcd(4);
+    assert_eq!(mode(&mmu), 3);
+    mmu.update_lcd(168);
+    assert_eq!(mode(&mmu), 3);
+    mmu.update_lcd(4);
+    assert_eq!(mode(&mmu), 0);
+
+    // The next line starts over in mode 2
+    mmu.update_lcd(204);
+    assert_eq!(mmu.read_byte(0xFF44), 1);
+    assert_eq!(mode(&mmu), 2);
+
+    // VBlank from line 144
+    for _ in 1..144 {
+        mmu.update_lcd(456);
+    }
+    assert_eq!(mmu.read_byte(0xFF44), 144);
+    assert_eq!(mode(&mmu), 1);
+}
+
+#[test]
+fn test_general_purpose_dma() {
+    let mut mmu = MMU::new();
+    mmu.ppu.set_cgb_mode(true);
+
+    // Source data in WRAM
+    for i in 0..0x10 {
+        mmu.write_byte(0xC000 + i, 0xA0 + i as u8);
+    }
+
+    // Source 0xC000, destination 0x8100 (VRAM offset 0x0100)
+    mmu.write_byte(0xFF51, 0xC0);
+    mmu.write_byte(0xFF52, 0x00);
+    mmu.write_byte(0xFF53, 0x01);
+    mmu.write_byte(0xFF54, 0x00);
+
+    // Bit 7 clear: general-purpose DMA of (0+1)*16 bytes
+    mmu.write_byte(0xFF55, 0x00);
+
+    for i in 0..0x10 {
+        assert_eq!(mmu.read_byte(0x8100 + i), 0xA0 + i as u8);
+    }
+    assert_eq!(mmu.read_byte(0x8110), 0x00);  // Nothing past the block
+    assert_eq!(mmu.read_byte(0xFF55), 0xFF);  // Transfer complete
+}
+
+#[test]
+fn test_hblank_dma() {
+    let mut mmu = MMU::new();
+    mmu.ppu.set_cgb_mode(true);
+
+    for i in 0..0x20 {
+        mmu.write_byte(0xC000 + i, 0x50 + i as u8);
+    }
+    mmu.write_byte(0xFF51, 0xC0);
+    mmu.write_byte(0xFF52, 0x00);
+    mmu.write_byte(0xFF53, 0x00);
+    mmu.write_byte(0xFF54, 0x00);
+
+    // Bit 7 set: HBlank DMA of 2 blocks
+    mmu.write_byte(0xFF55, 0x81);
+    assert_eq!(mmu.read_byte(0xFF55), 0x01);  // Active, 2 blocks to go
+    assert_eq!(mmu.read_byte(0x8000), 0x00);  // Nothing copied yet
+
+    // First HBlank copies one block (advance in CPU-sized steps)
+    for _ in 0..(252 / 4) {
+        mmu.update_lcd(4);
+    }
+    assert_eq!(mmu.mode, 0);
+    assert_eq!(mmu.read_byte(0x800F), 0x5F);
+    assert_eq!(mmu.read_byte(0x8010), 0x00);
+    assert_eq!(mmu.read_byte(0xFF55), 0x00);
+
+    // Next line's HBlank copies the second block and finishes
+    for _ in 0..(456 / 4) {
+        mmu.update_lcd(4);
+    }
+    assert_eq!(mmu.read_byte(0x801F), 0x6F);
+    assert_eq!(mmu.read_byte(0xFF55), 0xFF);
+}
+
+#[test]
+fn test_vram_dma_ignored_on_dmg() {
+    // DMG has no VRAM DMA, so an HDMA5 write must not copy anything
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xC000, 0xAB);
+    mmu.write_byte(0xFF51, 0xC0);
+    mmu.write_byte(0xFF52, 0x00);
+    mmu.write_byte(0xFF53, 0x00);
+    mmu.write_byte(0xFF54, 0x00);
+
+    mmu.write_byte(0xFF55, 0x00);
+    assert_eq!(mmu.read_byte(0x8000), 0x00);
+}
+
+#[test]
+fn test_serial_transfer() {
+    let mut mmu = MMU::new();
+
+    // Internal clock at 8192 Hz: 8 bits * 512 cycles
+    mmu.write_byte(0xFF01, b'P');
+    mmu.write_byte(0xFF02, 0x81);
+    assert_eq!(mmu.read_byte(0xFF02), 0xFD);  // Transfer in progress
+    mmu.step_serial(8 * 512 - 1);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x08, 0);
+    assert!(mmu.serial.output.is_empty());
+
+    // Last serial clock: SC bit 7 clears and the serial interrupt is requested
+    mmu.step_serial(1);
+    assert_eq!(mmu.read_byte(0xFF02), 0x7D);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x08, 0x08);
+    assert_eq!(mmu.read_byte(0xFF01), 0xFF);  // No peer: 1s shifted in
+    assert_eq!(mmu.serial.output, b"P");
+
+    // CGB fast clock: 8 bits * 16 cycles
+    mmu.write_byte(0xFF0F, 0x00);
+    mmu.write_byte(0xFF01, b'a');
+    mmu.write_byte(0xFF02, 0x83);
+    mmu.step_serial(8 * 16);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x08, 0x08);
+    assert_eq!(mmu.serial.output, b"Pa");
+
+    // External clock with no peer: the transfer stalls
+    mmu.write_byte(0xFF0F, 0x00);
+    mmu.write_byte(0xFF02, 0x80);
+    mmu.step_serial(100_000);
+    assert!(mmu.serial.transferring());
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x08, 0);
+    assert_eq!(mmu.serial.output.len(), 2);
+}
+
+#[test]
+fn test_oam_gating() {
+    let mut mmu = MMU::new();
+
+    // HBlank: OAM is accessible
+    mmu.mode = 0;
+    mmu.write_byte(0xFE00, 0x42);
+    assert_eq!(mmu.read_byte(0xFE00), 0x42);
+
+    // OAM scan and pixel transfer: reads return 0xFF, writes are dropped
+    for mode in [2, 3] {
+        mmu.mode = mode;
+        assert_eq!(mmu.read_byte(0xFE00), 0xFF);
+        mmu.write_byte(0xFE00, 0x99);
+    }
+
+    // VBlank: the original value is still there
+    mmu.mode = 1;
+    assert_eq!(mmu.read_byte(0xFE00), 0x42);
+}
+
+#[test]
+fn test_vram_gating() {
+    let mut mmu = MMU::new();
+
+    // Pixel transfer: writes are dropped and reads return 0xFF
+    mmu.mode = 3;
+    mmu.write_byte(0x8000, 0x42);
+    assert_eq!(mmu.read_byte(0x8000), 0xFF);
+
+    // HBlank: the write went nowhere, and a new one succeeds
+    mmu.mode = 0;
+    assert_eq!(mmu.read_byte(0x8000), 0x00);
+    mmu.write_byte(0x8000, 0x42);
+    assert_eq!(mmu.read_byte(0x8000), 0x42);
+
+    // OAM scan and VBlank leave VRAM open
+    for mode in [1, 2] {
+        mmu.mode = mode;
+        assert_eq!(mmu.read_byte(0x8000), 0x42);
+    }
+}
+
+#[test]
+fn test_load_rom_bytes() {
+    let mut mmu = MMU::new();
+
+    // Minimal 32 KB ROM with a title and one byte in each bank
+    let mut rom = vec![0; 0x8000];
+    rom[0x134..0x134 + 8].copy_from_slice(b"MEMTEST!");
+    rom[0x0150] = 0x12;
+    rom[0x4000] = 0x34;
+    mmu.load_rom_bytes(&rom).unwrap();
+
+    assert_eq!(mmu.header.as_ref().unwrap().title, "MEMTEST!");
+    assert_eq!(mmu.read_byte(0x0150), 0x12);
+    assert_eq!(mmu.read_byte(0x4000), 0x34);
+
+    // Too short to contain a header
+    assert!(mmu.load_rom_bytes(&[0; 0x100]).is_err());
+}
+
+#[test]
+fn test_header_region_and_publisher() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x134..0x134 + 6].copy_from_slice(b"POKEMO");
+    rom[0x146] = 0x03;  // SGB functions
+    rom[0x14A] = 0x01;  // Overseas
+    rom[0x14B] = 0x01;  // Old licensee: Nintendo
+    rom[0x14C] = 0x02;
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+    let header = mmu.header.as_ref().unwrap();
+    assert_eq!(header.region(), "Overseas");
+    assert!(!header.is_japanese());
+    assert!(header.supports_sgb());
+    assert_eq!(header.version, 2);
+    assert_eq!(header.publisher(), Some("Nintendo"));
+    assert_eq!(header.licensee_code(), "01");
+
+    // 0x33 defers to the two-character new licensee code
+    rom[0x14A] = 0x00;
+    rom[0x14B] = 0x33;
+    rom[0x144..0x146].copy_from_slice(b"A4");
+    mmu.load_rom_bytes(&rom).unwrap();
+    let header = mmu.header.as_ref().unwrap();
+    assert_eq!(header.region(), "Japan");
+    assert_eq!(header.publisher(), Some("Konami"));
+    assert_eq!(header.licensee_code(), "A4");
+
+    rom[0x144..0x146].copy_from_slice(b"ZZ");
+    mmu.load_rom_bytes(&rom).unwrap();
+    assert_eq!(mmu.header.as_ref().unwrap().publisher(), None);
+}
+
+#[test]
+fn test_joypad() {
+    use gbrust::joypad::Button;
+
+    let mut mmu = MMU::new();
+
+    // Nothing selected: all lines read high
+    assert_eq!(mmu.read_byte(0xFF00), 0xFF);
+
+    // Pressing a button requests the joypad interrupt
+    mmu.set_button(Button::Start, true);
+    mmu.set_button(Button::Left, true);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x10, 0x10);
+
+    // Action row (bit 5 low): Start is bit 3
+    mmu.write_byte(0xFF00, 0x10);
+    assert_eq!(mmu.read_byte(0xFF00), 0xD7);
+
+    // Direction row (bit 4 low): Left is bit 1
+    mmu.write_byte(0xFF00, 0x20);
+    assert_eq!(mmu.read_byte(0xFF00), 0xED);
+
+    // Releasing clears the line again
+    mmu.set_button(Button::Left, false);
+    assert_eq!(mmu.read_byte(0xFF00), 0xEF);
+    assert_eq!(Button::from_index(7), Some(Button::Start));
+    assert_eq!(Button::from_index(8), None);
+}
+
+#[test]
+fn test_mapper_detection() {
+    use gbrust::mapper::Mapper;
+
+    // 0x1B: MBC5+RAM+BATTERY
+    let mapper = Mapper::from_byte(0x1B);
+    assert_eq!(mapper, Mapper::Mbc5 { ram: true, battery: true, rumble: false });
+    assert!(mapper.has_ram());
+    assert!(mapper.has_battery());
+    assert!(!mapper.has_rtc());
+    assert_eq!(mapper.to_string(), "MBC5+RAM+BATTERY");
+
+    assert_eq!(Mapper::from_byte(0x00), Mapper::NoMbc { ram: false, battery: false });
+    assert!(Mapper::from_byte(0x10).has_rtc());
+    assert_eq!(Mapper::from_byte(0x42), Mapper::Unknown(0x42));
+
+    // The header carries the decoded mapper
+    let mut mmu = MMU::new();
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x13;
+    mmu.load_rom_bytes(&rom).unwrap();
+    assert_eq!(
+        mmu.header.as_ref().unwrap().mapper,
+        Mapper::Mbc3 { ram: true, battery: true, rtc: false }
+    );
+}
+
+#[test]
+fn test_mbc2() {
+    let mut mmu = MMU::new();
+
+    // MBC2+BATTERY, 256 KB ROM: each bank starts with its own number
+    let mut rom = vec![0; 0x40000];
+    for bank in 0..16 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    rom[0x147] = 0x06;
+    mmu.load_rom_bytes(&rom).unwrap();
+
+    // Address bit 8 clear: RAM enable
+    mmu.write_byte(0x0000, 0x0A);
+    mmu.write_byte(0xA000, 0xFF);
+    assert_eq!(mmu.read_byte(0xA000), 0xFF);  // Low nibble stored, high nibble reads 1s
+    mmu.write_byte(0xA001, 0x35);
+    assert_eq!(mmu.read_byte(0xA001), 0xF5);
+
+    // 512 nibbles, mirrored through A000-BFFF
+    assert_eq!(mmu.read_byte(0xA201), 0xF5);
+    assert_eq!(mmu.read_byte(0xBE01), 0xF5);
+
+    // Address bit 8 set: ROM bank select (4 bits, 0 acts as 1)
+    assert_eq!(mmu.read_byte(0x4000), 1);
+    mmu.write_byte(0x2100, 0x05);
+    assert_eq!(mmu.read_byte(0x4000), 5);
+    mmu.write_byte(0x0100, 0x1F);
+    assert_eq!(mmu.read_byte(0x4000), 15);
+    mmu.write_byte(0x0100, 0x00);
+    assert_eq!(mmu.read_byte(0x4000), 1);
+
+    // Bank select did not disturb RAM enable; disabling hides the RAM
+    assert_eq!(mmu.read_byte(0xA001), 0xF5);
+    mmu.write_byte(0x0000, 0x00);
+    assert_eq!(mmu.read_byte(0xA001), 0xFF);
+}
+
+#[test]
+fn test_mbc5() {
+    let mut mmu = MMU::new();
+
+    // MBC5+RAM+BATTERY, 8 MB ROM (512 banks) tagged with their bank number
+    let mut rom = vec![0; 512 * 0x4000];
+    for bank in 0..512 {
+        rom[bank * 0x4000] = (bank & 0xFF) as u8;
+        rom[bank * 0x4000 + 1] = (bank >> 8) as u8;
+    }
+    rom[0x147] = 0x1B;
+    rom[0x149] = 0x04;  // 128 KB RAM, 16 banks
+    mmu.load_rom_bytes(&rom).unwrap();
+
+    // Bank 0x100: bit 8 at 3000-3FFF, low byte at 2000-2FFF
+    mmu.write_byte(0x3000, 0x01);
+    mmu.write_byte(0x2000, 0x00);
+    assert_eq!(mmu.read_byte(0x4000), 0x00);
+    assert_eq!(mmu.read_byte(0x4001), 0x01);
+
+    // Bank 0x1FF, then bank 0 (selectable on MBC5)
+    mmu.write_byte(0x2000, 0xFF);
+    assert_eq!((mmu.read_byte(0x4000), mmu.read_byte(0x4001)), (0xFF, 0x01));
+    mmu.write_byte(0x3000, 0x00);
+    mmu.write_byte(0x2000, 0x00);
+    assert_eq!((mmu.read_byte(0x4000), mmu.read_byte(0x4001)), (0x00, 0x00));
+
+    // 16 RAM banks
+    mmu.write_byte(0x0000, 0x0A);
+    mmu.write_byte(0x4000, 0x0F);
+    mmu.write_byte(0xA000, 0xEE);
+    mmu.write_byte(0x4000, 0x00);
+    assert_eq!(mmu.read_byte(0xA000), 0x00);
+    mmu.write_byte(0x4000, 0x0F);
+    assert_eq!(mmu.read_byte(0xA000), 0xEE);
+}
+
+#[test]
+fn test_io_read_masks() {
+    let mut mmu = MMU::new();
+
+    // STAT bit 7 always reads 1
+    mmu.write_byte(0xFF41, 0x00);
+    assert_eq!(mmu.read_byte(0xFF41) & 0x80, 0x80);
+
+    // Only the low 3 bits of TAC and 5 bits of IF exist
+    mmu.write_byte(0xFF07, 0x05);
+    assert_eq!(mmu.read_byte(0xFF07), 0xFD);
+    mmu.write_byte(0xFF0F, 0x00);
+    assert_eq!(mmu.read_byte(0xFF0F), 0xE0);
+
+    // Unimplemented and write-only registers read 0xFF
+    mmu.write_byte(0xFF03, 0x00);
+    assert_eq!(mmu.read_byte(0xFF03), 0xFF);
+    mmu.write_byte(0xFF51, 0x12);
+    assert_eq!(mmu.read_byte(0xFF51), 0xFF);
+}
+
+#[test]
+fn test_echo_ram_toggle() {
+    let mut mmu = MMU::new();
+    assert!(mmu.echo_ram_enabled);
+
+    // Enabled: E000-FDFF mirrors C000-DDFF both ways
+    mmu.write_byte(0xC123, 0x42);
+    assert_eq!(mmu.read_byte(0xE123), 0x42);
+    mmu.write_byte(0xE124, 0x99);
+    assert_eq!(mmu.read_byte(0xC124), 0x99);
+
+    // Disabled: reads float high and writes don't reach WRAM
+    mmu.echo_ram_enabled = false;
+    assert_eq!(mmu.read_byte(0xE123), 0xFF);
+    mmu.write_byte(0xE123, 0x00);
+    assert_eq!(mmu.read_byte(0xC123), 0x42);
+}
+
+#[test]
+fn test_region_boundaries() {
+    // First and last byte of every RAM region round-trip through the bus
+    let mut mmu = MMU::new();
+    let edges = [
+        0x8000, 0x9FFF,  // VRAM
+        0xC000, 0xDFFF,  // WRAM
+        0xFE00, 0xFE9F,  // OAM
+        0xFF80, 0xFFFE,  // HRAM
+    ];
+    for (i, &address) in edges.iter().enumerate() {
+        mmu.write_byte(address, i as u8 + 1);
+    }
+    for (i, &address) in edges.iter().enumerate() {
+        assert_eq!(mmu.read_byte(address), i as u8 + 1, "address {:04X}", address);
+    }
+
+    // Echo RAM's last byte mirrors DDFF
+    mmu.write_byte(0xDDFF, 0x5A);
+    assert_eq!(mmu.read_byte(0xFDFF), 0x5A);
+
+    // I/O edges: P1 keeps its fixed bits, FF7F has nothing behind it
+    assert_eq!(mmu.read_byte(0xFF00) & 0xC0, 0xC0);
+    assert_eq!(mmu.read_byte(0xFF7F), 0xFF);
+}
+
+#[test]
+fn test_lcd_off() {
+    let mut mmu = MMU::new();
+    assert!(mmu.lcd_enabled());
+
+    // Run into line 5, mode 3
+    for _ in 0..(456 * 5 + 100) / 4 {
+        mmu.update_lcd(4);
+    }
+    assert_eq!(mmu.read_byte(0xFF44), 5);
+    assert_eq!(mmu.mode, 3);
+    assert_eq!(mmu.read_byte(0x8000), 0xFF);  // VRAM locked during pixel transfer
+
+    // Disabling resets LY and the mode and stops the PPU
+    mmu.write_byte(0xFF40, 0x11);
+    assert!(!mmu.lcd_enabled());
+    assert_eq!(mmu.read_byte(0xFF44), 0);
+    assert_eq!(mmu.read_byte(0xFF41) & 0x03, 0);
+    for _ in 0..456 {
+        mmu.update_lcd(4);
+    }
+    assert_eq!(mmu.read_byte(0xFF44), 0);
+
+    // VRAM and OAM are unrestricted while off
+    mmu.write_byte(0x8000, 0x42);
+    mmu.write_byte(0xFE00, 0x24);
+    assert_eq!(mmu.read_byte(0x8000), 0x42);
+    assert_eq!(mmu.read_byte(0xFE00), 0x24);
+
+    // Re-enabling starts again from the top of the frame
+    mmu.write_byte(0xFF40, 0x91);
+    mmu.update_lcd(10);
+    assert_eq!(mmu.read_byte(0xFF44), 0);
+    assert_eq!(mmu.mode, 2);
+}
+
+#[test]
+fn test_unusable_region() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFF40, 0x11);  // LCD off: OAM is accessible
+
+    // Reads as 0x00 and ignores writes
+    mmu.write_byte(0xFEA0, 0x42);
+    mmu.write_byte(0xFEFF, 0x42);
+    assert_eq!(mmu.read_byte(0xFEA0), 0x00);
+    assert_eq!(mmu.read_byte(0xFEFF), 0x00);
+    assert_eq!(mmu.read_byte(0xFE9F), 0x00);  // Last OAM byte untouched
+
+    // While the PPU holds OAM it reads 0xFF like OAM itself
+    mmu.write_byte(0xFF40, 0x91);
+    mmu.update_lcd(4);
+    assert_eq!(mmu.mode, 2);
+    assert_eq!(mmu.read_byte(0xFEA0), 0xFF);
+}
+
+#[test]
+fn test_cheats() {
+    use gbrust::cheats::Cheat;
+
+    let mut rom = vec![0; 0x8000];
+    rom[0x4ABC] = 0x12;
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+
+    // Value 0x34 at 0x4ABC (F=B inverted is 4), compare G=A I=2 -> 0xA2 ror 2 ^ 0xBA = 0x12
+    let cheat = mmu.add_cheat("34A-BCB-AE2").unwrap();
+    assert_eq!(cheat, Cheat::GameGenie { address: 0x4ABC, value: 0x34, compare: Some(0x12) });
+    assert_eq!(mmu.read_byte(0x4ABC), 0x34);
+    assert_eq!(mmu.read_byte(0x4ABD), 0x00);
+
+    // When the cartridge byte doesn't match the compare value the code does nothing
+    mmu.cheats.clear();
+    mmu.add_cheat("56A-BCB-AE2").unwrap();
+    rom[0x4ABC] = 0x99;
+    mmu.load_rom_bytes(&rom).unwrap();
+    assert_eq!(mmu.read_byte(0x4ABC), 0x99);
+
+    // Six-digit codes patch unconditionally
+    mmu.cheats.clear();
+    mmu.add_cheat("78A-BCB").unwrap();
+    assert_eq!(mmu.read_byte(0x4ABC), 0x78);
+
+    // GameShark: bank 01, value 0x63 at 0xC123, applied on demand
+    let cheat = mmu.add_cheat("016323C1").unwrap();
+    assert_eq!(cheat, Cheat::GameShark { bank: 0x01, address: 0xC123, value: 0x63 });
+    mmu.write_byte(0xC123, 0x00);
+    mmu.apply_ram_cheats();
+    assert_eq!(mmu.read_byte(0xC123), 0x63);
+
+    // GameShark codes only reach RAM: not ROM, VRAM, I/O or IE
+    assert!(mmu.add_cheat("01630020").is_err());
+    assert!(mmu.add_cheat("01630080").is_err());
+    assert!(mmu.add_cheat("016340FF").is_err());
+    assert!(mmu.add_cheat("0163FFFF").is_err());
+    assert!(mmu.add_cheat("016380FF").is_ok());
+
+    assert!(mmu.add_cheat("XYZ-123").is_err());
+    assert!(mmu.add_cheat("12-34").is_err());
+}
+
+#[test]
+fn test_verify_rom() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x134..0x138].copy_from_slice(b"SUMS");
+    rom[0x4000] = 0xFF;
+    rom[0x7FFF] = 0x80;
+    let sum = rom.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    rom[0x14E..0x150].copy_from_slice(&sum.to_be_bytes());
+
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+    assert_eq!(mmu.global_checksum(), sum);
+    assert!(mmu.verify_rom());
+
+    // A flipped byte still loads, but fails verification
+    rom[0x5000] ^= 0x01;
+    mmu.load_rom_bytes(&rom).unwrap();
+    assert!(!mmu.verify_rom());
+}
+
+#[test]
+fn test_oam_corruption_bug() {
+    // Row 1 starts with words 0x1234, 0x5555, 0x0F0F, 0x6666; row 2 with 0xABCD
+    let setup = |accuracy: Accuracy| {
+        let mut mmu = MMU::new();
+        mmu.accuracy = accuracy;
+        mmu.write_byte(0xFF40, 0x11);  // LCD off so OAM can be written
+        let row1 = [0x34, 0x12, 0x55, 0x55, 0x0F, 0x0F, 0x66, 0x66];
+        for (i, byte) in row1.iter().enumerate() {
+            mmu.write_byte(0xFE08 + i as u16, *byte);
+        }
+        mmu.write_byte(0xFE10, 0xCD);
+        mmu.write_byte(0xFE11, 0xAB);
+        mmu.write_byte(0xFE12, 0x77);
+
+        // Restart the LCD and run 8 dots into mode 2: the scan is on row 2
+        mmu.write_byte(0xFF40, 0x91);
+        mmu.update_lcd(4);
+        mmu.update_lcd(4);
+        assert_eq!(mmu.mode, 2);
+        mmu
+    };
+    let oam = |mmu: &mut MMU, address: u16| {
+        mmu.write_byte(0xFF40, 0x11);
+        let value = mmu.read_byte(address);
+        mmu.write_byte(0xFF40, 0x91);
+        value
+    };
+
+    // Write corruption: ((a ^ c) & (b ^ c)) ^ c = 0x0B0D, rest copied from row 1
+    let mut mmu = setup(Accuracy::Accurate);
+    mmu.oam_bug_write(0xFE40);
+    let row2: Vec<u8> = (0xFE10..0xFE18).map(|address| oam(&mut mmu, address)).collect();
+    assert_eq!(row2, [0x0D, 0x0B, 0x55, 0x55, 0x0F, 0x0F, 0x66, 0x66]);
+    assert_eq!(oam(&mut mmu, 0xFE08), 0x34);  // Row 1 itself is untouched
+
+    // Addresses outside FE00-FEFF and the Fast profile leave OAM alone
+    let mut mmu = setup(Accuracy::Accurate);
+    mmu.oam_bug_write(0xC000);
+    assert_eq!(oam(&mut mmu, 0xFE12), 0x77);
+    let mut mmu = setup(Accuracy::Fast);
+    mmu.oam_bug_write(0xFE40);
+    assert_eq!(oam(&mut mmu, 0xFE10), 0xCD);
+}
+
+#[test]
+fn test_mode3_length() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFF40, 0x93);  // LCD, sprites and background on
+    assert_eq!(mmu.mode3_length(0), 172);
+
+    // Count the cycles line 0 spends in mode 3
+    let measure = |mmu: &mut MMU| {
+        mmu.write_byte(0xFF40, 0x00);
+        mmu.write_byte(0xFF40, 0x93);
+        let mut cycles = 0;
+        for _ in 0..456 / 4 {
+            mmu.update_lcd(4);
+            if mmu.mode == 3 {
+                cycles += 4;
+            }
+        }
+        cycles
+    };
+    let empty = measure(&mut mmu);
+
+    // Under Accuracy::Accurate the pixel FIFO's dot count ends mode 3
+    assert_eq!(empty, mmu.ppu.fifo().dots().next_multiple_of(4));
+    mmu.accuracy = Accuracy::Fast;
+    assert_eq!(measure(&mut mmu), 172);
+    mmu.accuracy = Accuracy::Accurate;
+
+    // Four sprites on line 0, each starting at a tile boundary (the costliest
+    // case). OAM is only writable with the LCD off outside HBlank/VBlank.
+    mmu.write_byte(0xFF40, 0x00);
+    for i in 0..4 {
+        mmu.write_byte(0xFE00 + i * 4, 16);
+        mmu.write_byte(0xFE01 + i * 4, 8 + i as u8 * 32);
+    }
+    mmu.write_byte(0xFF40, 0x93);
+    assert_eq!(mmu.mode3_length(0), 172 + 4 * 11);
+    assert_eq!(mmu.mode3_length(20), 172);
+    assert!(measure(&mut mmu) > empty);
+
+    // Fine scroll and the window add their own delays
+    mmu.write_byte(0xFF40, 0x00);
+    mmu.write_byte(0xFE00, 0);
+    mmu.write_byte(0xFE04, 0);
+    mmu.write_byte(0xFE08, 0);
+    mmu.write_byte(0xFE0C, 0);
+    mmu.write_byte(0xFF40, 0x93);
+    mmu.write_byte(0xFF43, 3);
+    assert_eq!(mmu.mode3_length(0), 175);
+    mmu.write_byte(0xFF4A, 0);
+    mmu.write_byte(0xFF4B, 7);
+    mmu.write_byte(0xFF40, 0xB3);  // Window on
+    assert_eq!(mmu.mode3_length(0), 181);
+}
+
+#[test]
+fn test_mbc1_multicart() {
+    // Four 256KB games; every 16KB bank is tagged with its number at offset 0x2000
+    let mut rom = vec![0; 0x100000];
+    for bank in 0..64 {
+        rom[bank * 0x4000 + 0x2000] = bank as u8;
+    }
+    for game in 0..4 {
+        let start = game * 0x40000;
+        for (i, byte) in rom[start + 0x104..start + 0x134].iter_mut().enumerate() {
+            *byte = 0xCE ^ i as u8;
+        }
+        rom[start + 0x147] = 0x01;  // MBC1
+        rom[start + 0x148] = 0x05;  // 1MB
+    }
+
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+    assert_eq!(mmu.header.as_ref().unwrap().mapper, gbrust::mapper::Mapper::Mbc1 { ram: false, battery: false, multicart: true });
+    assert_eq!(mmu.header.as_ref().unwrap().mapper.name(), "MBC1M");
+
+    // Game 2 in mode 1: its bank 0 at 0000-3FFF, its bank 3 at 4000-7FFF
+    mmu.write_byte(0x6000, 0x01);
+    mmu.write_byte(0x4000, 0x02);
+    mmu.write_byte(0x2000, 0x03);
+    assert_eq!(mmu.read_byte(0x2000), 0x20);
+    assert_eq!(mmu.read_byte(0x6000), 0x23);
+    // Bit 4 of the low register isn't wired; bank 0x10 still maps as 0 -> 1 though
+    mmu.write_byte(0x2000, 0x13);
+    assert_eq!(mmu.read_byte(0x6000), 0x23);
+    mmu.write_byte(0x2000, 0x00);
+    assert_eq!(mmu.read_byte(0x6000), 0x21);
+
+    // Regular MBC1 wiring puts the upper bits at bit 5 instead
+    mmu.force_multicart(Some(false));
+    mmu.load_rom_bytes(&rom).unwrap();
+    mmu.write_byte(0x6000, 0x01);
+    mmu.write_byte(0x4000, 0x01);
+    mmu.write_byte(0x2000, 0x03);
+    assert_eq!(mmu.read_byte(0x2000), 0x20);
+    assert_eq!(mmu.read_byte(0x6000), 0x23);
+    // Mode 0 keeps bank 0 at 0000-3FFF
+    mmu.write_byte(0x6000, 0x00);
+    assert_eq!(mmu.read_byte(0x2000), 0x00);
+}
+
+#[test]
+fn test_power_on_ram_pattern() {
+    let wram_is_zero = |mmu: &MMU| (0xC000..0xE000).all(|address| mmu.read_byte(address) == 0);
+
+    // The DMG pattern is the default
+    let mut mmu = MMU::new();
+    assert!(!wram_is_zero(&mmu));
+    assert!((0xC008..0xC010).map(|address| mmu.read_byte(address).count_ones()).sum::<u32>() > 32);
+
+    mmu.fill_ram_pattern(RamFill::Zeros);
+    assert!(wram_is_zero(&mmu));
+    assert!((0xFF80..0xFFFF).all(|address| mmu.read_byte(address) == 0));
+
+    // Refilling with the pattern gives the same contents for the same seed
+    mmu.fill_ram_pattern(RamFill::Pattern);
+    assert!(!wram_is_zero(&mmu));
+    assert_eq!(mmu.read_byte(0xC123), MMU::new().read_byte(0xC123));
+}
+
+#[test]
+fn test_mmio_override() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records writes to its range and answers reads with a fixed value
+    struct Spy {
+        writes: Rc<RefCell<Vec<(u16, u8)>>>,
+    }
+
+    impl MmioHandler for Spy {
+        fn read(&self, _address: u16) -> Option<u8> {
+            Some(0xAB)
+        }
+
+        fn write(&mut self, address: u16, value: u8) -> bool {
+            self.writes.borrow_mut().push((address, value));
+            true
+        }
+    }
+
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xC000, 0x12);
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    mmu.register_mmio(0xFF50..=0xFF50, Box::new(Spy { writes: Rc::clone(&writes) }));
+
+    mmu.write_byte(0xFF50, 0x01);
+    assert_eq!(*writes.borrow(), vec![(0xFF50, 0x01)]);
+    assert_eq!(mmu.read_byte(0xFF50), 0xAB);
+
+    // Addresses outside the range use the normal map
+    mmu.write_byte(0xFF51, 0x00);
+    assert_eq!(writes.borrow().len(), 1);
+    assert_eq!(mmu.read_byte(0xC000), 0x12);
+
+    // A handler that declines falls through to the map
+    struct Passive;
+    impl MmioHandler for Passive {}
+    mmu.register_mmio(0xC000..=0xC0FF, Box::new(Passive));
+    mmu.write_byte(0xC000, 0x34);
+    assert_eq!(mmu.read_byte(0xC000), 0x34);
+
+    mmu.clear_mmio();
+    assert_ne!(mmu.read_byte(0xFF50), 0xAB);
+}
+
+#[test]
+fn test_sgb_packet_capture() {
+    // MLT_REQ (command 0x11, 1 packet) asking for two controllers
+    let mut packet = [0u8; 16];
+    packet[0] = (0x11 << 3) | 1;
+    packet[1] = 0x01;
+
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFF00, 0x00);  // Reset pulse
+    mmu.write_byte(0xFF00, 0x30);
+    for i in 0..128 {
+        let one = packet[i / 8] & (1 << (i % 8)) != 0;
+        mmu.write_byte(0xFF00, if one { 0x10 } else { 0x20 });
+        mmu.write_byte(0xFF00, 0x30);
+    }
+    assert!(mmu.sgb.packets().is_empty());
+    mmu.write_byte(0xFF00, 0x20);  // Stop bit
+    mmu.write_byte(0xFF00, 0x30);
+
+    let packets = mmu.sgb.take_packets();
+    assert_eq!(packets.len(), 1);
+    assert_eq!(packets[0].0, packet);
+    assert_eq!(packets[0].command(), 0x11);
+    assert_eq!(packets[0].length(), 1);
+
+    // Ordinary joypad polling (one line low at a time, no reset) sends nothing
+    for _ in 0..200 {
+        mmu.write_byte(0xFF00, 0x20);
+        mmu.write_byte(0xFF00, 0x10);
+        mmu.write_byte(0xFF00, 0x30);
+    }
+    assert!(mmu.sgb.packets().is_empty());
+}
+
+#[test]
+fn test_strict_mode() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0150] = 0x12;
+    let mut mmu = MMU::new();
+    mmu.load_rom_bytes(&rom).unwrap();
+
+    // Off by default: ignored writes go unreported
+    mmu.write_byte(0x0150, 0x34);
+    assert!(mmu.take_diagnostics().is_empty());
+
+    mmu.strict = true;
+    mmu.instruction_pc = 0xC123;
+    mmu.write_byte(0x0150, 0x34);
+    assert_eq!(mmu.read_byte(0x0150), 0x12);  // Still read-only
+    let diagnostics = mmu.take_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].violation, Violation::RomWrite);
+    assert_eq!((diagnostics[0].pc, diagnostics[0].address, diagnostics[0].value), (0xC123, 0x0150, 0x34));
+    assert_eq!(diagnostics[0].to_string(), "PC C123: write of 34 to ROM at 0150 (no MBC)");
+
+    mmu.read_byte(0xFF13);  // NR13
+    mmu.write_byte(0xFEA0, 0x00);
+    mmu.write_byte(0xC000, 0x00);  // Fine
+    let violations: Vec<_> = mmu.take_diagnostics().iter().map(|d| d.violation).collect();
+    assert_eq!(violations, vec![Violation::WriteOnlyRead, Violation::UnusableWrite]);
+
+    // Read-only register bits: LY, STAT bits 0-2, NR52 bits 0-3
+    mmu.write_byte(0xFF44, 0x00);
+    mmu.write_byte(0xFF41, 0x43);
+    mmu.write_byte(0xFF41, 0x40);  // Fine
+    mmu.write_byte(0xFF26, 0x8F);
+    mmu.write_byte(0xFF26, 0x80);  // Fine
+    let diagnostics = mmu.take_diagnostics();
+    let addresses: Vec<_> = diagnostics.iter().map(|d| (d.violation, d.address)).collect();
+    assert_eq!(addresses, vec![
+        (Violation::ReadOnlyWrite, 0xFF44),
+        (Violation::ReadOnlyWrite, 0xFF41),
+        (Violation::ReadOnlyWrite, 0xFF26),
+    ]);
+    assert_eq!(diagnostics[1].to_string(), "PC C123: write of 43 to read-only bits of FF41");
+
+    // The log keeps the most recent warnings and counts the rest
+    for i in 0..300u16 {
+        mmu.write_byte(0xFEA0 + i % 0x60, 0x00);
+    }
+    assert_eq!(mmu.dropped_diagnostics(), 44);
+    let diagnostics = mmu.take_diagnostics();
+    assert_eq!(diagnostics.len(), 256);
+    assert_eq!(diagnostics.last().unwrap().address, 0xFEA0 + 299 % 0x60);
+    assert_eq!(mmu.dropped_diagnostics(), 0);
+}
+
+#[test]
+fn test_stat_write_bug() {
+    let mut mmu = MMU::new();
+    mmu.write_byte(0xFF45, 0x99);  // LYC never matches
+
+    // Mode 3: nothing to trigger
+    mmu.tick(100);
+    assert_eq!(mmu.read_byte(0xFF41) & 0x03, 3);
+    mmu.write_byte(0xFF0F, 0x00);
+    mmu.write_byte(0xFF41, 0x00);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x02, 0);
+
+    // HBlank: the write raises the STAT interrupt with no sources enabled
+    mmu.tick(200);
+    assert_eq!(mmu.read_byte(0xFF41) & 0x03, 0);
+    mmu.write_byte(0xFF41, 0x00);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x02, 0x02);
+
+    // Not under Fast
+    mmu.write_byte(0xFF0F, 0x00);
+    mmu.accuracy = Accuracy::Fast;
+    mmu.write_byte(0xFF41, 0x00);
+    assert_eq!(mmu.read_byte(0xFF0F) & 0x02, 0);
+}
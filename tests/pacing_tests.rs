@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use gbrust::pacing::{FrameLimiter, FRAME_RATE, MIN_SPEED};
+
+#[test]
+fn test_sleep_scales_with_speed() {
+    let mut limiter = FrameLimiter::new();
+    let elapsed = Duration::from_millis(2);
+
+    // Normal speed targets ~59.7 fps (16.74 ms per frame)
+    let normal = limiter.frame_duration();
+    assert!((normal.as_secs_f64() - 1.0 / 59.7275).abs() < 1e-6);
+    assert_eq!(limiter.sleep_duration(elapsed), normal - elapsed);
+
+    // Doubling the speed halves the frame time
+    limiter.speed = 2.0;
+    let fast = limiter.frame_duration();
+    assert!((fast.as_secs_f64() * 2.0 - normal.as_secs_f64()).abs() < 1e-9);
+    assert_eq!(limiter.sleep_duration(elapsed), fast - elapsed);
+
+    // Slow motion doubles it
+    limiter.speed = 0.5;
+    assert!((limiter.frame_duration().as_secs_f64() - normal.as_secs_f64() * 2.0).abs() < 1e-9);
+
+    // A frame that overran does not sleep
+    limiter.speed = 1.0;
+    assert_eq!(limiter.sleep_duration(Duration::from_millis(20)), Duration::ZERO);
+
+    // Turbo never sleeps
+    limiter.uncapped = true;
+    assert_eq!(limiter.sleep_duration(elapsed), Duration::ZERO);
+}
+
+#[test]
+fn test_invalid_speed_does_not_sleep() {
+    let mut limiter = FrameLimiter::new();
+    let elapsed = Duration::from_millis(2);
+
+    // Zero, NaN and negative speeds mean no pacing rather than a panic
+    for speed in [0.0, f32::NAN, -1.0] {
+        limiter.speed = speed;
+        assert_eq!(limiter.frame_duration(), Duration::ZERO);
+        assert_eq!(limiter.sleep_duration(elapsed), Duration::ZERO);
+    }
+}
+
+#[test]
+fn test_tiny_speed_is_clamped() {
+    let mut limiter = FrameLimiter::new();
+    let slowest = Duration::from_secs_f64(1.0 / (FRAME_RATE * MIN_SPEED as f64));
+
+    // Speeds below the minimum pace like the minimum instead of not sleeping
+    for speed in [f32::MIN_POSITIVE, 0.001, MIN_SPEED] {
+        limiter.speed = speed;
+        assert_eq!(limiter.frame_duration(), slowest);
+        assert_eq!(limiter.sleep_duration(Duration::from_millis(2)), slowest - Duration::from_millis(2));
+    }
+}
@@ -0,0 +1,291 @@
+use gbrust::mmu::{Accuracy, MMU};
+use gbrust::ppu::{decode_tile, decode_tile_row, select_sprites, OamEntry, ColorCorrection, LcdRegs, Palette, Ppu, MAX_SPRITES_PER_LINE};
+
+#[test]
+fn test_color_correction() {
+    let mut ppu = Ppu::new();
+    let red = 0x001F;  // Pure red: bits 0-4
+
+    // No correction passes the color through, scaled to 8 bits
+    assert_eq!(ppu.color_correction(), ColorCorrection::None);
+    assert_eq!(ppu.rgb555_to_rgba(red), [0xFF, 0x00, 0x00, 0xFF]);
+    assert_eq!(ppu.rgb555_to_rgba(0x7FFF), [0xFF, 0xFF, 0xFF, 0xFF]);
+
+    // CGB correction bleeds some red into blue and compresses the range
+    ppu.set_color_correction(ColorCorrection::Cgb);
+    assert_eq!(ppu.rgb555_to_rgba(red), [201, 0, 46, 0xFF]);
+    assert_eq!(ppu.rgb555_to_rgba(0x7FFF), [240, 240, 240, 0xFF]);
+
+    // DMG correction maps black and white onto the green LCD shades
+    ppu.set_color_correction(ColorCorrection::Dmg);
+    assert_eq!(ppu.rgb555_to_rgba(0x0000), [0x0F, 0x38, 0x0F, 0xFF]);
+    assert_eq!(ppu.rgb555_to_rgba(0x7FFF), [0x9B, 0xBC, 0x0F, 0xFF]);
+}
+
+#[test]
+fn test_cgb_palette_auto_increment() {
+    let mut mmu = MMU::new();
+
+    // BCPS: index 0x08 (palette 1, color 0) with auto-increment
+    mmu.write_byte(0xFF68, 0x88);
+    assert_eq!(mmu.read_byte(0xFF68), 0xC8);  // Bit 6 reads as 1
+
+    // Write 0x7C1F (magenta) little-endian; the index advances after each byte
+    mmu.write_byte(0xFF69, 0x1F);
+    assert_eq!(mmu.read_byte(0xFF68) & 0x3F, 0x09);
+    mmu.write_byte(0xFF69, 0x7C);
+    assert_eq!(mmu.read_byte(0xFF68) & 0x3F, 0x0A);
+    assert_eq!(mmu.ppu.bg_palette_color(1, 0), 0x7C1F);
+
+    // Read back through the index register without auto-increment
+    mmu.write_byte(0xFF68, 0x08);
+    assert_eq!(mmu.read_byte(0xFF69), 0x1F);
+    mmu.write_byte(0xFF68, 0x09);
+    assert_eq!(mmu.read_byte(0xFF69), 0x7C);
+    assert_eq!(mmu.read_byte(0xFF68) & 0x3F, 0x09);  // Reads never advance the index
+
+    // Index wraps within the 64 bytes of palette RAM
+    mmu.write_byte(0xFF6A, 0xBF);
+    mmu.write_byte(0xFF6B, 0x42);
+    assert_eq!(mmu.read_byte(0xFF6A) & 0x3F, 0x00);
+}
+
+#[test]
+fn test_cgb_background_attributes_select_palette() {
+    let mut mmu = MMU::new();
+    mmu.ppu.set_cgb_mode(true);
+    mmu.write_byte(0xFF40, 0x91);  // LCD on, tile data at 0x8000, map at 0x9800
+
+    // Tile 0: every pixel uses color 1
+    for row in 0..8 {
+        mmu.write_byte(0x8000 + row * 2, 0xFF);
+        mmu.write_byte(0x8001 + row * 2, 0x00);
+    }
+
+    // First map entry uses palette 1 via its bank-1 attribute, the second palette 0
+    mmu.write_byte(0xFF4F, 0x01);
+    mmu.write_byte(0x9800, 0x01);
+    mmu.write_byte(0xFF4F, 0x00);
+
+    // Palette 1 color 1 = red, palette 0 color 1 = blue
+    mmu.write_byte(0xFF68, 0x80 | 0x0A);
+    mmu.write_byte(0xFF69, 0x1F);
+    mmu.write_byte(0xFF69, 0x00);
+    mmu.write_byte(0xFF68, 0x80 | 0x02);
+    mmu.write_byte(0xFF69, 0x00);
+    mmu.write_byte(0xFF69, 0x7C);
+
+    // Run line 0 through pixel transfer
+    for _ in 0..(252 / 4) {
+        mmu.update_lcd(4);
+    }
+
+    let fb = mmu.ppu.framebuffer();
+    assert_eq!(&fb[0..4], &[0xFF, 0x00, 0x00, 0xFF]);
+    assert_eq!(&fb[8 * 4..8 * 4 + 4], &[0x00, 0x00, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_shade_palette_presets() {
+    let mut mmu = MMU::new();
+
+    // Tile 0 row 0: color 0 on the left half, color 3 on the right half
+    mmu.write_byte(0x8000, 0x0F);
+    mmu.write_byte(0x8001, 0x0F);
+    mmu.write_byte(0xFF40, 0x91);  // LCD on, unsigned tile data, BG on
+    mmu.write_byte(0xFF47, 0xE4);  // Identity BGP: color n -> shade n
+
+    for palette in [Palette::DMG_GREEN, Palette::POCKET_GREY] {
+        mmu.ppu.set_shade_palette(palette);
+        for _ in 0..(252 / 4) {
+            mmu.update_lcd(4);
+        }
+        let frame = mmu.ppu.framebuffer();
+        assert_eq!(frame[0..4], palette.colors[0]);
+        assert_eq!(frame[7 * 4..8 * 4], palette.colors[3]);
+        // Run to the start of the next frame
+        for _ in 0..((154 * 456 - 252) / 4) {
+            mmu.update_lcd(4);
+        }
+    }
+
+    // Custom palettes map shades directly
+    let custom = Palette::custom([[1, 2, 3, 255], [4, 5, 6, 255], [7, 8, 9, 255], [10, 11, 12, 255]]);
+    assert_eq!(custom.shade(0), [1, 2, 3, 255]);
+    assert_eq!(custom.shade(3), [10, 11, 12, 255]);
+    assert_eq!(Palette::default(), Palette::GREYSCALE);
+}
+
+#[test]
+fn test_decode_tile_row() {
+    // Low plane 0b0101_0011, high plane 0b0011_0101
+    assert_eq!(decode_tile_row(0x53, 0x35), [0, 1, 2, 3, 0, 2, 1, 3]);
+    assert_eq!(decode_tile_row(0x00, 0x00), [0; 8]);
+    assert_eq!(decode_tile_row(0xFF, 0xFF), [3; 8]);
+}
+
+#[test]
+fn test_decode_tile() {
+    // Rows cycle through solid shades 0, 1, 2, 3
+    let mut bytes = [0; 16];
+    for row in 0..8 {
+        let shade = row % 4;
+        bytes[row * 2] = if shade & 0x01 != 0 { 0xFF } else { 0x00 };
+        bytes[row * 2 + 1] = if shade & 0x02 != 0 { 0xFF } else { 0x00 };
+    }
+    let tile = decode_tile(&bytes);
+    for (row, pixels) in tile.iter().enumerate() {
+        assert_eq!(*pixels, [(row % 4) as u8; 8], "row {}", row);
+    }
+
+    // A vertical stripe pattern: each row is 0,1,2,3,0,1,2,3
+    let stripes = [0x55, 0x33].repeat(8);
+    let tile = decode_tile(stripes.as_slice().try_into().unwrap());
+    assert!(tile.iter().all(|row| *row == [0, 1, 2, 3, 0, 1, 2, 3]));
+}
+
+#[test]
+fn test_select_sprites() {
+    // 12 sprites covering line 20 (Y = 20 + 16 - 4), X counting down so
+    // priority order is the reverse of OAM order, plus one sprite elsewhere
+    let mut oam = [0u8; 0xA0];
+    for i in 0..12 {
+        oam[i * 4] = 32;
+        oam[i * 4 + 1] = 100 - i as u8 * 5;
+        oam[i * 4 + 2] = i as u8;
+    }
+    oam[12 * 4] = 80;
+
+    let sprites = select_sprites(&oam, 20, false);
+    assert_eq!(sprites.len(), MAX_SPRITES_PER_LINE);
+    // Only the first 10 in OAM order make it, then lowest X goes first
+    let indices: Vec<u8> = sprites.iter().map(|s| s.index).collect();
+    assert_eq!(indices, vec![9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    assert_eq!(sprites[0].x, 55);
+    assert_eq!(sprites[0].tile, 9);
+
+    // Equal X falls back to OAM order
+    oam[5] = 55;  // Sprite 1 X
+    let sprites = select_sprites(&oam, 20, false);
+    assert_eq!(sprites[0].index, 1);
+    assert_eq!(sprites[1].index, 9);
+
+    // Line 28 is past the bottom of 8x8 sprites but inside 8x16 ones
+    assert!(select_sprites(&oam, 28, false).is_empty());
+    assert_eq!(select_sprites(&oam, 28, true).len(), MAX_SPRITES_PER_LINE);
+}
+
+#[test]
+fn test_frame_skip() {
+    let mut mmu = MMU::new();
+    mmu.ppu.set_frame_skip(1);
+    let run_frame = |mmu: &mut MMU| {
+        for _ in 0..70224 / 4 {
+            mmu.update_lcd(4);
+        }
+    };
+    let black = Palette::GREYSCALE.shade(3);
+    let white = Palette::GREYSCALE.shade(0);
+
+    // BGP 0xFF maps the blank background to black on a rendered frame
+    mmu.write_byte(0xFF47, 0xFF);
+    run_frame(&mut mmu);
+    assert_eq!(mmu.ppu.framebuffer()[0..4], black);
+    assert!(mmu.ppu.frame_skipped());
+
+    // The next frame is skipped: the palette change doesn't show up
+    mmu.write_byte(0xFF47, 0x00);
+    run_frame(&mut mmu);
+    assert!(!mmu.ppu.frame_skipped());
+    assert_eq!(mmu.ppu.framebuffer()[0..4], black);
+
+    // And the one after is drawn again
+    run_frame(&mut mmu);
+    assert_eq!(mmu.ppu.framebuffer()[0..4], white);
+    assert_eq!(mmu.read_byte(0xFF44), 0);
+}
+
+#[test]
+fn test_oam_entry_decode() {
+    let sprite = OamEntry::from_bytes(7, [0x20, 0x18, 0x42, 0xB0]);
+    assert_eq!(sprite, OamEntry { index: 7, y: 0x20, x: 0x18, tile: 0x42, attrs: 0xB0 });
+    assert!(sprite.behind_background());
+    assert!(sprite.x_flip());
+    assert!(!sprite.y_flip());
+    assert_eq!(sprite.dmg_palette(), 1);
+    assert!(sprite.on_screen(false));
+
+    // Y and X of 0 hide a sprite; at Y 1 only the last row of an 8x16 sprite shows
+    assert!(!OamEntry::from_bytes(0, [0, 0x18, 0, 0]).on_screen(false));
+    assert!(!OamEntry::from_bytes(0, [0x20, 0, 0, 0]).on_screen(false));
+    assert!(!OamEntry::from_bytes(0, [1, 0x18, 0, 0]).on_screen(false));
+    assert!(OamEntry::from_bytes(0, [1, 0x18, 0, 0]).on_screen(true));
+    assert!(!OamEntry::from_bytes(0, [160, 0x18, 0, 0]).on_screen(false));
+    assert!(!OamEntry::from_bytes(0, [0x20, 168, 0, 0]).on_screen(false));
+}
+
+#[test]
+fn test_fifo_scx_discard() {
+    // Tile 0 row 0 holds colors 0,1,2,3,0,1,2,3; the map is all tile 0
+    let mut vram = [[0u8; 0x2000]; 2];
+    vram[0][0] = 0x55;
+    vram[0][1] = 0x33;
+    let regs = |scx| LcdRegs { lcdc: 0x91, bgp: 0xE4, scx, ..Default::default() };
+    let shades = Palette::GREYSCALE;
+
+    let mut ppu = Ppu::new();
+    ppu.start_fifo_line(0, Vec::new(), regs(0));
+    ppu.finish_fifo_line(&vram, regs(0));
+    let unscrolled = ppu.fifo().dots();
+    assert_eq!(unscrolled, 172);  // Two 6-dot tile fetches, then one pixel per dot
+    assert_eq!(ppu.framebuffer()[0..4], shades.shade(0));
+    assert_eq!(ppu.framebuffer()[4..8], shades.shade(1));
+
+    // SCX=3 drops the first three pixels of the first tile, one dot each
+    ppu.start_fifo_line(0, Vec::new(), regs(3));
+    ppu.finish_fifo_line(&vram, regs(3));
+    assert_eq!(ppu.fifo().dots(), unscrolled + 3);
+    for x in 0..8 {
+        assert_eq!(ppu.framebuffer()[x * 4..x * 4 + 4], shades.shade(((x + 3) % 4) as u8), "pixel {}", x);
+    }
+
+    // Same picture as the scanline renderer
+    let fifo_line = ppu.framebuffer()[..160 * 4].to_vec();
+    let mut scanline = Ppu::new();
+    scanline.render_scanline(0, &vram, regs(3), &[]);
+    assert_eq!(scanline.framebuffer()[..160 * 4], fifo_line[..]);
+}
+
+#[test]
+fn test_fast_renderer_sprite_limit() {
+    // Eleven solid 8x8 sprites side by side on line 0, over a blank background
+    let mut mmu = MMU::new();
+    mmu.accuracy = Accuracy::Fast;
+    mmu.write_byte(0xFF40, 0x00);
+    for address in 0x8010..0x8020 {
+        mmu.write_byte(address, 0xFF);
+    }
+    for i in 0..11u16 {
+        let oam = 0xFE00 + i * 4;
+        mmu.write_byte(oam, 16);
+        mmu.write_byte(oam + 1, 8 + i as u8 * 8);
+        mmu.write_byte(oam + 2, 1);
+        mmu.write_byte(oam + 3, 0);
+    }
+    mmu.write_byte(0xFF47, 0xE4);
+    mmu.write_byte(0xFF48, 0xE4);
+    mmu.write_byte(0xFF40, 0x83);
+    for _ in 0..456 / 4 {
+        mmu.update_lcd(4);
+    }
+
+    // Only the first ten make it through the OAM scan
+    let shades = Palette::GREYSCALE;
+    let pixel = |x: usize| mmu.ppu.framebuffer()[x * 4..x * 4 + 4].to_vec();
+    for x in 0..80 {
+        assert_eq!(pixel(x), shades.shade(3), "pixel {}", x);
+    }
+    for x in 80..88 {
+        assert_eq!(pixel(x), shades.shade(0), "pixel {}", x);
+    }
+}
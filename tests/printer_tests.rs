@@ -0,0 +1,64 @@
+use gbrust::ppu::Palette;
+use gbrust::printer::GbPrinter;
+use gbrust::serial::SerialLink;
+
+/// A complete packet as the Game Boy sends it, ending with the two bytes
+/// the printer answers on
+fn packet(command: u8, compressed: bool, data: &[u8]) -> Vec<u8> {
+    let mut body = vec![command, compressed as u8];
+    body.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    body.extend_from_slice(data);
+    let checksum = body.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+
+    let mut packet = vec![0x88, 0x33];
+    packet.extend_from_slice(&body);
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    packet.extend_from_slice(&[0x00, 0x00]);
+    packet
+}
+
+/// Send `packet`, returning the (alive, status) answer bytes
+fn send(printer: &mut GbPrinter, packet: &[u8]) -> (u8, u8) {
+    let replies: Vec<u8> = packet.iter().map(|&byte| printer.exchange(byte)).collect();
+    (replies[replies.len() - 2], replies[replies.len() - 1])
+}
+
+#[test]
+fn test_print_sequence() {
+    let mut printer = GbPrinter::new();
+    assert_eq!(send(&mut printer, &packet(0x01, false, &[])), (0x81, 0x00));
+
+    // One band (two tile rows) of color 3, then a compressed band: a row of
+    // color 0 as repeat runs and a row of color 1 as literals
+    assert_eq!(send(&mut printer, &packet(0x04, false, &[0xFF; 640])), (0x81, 0x08));
+    let mut rle = Vec::new();
+    for _ in 0..5 {
+        rle.extend_from_slice(&[0x80 | 62, 0x00]);  // 64 x 0x00
+    }
+    for _ in 0..160 {
+        rle.extend_from_slice(&[0x01, 0xFF, 0x00]);  // 2 literal bytes: low 0xFF, high 0x00
+    }
+    send(&mut printer, &packet(0x04, true, &rle));
+    send(&mut printer, &packet(0x04, false, &[]));  // End of data
+
+    // Status inquiry doesn't print anything
+    assert_eq!(send(&mut printer, &packet(0x0F, false, &[])).1, 0x08);
+    assert!(printer.images().is_empty());
+
+    // Print: 1 sheet, no margins, identity palette, default exposure
+    assert_eq!(send(&mut printer, &packet(0x02, false, &[0x01, 0x00, 0xE4, 0x40])), (0x81, 0x00));
+    assert_eq!(printer.images().len(), 1);
+    let image = &printer.images()[0];
+    assert_eq!((image.width, image.height), (160, 32));
+    assert_eq!(image.pixels.len(), 160 * 32 * 4);
+    assert_eq!(image.pixels[0..4], Palette::GREYSCALE.shade(3));
+    assert_eq!(image.pixels[(16 * 160) * 4..(16 * 160) * 4 + 4], Palette::GREYSCALE.shade(0));
+    assert_eq!(image.pixels[(24 * 160) * 4..(24 * 160) * 4 + 4], Palette::GREYSCALE.shade(1));
+
+    // A corrupted checksum is flagged in the status and the packet ignored
+    let mut bad = packet(0x02, false, &[0x01, 0x00, 0xE4, 0x40]);
+    let checksum_low = bad.len() - 4;
+    bad[checksum_low] ^= 0xFF;
+    assert_eq!(send(&mut printer, &bad).1 & 0x01, 0x01);
+    assert_eq!(printer.images().len(), 1);
+}
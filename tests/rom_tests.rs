@@ -0,0 +1,90 @@
+// Runs public test ROMs from tests/roms/. The ROMs are not part of the
+// repository, so the tests are ignored by default: download them with
+// tests/roms/fetch.sh, then run `cargo test --test rom_tests -- --ignored`.
+use std::path::Path;
+
+use gbrust::cpu::CPU;
+use gbrust::mmu::MMU;
+
+// About 60 seconds of emulated time
+const DEFAULT_CYCLE_CAP: u64 = 60 * 4_194_304;
+
+/// How a test ROM reports its result
+#[derive(Clone, Copy)]
+enum Expect {
+    /// Blargg: prints "Passed" or "Failed" over the serial port
+    Serial,
+    /// Mooneye: loads Fibonacci numbers into B,C,D,E,H,L and executes LD B,B
+    Registers,
+}
+
+fn serial_text(mmu: &MMU) -> String {
+    String::from_utf8_lossy(&mmu.serial.output).into_owned()
+}
+
+fn mooneye_signature(cpu: &CPU, mmu: &MMU) -> Option<bool> {
+    // LD B,B is Mooneye's software breakpoint, executed once the result is in the registers
+    if mmu.read_byte(cpu.pc) != 0x40 {
+        return None;
+    }
+    let regs = [cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l];
+    if regs == [3, 5, 8, 13, 21, 34] {
+        Some(true)
+    } else if regs == [0x42; 6] {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Run `rom` (relative to tests/roms/) for at most `max_cycles` and check its result
+fn run_test_rom(rom: &str, expect: Expect, max_cycles: u64) -> Result<(), String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(rom);
+    if !path.exists() {
+        return Err(format!("{}: ROM not found at {} (run tests/roms/fetch.sh)", rom, path.display()));
+    }
+
+    let mut mmu = MMU::new();
+    mmu.load_rom(&path.to_string_lossy()).unwrap();
+    let mut cpu = CPU::new();
+    cpu.initialize();
+
+    let done = |cpu: &CPU, mmu: &MMU| match expect {
+        Expect::Serial => {
+            let text = serial_text(mmu);
+            text.contains("Passed") || text.contains("Failed")
+        },
+        Expect::Registers => mooneye_signature(cpu, mmu).is_some(),
+    };
+    if let Err(e) = cpu.run_headless(&mut mmu, max_cycles, done) {
+        return Err(format!("{}: CPU error at PC {:04X}: {}", rom, cpu.pc, e));
+    }
+
+    match expect {
+        Expect::Serial => {
+            let text = serial_text(&mmu);
+            if text.contains("Passed") {
+                Ok(())
+            } else {
+                Err(format!("{}: serial output {:?}", rom, text))
+            }
+        },
+        Expect::Registers => match mooneye_signature(&cpu, &mmu) {
+            Some(true) => Ok(()),
+            Some(false) => Err(format!("{}: test reported failure", rom)),
+            None => Err(format!("{}: no result within {} cycles", rom, max_cycles)),
+        },
+    }
+}
+
+#[test]
+#[ignore = "needs test ROMs in tests/roms/ (see tests/roms/README.md)"]
+fn test_blargg_cpu_instrs_ld_r_r() {
+    run_test_rom("cpu_instrs/06-ld r,r.gb", Expect::Serial, DEFAULT_CYCLE_CAP).unwrap();
+}
+
+#[test]
+#[ignore = "needs test ROMs in tests/roms/ (see tests/roms/README.md)"]
+fn test_mooneye_daa() {
+    run_test_rom("mooneye/instr/daa.gb", Expect::Registers, DEFAULT_CYCLE_CAP).unwrap();
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Download the test ROMs used by tests/rom_tests.rs into this directory,
+# taken from the c-sp/game-boy-test-roms release bundle.
+set -e
+cd "$(dirname "$0")"
+VERSION=v7.0
+URL=https://github.com/c-sp/game-boy-test-roms/releases/download/$VERSION/game-boy-test-roms-$VERSION.zip
+
+curl -fsSL -o bundle.zip "$URL"
+unzip -o -j -d cpu_instrs bundle.zip "blargg/cpu_instrs/individual/06-ld r,r.gb"
+unzip -o -j -d mooneye/instr bundle.zip "mooneye-test-suite/acceptance/instr/daa.gb"
+rm bundle.zip
@@ -0,0 +1,21 @@
+#!/bin/sh
+# Download the SingleStepTests/sm83 JSON files into this directory.
+# Usage: tests/sm83/fetch.sh [opcode ...]   e.g. fetch.sh 00 06 "cb 37"
+# With no opcodes, fetches the whole suite (a few hundred MB).
+set -e
+cd "$(dirname "$0")"
+BASE=https://raw.githubusercontent.com/SingleStepTests/sm83/main/v1
+
+if [ $# -eq 0 ]; then
+    git clone --depth 1 --filter=blob:none --sparse https://github.com/SingleStepTests/sm83 upstream
+    git -C upstream sparse-checkout set v1
+    mv upstream/v1/*.json .
+    rm -rf upstream
+    exit 0
+fi
+
+for opcode in "$@"; do
+    name=$(echo "$opcode" | tr 'A-F' 'a-f')
+    curl -fsSL -o "$name.json" "$BASE/$(echo "$name" | sed 's/ /%20/g').json"
+    echo "fetched $name.json"
+done
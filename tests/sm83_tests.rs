@@ -0,0 +1,168 @@
+// Runs the SM83 single-step JSON tests (github.com/SingleStepTests/sm83)
+// from tests/sm83/. Each file (`00.json`, `cb 37.json`, ...) holds cases
+// for one opcode: the registers and RAM before and after executing it.
+// The files are not part of the repository, so the test is ignored by
+// default: download them with tests/sm83/fetch.sh, then run
+// `cargo test --test sm83_tests -- --ignored`. Files for opcodes the CPU
+// doesn't implement yet are skipped.
+use std::fs;
+use std::path::Path;
+
+use gbrust::cpu::{implemented_opcodes, CPU};
+use gbrust::mmu::{MmioHandler, MMU};
+use serde_json::Value;
+
+/// Opcodes whose cases depend on more than the CPU and RAM the suite models
+const SKIPPED: &[&str] = &["10", "76"];  // STOP, HALT
+
+/// Flat 64KB of RAM in front of the whole address map
+struct FlatRam(Vec<u8>);
+
+impl MmioHandler for FlatRam {
+    fn read(&self, addr: u16) -> Option<u8> {
+        Some(self.0[addr as usize])
+    }
+
+    fn write(&mut self, addr: u16, val: u8) -> bool {
+        self.0[addr as usize] = val;
+        true
+    }
+}
+
+fn field(state: &Value, name: &str) -> u16 {
+    state[name].as_u64().unwrap_or_else(|| panic!("missing field {}", name)) as u16
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .expect("missing ram")
+        .iter()
+        .map(|entry| (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8))
+        .collect()
+}
+
+/// Run one case, returning a description of the first mismatch
+fn run_case(case: &Value, opcode: u8) -> Result<(), String> {
+    let initial = &case["initial"];
+    let expected = &case["final"];
+    let initial_ram = ram(initial);
+
+    // The SM83 fetches the next opcode during the last cycle of each
+    // instruction, and the suite's states are taken at that point: the
+    // opcode under test has already been read, so `pc` is the address after
+    // it, the opcode byte sits at pc-1 in `ram`, and the first `cycles`
+    // entry is the read at pc. Likewise the final `pc` is one past the next
+    // opcode. This CPU fetches the opcode inside step(), so it starts at
+    // pc-1 and its final PC is compared plus one. A case whose RAM doesn't
+    // hold the opcode at pc-1 is taken as an unfetched state and run as is.
+    let pc = field(initial, "pc");
+    let prefetched = initial_ram.contains(&(pc.wrapping_sub(1), opcode));
+    let offset = if prefetched { 1 } else { 0 };
+
+    let mut mmu = MMU::new();
+    mmu.register_mmio(0x0000..=0xFFFF, Box::new(FlatRam(vec![0; 0x10000])));
+    for &(addr, value) in &initial_ram {
+        mmu.write_byte(addr, value);
+    }
+    if let Some(ie) = initial["ie"].as_u64() {
+        mmu.write_byte(0xFFFF, ie as u8);
+    }
+
+    let mut cpu = CPU::new();
+    cpu.a = field(initial, "a") as u8;
+    cpu.f = field(initial, "f") as u8;
+    cpu.b = field(initial, "b") as u8;
+    cpu.c = field(initial, "c") as u8;
+    cpu.d = field(initial, "d") as u8;
+    cpu.e = field(initial, "e") as u8;
+    cpu.h = field(initial, "h") as u8;
+    cpu.l = field(initial, "l") as u8;
+    cpu.sp = field(initial, "sp");
+    cpu.pc = pc.wrapping_sub(offset);
+    cpu.interrupt_enabled = field(initial, "ime") != 0;
+    // Set when the state is captured right after an EI
+    cpu.ei_pending = initial["ei"].as_u64().is_some_and(|ei| ei != 0);
+
+    cpu.step(&mut mmu).map_err(|e| e.to_string())?;
+
+    let registers = [
+        ("a", cpu.a as u16),
+        ("f", cpu.f as u16),
+        ("b", cpu.b as u16),
+        ("c", cpu.c as u16),
+        ("d", cpu.d as u16),
+        ("e", cpu.e as u16),
+        ("h", cpu.h as u16),
+        ("l", cpu.l as u16),
+        ("sp", cpu.sp),
+        ("pc", cpu.pc.wrapping_add(offset)),
+        ("ime", cpu.interrupt_enabled as u16),
+    ];
+    for (name, actual) in registers {
+        let want = field(expected, name);
+        if actual != want {
+            return Err(format!("{} is {:#06x}, expected {:#06x}", name, actual, want));
+        }
+    }
+
+    for (addr, want) in ram(expected) {
+        let actual = mmu.read_byte(addr);
+        if actual != want {
+            return Err(format!("({:#06x}) is {:#04x}, expected {:#04x}", addr, actual, want));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs the SM83 suite in tests/sm83/ (see tests/sm83/README.md)"]
+fn test_sm83_single_step() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sm83");
+    let (base, cb) = implemented_opcodes();
+
+    let mut files: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    assert!(!files.is_empty(), "no SM83 test files in {}: run tests/sm83/fetch.sh first", dir.display());
+    files.sort();
+
+    let mut failures = Vec::new();
+    let mut cases = 0;
+    for path in files {
+        let stem = path.file_stem().unwrap().to_string_lossy().to_lowercase();
+        let (prefixed, hex) = match stem.strip_prefix("cb ") {
+            Some(hex) => (true, hex.to_string()),
+            None => (false, stem.clone()),
+        };
+        let Ok(opcode) = u8::from_str_radix(&hex, 16) else {
+            continue;
+        };
+        let implemented = if prefixed { cb[opcode as usize] } else { base[opcode as usize] };
+        if !implemented {
+            eprintln!("skipping {}: opcode not implemented", path.display());
+            continue;
+        }
+        if !prefixed && SKIPPED.contains(&hex.as_str()) {
+            eprintln!("skipping {}: not modelled by the suite", path.display());
+            continue;
+        }
+
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let first_byte = if prefixed { 0xCB } else { opcode };
+        for case in json.as_array().expect("expected an array of cases") {
+            cases += 1;
+            if let Err(e) = run_case(case, first_byte) {
+                failures.push(format!("{}: {}", case["name"].as_str().unwrap_or("?"), e));
+            }
+        }
+    }
+
+    assert!(cases > 0, "no cases found in {}", dir.display());
+    assert!(failures.is_empty(), "{} of {} cases failed:\n{}", failures.len(), cases,
+        failures.iter().take(20).cloned().collect::<Vec<_>>().join("\n"));
+}
@@ -0,0 +1,15 @@
+// Run with: wasm-pack test --node --features wasm
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use gbrust::wasm::WasmGb;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_wasm_run_frame() {
+    let mut gb = WasmGb::new();
+    let rom = vec![0; 0x8000];
+    gb.load_rom(&rom).unwrap();
+    gb.set_button(7, true);
+    gb.run_frame().unwrap();
+    assert_eq!(gb.framebuffer().len(), 160 * 144 * 4);
+}